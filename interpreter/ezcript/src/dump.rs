@@ -0,0 +1,189 @@
+use serde_json::json;
+
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::{Literal, Token, TokenKind};
+
+/// The formats in which the tokens of a script can be dumped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TokenFormat {
+    /// Aligned columns, made for humans
+    Table,
+    /// One JSON object per token, one per line
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+impl TokenFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "table" => Some(TokenFormat::Table),
+            "json" => Some(TokenFormat::Json),
+            "csv" => Some(TokenFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+const HEADER: [&str; 7] = [
+    "kind", "lexeme", "literal", "line", "column", "start", "end",
+];
+
+/// Write the tokens in the requested format, every row ends with a new line. The `Eof` of the
+/// end is left out.
+pub fn dump_tokens(tokens: &[Token], format: TokenFormat) -> String {
+    let tokens = match tokens.split_last() {
+        Some((last, rest)) if last.kind == TokenKind::Eof => rest,
+        _ => tokens,
+    };
+    match format {
+        TokenFormat::Table => table(tokens),
+        TokenFormat::Json => tokens.iter().map(|t| json_row(t) + "\n").collect(),
+        TokenFormat::Csv => {
+            let mut out = HEADER.join(",") + "\n";
+            for token in tokens {
+                let row: Vec<String> = row(token).iter().map(|f| csv_field(f)).collect();
+                out.push_str(&row.join(","));
+                out.push('\n');
+            }
+            out
+        }
+    }
+}
+
+/// The fields of a token as text, in the same order as the `HEADER`
+fn row(token: &Token) -> [String; 7] {
+    [
+        format!("{:?}", token.kind),
+        token.lexeme.clone(),
+        token
+            .literal
+            .as_ref()
+            .map_or_else(String::new, |l| l.to_string()),
+        token.span.line.to_string(),
+        token.span.column.to_string(),
+        token.span.start.to_string(),
+        token.span.end.to_string(),
+    ]
+}
+
+fn table(tokens: &[Token]) -> String {
+    let rows: Vec<[String; 7]> = tokens
+        .iter()
+        .map(|t| {
+            let mut row = row(t);
+            // The lexemes and strings can have new lines, they would break the table
+            row[1] = one_line(&row[1]);
+            row[2] = one_line(&row[2]);
+            row
+        })
+        .collect();
+
+    let mut widths: Vec<usize> = HEADER.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, field) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(field.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    let header: Vec<String> = HEADER.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header[..]).chain(rows.iter().map(|r| &r[..])) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(field, width)| format!("{:<width$}", field, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn one_line(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+        .replace('\t', "\\t")
+}
+
+fn json_row(token: &Token) -> String {
+    let literal = token.literal.as_ref().map(|literal| match literal {
+        Literal::Null => json!({ "type": "Null", "value": null }),
+        Literal::Boolean(b) => json!({ "type": "Boolean", "value": b }),
+        Literal::Number(Number::Integer(n)) => json!({ "type": "Number", "value": n }),
+        Literal::Number(Number::Float(n)) => json!({ "type": "Number", "value": n }),
+        // The big integers and the decimals are written as text, to keep all their digits
+        Literal::Number(n) => json!({ "type": "Number", "value": n.to_string() }),
+        Literal::String(s) => json!({ "type": "String", "value": s }),
+    });
+
+    json!({
+        "kind": format!("{:?}", token.kind),
+        "lexeme": token.lexeme,
+        "literal": literal,
+        "line": token.span.line,
+        "column": token.span.column,
+        "span": { "start": token.span.start, "end": token.span.end },
+    })
+    .to_string()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::lexer::Lexer;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        Lexer::new(source.chars()).tokenize().unwrap()
+    }
+
+    #[test]
+    fn test_dump_csv() {
+        let out = dump_tokens(&tokens("set s = \"a, b\""), TokenFormat::Csv);
+
+        assert_eq!(
+            out,
+            "kind,lexeme,literal,line,column,start,end\n\
+             Keyword,set,,1,1,0,3\n\
+             Ident,s,,1,5,4,5\n\
+             Equal,=,,1,7,6,7\n\
+             String,\"\"\"a, b\"\"\",\"a, b\",1,9,8,14\n"
+        );
+    }
+
+    #[test]
+    fn test_dump_table() {
+        let out = dump_tokens(&tokens("x = 1"), TokenFormat::Table);
+
+        assert_eq!(
+            out,
+            "kind    lexeme  literal  line  column  start  end\n\
+             Ident   x                1     1       0      1\n\
+             Equal   =                1     3       2      3\n\
+             Number  1       1        1     5       4      5\n"
+        );
+    }
+
+    #[test]
+    fn test_dump_json() {
+        let out = dump_tokens(&tokens("true"), TokenFormat::Json);
+        let value: serde_json::Value = serde_json::from_str(out.trim_end()).unwrap();
+
+        assert_eq!(value["kind"], "Boolean");
+        assert_eq!(
+            value["literal"],
+            json!({ "type": "Boolean", "value": true })
+        );
+        assert_eq!(value["span"], json!({ "start": 0, "end": 4 }));
+    }
+}
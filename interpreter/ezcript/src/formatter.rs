@@ -0,0 +1,187 @@
+use ezcript_lexer::tokens::{Token, TokenKind};
+
+/// How many spaces are used for every level of indentation
+const INDENT: usize = 4;
+
+/// Write the tokens of a script (made by a lexer that keeps the comments) with the canonical
+/// layout: four spaces per block, one space between most tokens, and at most one empty line
+/// between statements. The comments are kept where they were.
+pub fn format(source: &str, tokens: &[Token]) -> String {
+    // The strings are copied from the source, their lexemes do not have the escapes anymore
+    let source: Vec<char> = source.chars().collect();
+    let mut out = String::new();
+    let mut level = 0;
+    let mut nesting = 0;
+    let mut previous: Option<&Token> = None;
+    let mut before_previous: Option<&Token> = None;
+    // The comments in their own line wait for the next token, to know their indentation
+    let mut comments: Vec<&Token> = Vec::new();
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Indent => level += 1,
+            TokenKind::Dedent => level -= 1,
+            TokenKind::Eof => break,
+            TokenKind::Comment
+                if comments.is_empty() && previous.is_some_and(|p| same_line(p, token)) =>
+            {
+                out.push_str("  ");
+                out.push_str(token.lexeme.trim_end());
+                // A block comment can end some lines below, the next token goes after its end
+                before_previous = previous;
+                previous = Some(token);
+            }
+            TokenKind::Comment => comments.push(token),
+            kind => {
+                if is_closing(kind) {
+                    nesting -= 1;
+                }
+
+                let mut last = previous;
+                for comment in comments.drain(..) {
+                    new_line(&mut out, last, comment, level + nesting);
+                    out.push_str(comment.lexeme.trim_end());
+                    last = Some(comment);
+                }
+
+                match last {
+                    Some(last) if same_line(last, token) => {
+                        if space_between(before_previous, last, token) {
+                            out.push(' ');
+                        }
+                    }
+                    _ => new_line(&mut out, last, token, level + nesting),
+                }
+                match kind {
+                    TokenKind::String => out.extend(&source[token.span.start..token.span.end]),
+                    _ => out.push_str(&token.lexeme),
+                }
+
+                if is_opening(kind) {
+                    nesting += 1;
+                }
+                before_previous = previous;
+                previous = Some(token);
+            }
+        }
+    }
+
+    let mut last = previous;
+    for comment in comments {
+        new_line(&mut out, last, comment, 0);
+        out.push_str(comment.lexeme.trim_end());
+        last = Some(comment);
+    }
+
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Start a new line for `token`, keeping one empty line if there was at least one before it
+fn new_line(out: &mut String, previous: Option<&Token>, token: &Token, level: usize) {
+    if let Some(previous) = previous {
+        out.push('\n');
+        if token.span.line > end_line(previous) + 1 {
+            out.push('\n');
+        }
+    }
+    out.push_str(&" ".repeat(level * INDENT));
+}
+
+fn space_between(before: Option<&Token>, previous: &Token, token: &Token) -> bool {
+    use TokenKind::*;
+
+    match (previous.kind, token.kind) {
+        (_, RParen) | (_, RBracket) | (_, RBrace) | (_, Comma) | (_, Colon) | (_, Dot) => false,
+        (LParen, _) | (LBracket, _) | (LBrace, _) | (Dot, _) => false,
+        (_, DoubleDot) | (DoubleDot, _) => false,
+        (_, DoublePlus) | (_, DoubleMinus) => false,
+        // Calls and indexes
+        (Ident, LParen) | (RParen, LParen) | (RBracket, LParen) => false,
+        (Ident, LBracket) | (RParen, LBracket) | (RBracket, LBracket) | (String, LBracket) => false,
+        (Keyword, LParen) if previous.lexeme == "func" => false,
+        (Not, _) if previous.lexeme == "!" => false,
+        (Minus, _) => before.is_some_and(is_operand),
+        _ => true,
+    }
+}
+
+/// True if the token can be the end of an operand, so a `-` after it is a binary minus
+fn is_operand(token: &Token) -> bool {
+    use TokenKind::*;
+
+    matches!(
+        token.kind,
+        Ident | Number | String | Float | Boolean | Null | RParen | RBracket | RBrace
+    )
+}
+
+fn is_opening(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace
+    )
+}
+
+fn is_closing(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace
+    )
+}
+
+fn same_line(previous: &Token, token: &Token) -> bool {
+    token.span.line <= end_line(previous)
+}
+
+/// The line where the token ends, strings and comments can take more than one line
+fn end_line(token: &Token) -> u64 {
+    token.span.line + token.lexeme.matches('\n').count() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::lexer::Lexer;
+
+    fn format_source(source: &str) -> String {
+        let tokens = Lexer::new(source.chars())
+            .with_comments()
+            .tokenize()
+            .unwrap();
+        format(source, &tokens)
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "set   x=1+-2*3 # the answer\n\n\n\
+                      while x>0   do\n  # count down\n  print( x , arg(0) )\n  x-=1\n  x++\n\
+                      print(-x)";
+        let expected = "set x = 1 + -2 * 3  # the answer\n\n\
+                        while x > 0 do\n    # count down\n    print(x, arg(0))\n    x -= 1\n    x++\n\
+                        print(-x)\n";
+
+        assert_eq!(format_source(source), expected);
+        assert_eq!(format_source(expected), expected);
+    }
+
+    #[test]
+    fn test_format_escapes() {
+        let source = "print( \"a\\\"b\" ,'c\\'d' )";
+        let expected = "print(\"a\\\"b\", 'c\\'d')\n";
+
+        assert_eq!(format_source(source), expected);
+        assert_eq!(format_source(&format_source(source)), expected);
+    }
+
+    #[test]
+    fn test_format_block_comments() {
+        let source = "set x = 1 #* the\n   answer *#\nprint(x)";
+        let expected = "set x = 1  #* the\n   answer *#\nprint(x)\n";
+
+        assert_eq!(format_source(source), expected);
+        assert_eq!(format_source(&format_source(source)), format_source(source));
+    }
+}
@@ -0,0 +1,228 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Result};
+
+use ezcript_interpreter::environment::Environment;
+use ezcript_lexer::{lexer::Lexer, tokens::TokenKind};
+
+/// The meta-commands of the prompt, without the `:`
+pub const COMMANDS: [&str; 9] = [
+    "ast", "env", "exit", "help", "lang", "load", "reset", "tokens", "type",
+];
+
+/// The ANSI colors of the highlighted code
+const KEYWORD: &str = "\x1b[35m";
+const LITERAL: &str = "\x1b[36m";
+const NUMBER: &str = "\x1b[33m";
+const STRING: &str = "\x1b[32m";
+const COMMENT: &str = "\x1b[90m";
+const ILLEGAL: &str = "\x1b[1;4;31m";
+const RESET: &str = "\x1b[0m";
+
+/// What the line editor of the prompt knows about the language: the names that it can complete
+/// and the colors of the code
+#[derive(Default)]
+pub struct EditorHelper {
+    /// The variables of the session, the prompt changes them when it starts again
+    pub globals: Option<Rc<RefCell<Environment>>>,
+    files: FilenameCompleter,
+}
+
+impl EditorHelper {
+    /// The names that start with the word before the cursor, and where that word starts
+    pub fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let before = &line[..pos];
+        let start = before.rfind(|c: char| !is_name_char(c)).map_or(0, |i| {
+            i + before[i..].chars().next().map_or(1, char::len_utf8)
+        });
+        let word = &before[start..];
+
+        let mut names: Vec<String> = match before[..start].chars().last() {
+            Some(':') if before[..start].trim() == ":" => {
+                COMMANDS.iter().map(|name| name.to_string()).collect()
+            }
+            Some('.') => self.members(&before[..start - 1]),
+            _ => {
+                let mut names: Vec<String> = TokenKind::reserved_words()
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+                if let Some(ref globals) = self.globals {
+                    let globals = globals.borrow();
+                    names.extend(
+                        globals
+                            .bindings()
+                            .into_iter()
+                            .map(|(name, _)| name.to_string()),
+                    );
+                }
+                names
+            }
+        };
+
+        names.retain(|name| name.starts_with(word));
+        names.sort();
+        names.dedup();
+        (start, names)
+    }
+
+    /// The members of the variable whose name ends the text, if it is known
+    fn members(&self, text: &str) -> Vec<String> {
+        let name_start = text.rfind(|c: char| !is_name_char(c)).map_or(0, |i| i + 1);
+        let name = &text[name_start..];
+        let globals = match self.globals {
+            Some(ref globals) if !name.is_empty() => globals.borrow(),
+            _ => return Vec::new(),
+        };
+        let bindings = globals.bindings();
+        match bindings.iter().find(|(binding, _)| *binding == name) {
+            Some((_, value)) => value.members(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// The characters of the names, the same ones that the lexer accepts
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '$'
+}
+
+impl Completer for EditorHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Result<(usize, Vec<Pair>)> {
+        if line.trim_start().starts_with(":load ") {
+            return self.files.complete_path(line, pos);
+        }
+
+        let (start, names) = self.candidates(line, pos);
+        let pairs = names
+            .into_iter()
+            .map(|name| Pair {
+                display: name.clone(),
+                replacement: name,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for EditorHelper {
+    type Hint = String;
+}
+
+impl Highlighter for EditorHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight(line))
+    }
+
+    /// The whole line is lexed again after every key, a character can change the tokens around it
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+/// Color the tokens of the line with ANSI codes, the characters that the lexer does not accept are
+/// marked as errors
+pub fn highlight(line: &str) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::with_capacity(line.len());
+    let mut last = 0;
+    let mut lexer = Lexer::new(line.chars()).with_comments();
+
+    while let Some(token) = lexer.next_token() {
+        let (span, color) = match token {
+            Ok(ref token) if token.kind == TokenKind::Eof => break,
+            Ok(token) => (token.span, color(token.kind, &token.lexeme)),
+            Err(err) => match err.span() {
+                Some(span) => (span, Some(ILLEGAL)),
+                None => break,
+            },
+        };
+        // `Indent` and `Dedent` have no characters
+        if span.start < last || span.end <= span.start {
+            continue;
+        }
+
+        out.extend(&chars[last..span.start]);
+        let text: String = chars[span.start..span.end.min(chars.len())]
+            .iter()
+            .collect();
+        match color {
+            Some(color) => {
+                out.push_str(color);
+                out.push_str(&text);
+                out.push_str(RESET);
+            }
+            None => out.push_str(&text),
+        }
+        last = span.end.min(chars.len());
+    }
+
+    out.extend(&chars[last..]);
+    out
+}
+
+fn color(kind: TokenKind, lexeme: &str) -> Option<&'static str> {
+    match kind {
+        TokenKind::Keyword => Some(KEYWORD),
+        TokenKind::Not if lexeme == "not" => Some(KEYWORD),
+        TokenKind::Boolean | TokenKind::Null => Some(LITERAL),
+        TokenKind::Number | TokenKind::Float => Some(NUMBER),
+        TokenKind::String => Some(STRING),
+        TokenKind::Comment => Some(COMMENT),
+        TokenKind::Illegal => Some(ILLEGAL),
+        _ => None,
+    }
+}
+
+impl Validator for EditorHelper {}
+
+impl rustyline::Helper for EditorHelper {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_interpreter::value::Value;
+    use ezcript_lexer::number::Number;
+
+    #[test]
+    fn test_candidates() {
+        let mut globals = Environment::new(None);
+        globals.define("counter", Value::Number(Number::Integer(1)));
+        globals.define("total", Value::Number(Number::Integer(2)));
+        let helper = EditorHelper {
+            globals: Some(Rc::new(RefCell::new(globals))),
+            ..EditorHelper::default()
+        };
+
+        assert_eq!(
+            helper.candidates("set x = co", 10),
+            (8, vec!["const".to_string(), "counter".to_string()])
+        );
+        assert_eq!(
+            helper.candidates("print(to", 8),
+            (6, vec!["total".to_string()])
+        );
+        assert_eq!(helper.candidates(":re", 3), (1, vec!["reset".to_string()]));
+        assert_eq!(helper.candidates("total.", 6), (6, Vec::new()));
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(
+            highlight("set x = 1 # one"),
+            "\x1b[35mset\x1b[0m x = \x1b[33m1\x1b[0m \x1b[90m# one\x1b[0m"
+        );
+        assert_eq!(
+            highlight("print(\"é\", ?)"),
+            "print(\x1b[32m\"é\"\x1b[0m, \x1b[1;4;31m?\x1b[0m)"
+        );
+    }
+}
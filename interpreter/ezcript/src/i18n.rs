@@ -0,0 +1,221 @@
+use std::env;
+
+/// The languages of the messages of the interactive prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lang {
+    En,
+    Es,
+}
+
+/// The messages of the interactive prompt that are translated, `{}` is replaced by the caller
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Text {
+    Welcome,
+    HelpHint,
+    UnknownCommand,
+    Usage,
+    NoHelp,
+    UnknownLang,
+    LangChanged,
+    Reset,
+    Loaded,
+    NoBindings,
+    CodeArg,
+    FileArg,
+}
+
+impl Lang {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "en" => Some(Lang::En),
+            "es" => Some(Lang::Es),
+            _ => None,
+        }
+    }
+
+    /// The language of the system, from the `LANG` variable, or English
+    pub fn from_env() -> Self {
+        match env::var("LANG") {
+            Ok(ref lang) if lang.starts_with("es") => Lang::Es,
+            _ => Lang::En,
+        }
+    }
+
+    pub fn text(self, text: Text) -> &'static str {
+        match (self, text) {
+            (Lang::En, Text::Welcome) => "Welcome to Ezcript v{}",
+            (Lang::Es, Text::Welcome) => "Bienvenido a Ezcript v{}",
+            (Lang::En, Text::HelpHint) => "Type \":help\" for more information",
+            (Lang::Es, Text::HelpHint) => "Escribe \":help\" para más información",
+            (Lang::En, Text::UnknownCommand) => {
+                "unknown command ':{}', type ':help' to see the commands"
+            }
+            (Lang::Es, Text::UnknownCommand) => {
+                "comando desconocido ':{}', escribe ':help' para ver los comandos"
+            }
+            (Lang::En, Text::Usage) => "usage: {}",
+            (Lang::Es, Text::Usage) => "uso: {}",
+            (Lang::En, Text::NoHelp) => "there is no help about '{}'",
+            (Lang::Es, Text::NoHelp) => "no hay ayuda sobre '{}'",
+            (Lang::En, Text::UnknownLang) => "unknown language '{}', use 'es' or 'en'",
+            (Lang::Es, Text::UnknownLang) => "idioma desconocido '{}', usa 'es' o 'en'",
+            (Lang::En, Text::LangChanged) => "The messages are now in English",
+            (Lang::Es, Text::LangChanged) => "Los mensajes ahora están en español",
+            (Lang::En, Text::Reset) => "All the variables were removed",
+            (Lang::Es, Text::Reset) => "Se borraron todas las variables",
+            (Lang::En, Text::Loaded) => "Loaded {}",
+            (Lang::Es, Text::Loaded) => "Se cargó {}",
+            (Lang::En, Text::NoBindings) => "There are no variables yet",
+            (Lang::Es, Text::NoBindings) => "Todavía no hay variables",
+            (Lang::En, Text::CodeArg) => "<code>",
+            (Lang::Es, Text::CodeArg) => "<código>",
+            (Lang::En, Text::FileArg) => "<file>",
+            (Lang::Es, Text::FileArg) => "<archivo>",
+        }
+    }
+
+    /// The help about a topic, `None` if there is no help about it. The empty topic is the list
+    /// of commands.
+    pub fn help(self, topic: &str) -> Option<&'static str> {
+        let help = match (self, topic) {
+            (Lang::En, "") => {
+                "Commands:
+  :tokens <code>   Shows the tokens of the code
+  :ast <code>      Shows the syntax tree of the code
+  :type <code>     Shows the inferred type of the code
+  :load <file>     Executes a script and keeps its variables
+  :reset           Removes all the variables
+  :env             Shows the variables and their values
+  :help <topic>    Shows the help about a topic
+  :lang es|en      Changes the language of the messages
+  :exit            Ends the session, the same as Ctrl-D
+
+Topics: set, const, if, while, do, for, func, class, interface, match, is, return, break, print, arg"
+            }
+            (Lang::Es, "") => {
+                "Comandos:
+  :tokens <código>  Muestra los símbolos del código
+  :ast <código>     Muestra el árbol de sintaxis del código
+  :type <código>    Muestra el tipo inferido del código
+  :load <archivo>   Ejecuta un script y conserva sus variables
+  :reset            Borra todas las variables
+  :env              Muestra las variables y sus valores
+  :help <tema>      Muestra la ayuda sobre un tema
+  :lang es|en       Cambia el idioma de los mensajes
+  :exit             Termina la sesión, lo mismo que Ctrl-D
+
+Temas: set, const, if, while, do, for, func, class, interface, match, is, return, break, print, arg"
+            }
+            (Lang::En, "set") => "set name = value\n  Creates a variable, without a value it is null",
+            (Lang::Es, "set") => "set nombre = valor\n  Crea una variable, sin un valor es null",
+            (Lang::En, "const") => "const name = value\n  Creates a variable that can not change",
+            (Lang::Es, "const") => "const nombre = valor\n  Crea una variable que no puede cambiar",
+            (Lang::En, "if") => {
+                "if condition do ... elseif condition do ... else do ...\n  \
+                 Executes the first block whose condition is true"
+            }
+            (Lang::Es, "if") => {
+                "if condición do ... elseif condición do ... else do ...\n  \
+                 Ejecuta el primer bloque cuya condición es verdadera"
+            }
+            (Lang::En, "while") => {
+                "while condition do ...\n  Executes the block while the condition is true"
+            }
+            (Lang::Es, "while") => {
+                "while condición do ...\n  Ejecuta el bloque mientras la condición sea verdadera"
+            }
+            (Lang::En, "do") => {
+                "do ... while condition\n  Executes the block once and then while the condition is true"
+            }
+            (Lang::Es, "do") => {
+                "do ... while condición\n  \
+                 Ejecuta el bloque una vez y luego mientras la condición sea verdadera"
+            }
+            (Lang::En, "for") => {
+                "for each name in value do ...\n  Executes the block for every item of the value"
+            }
+            (Lang::Es, "for") => {
+                "for each nombre in valor do ...\n  Ejecuta el bloque para cada elemento del valor"
+            }
+            (Lang::En, "func") => {
+                "func name(parameter, other = default) do ...\n  \
+                 Creates a function that is executed when it is called, like name(1, other = 2).\n  \
+                 Without a name, func(x) do ... is a value that can be saved or passed"
+            }
+            (Lang::Es, "func") => {
+                "func nombre(parámetro, otro = valor) do ...\n  \
+                 Crea una función que se ejecuta cuando se llama, como nombre(1, otro = 2).\n  \
+                 Sin nombre, func(x) do ... es un valor que se puede guardar o pasar"
+            }
+            (Lang::En, "class") => {
+                "class Name inherit Other, IOther do ...\n  \
+                 Creates a class with the methods of its block, Name(...) creates an object and\n  \
+                 calls init(self, ...). The methods of Other can be used with super.method(...)"
+            }
+            (Lang::Es, "class") => {
+                "class Nombre inherit Otra, IOtra do ...\n  \
+                 Crea una clase con los métodos de su bloque, Nombre(...) crea un objeto y\n  \
+                 llama a init(self, ...). Los métodos de Otra se usan con super.método(...)"
+            }
+            (Lang::En, "interface") => {
+                "interface Name do\n    field: Type\n    other: { type: Type, required: false }\n  \
+                 The fields that the objects of the classes that inherit it must set in init"
+            }
+            (Lang::Es, "interface") => {
+                "interface Nombre do\n    campo: Tipo\n    otro: { type: Tipo, required: false }\n  \
+                 Los campos que los objetos de las clases que la heredan deben poner en init"
+            }
+            (Lang::En, "match") => {
+                "match value do\n    1 do ...\n    2..5 do ...\n    [x, y] if x > y do ...\n    \
+                 Name { field } do ...\n    _ do ...\n  \
+                 Executes the first arm whose pattern matches the value, _ matches everything"
+            }
+            (Lang::Es, "match") => {
+                "match valor do\n    1 do ...\n    2..5 do ...\n    [x, y] if x > y do ...\n    \
+                 Nombre { campo } do ...\n    _ do ...\n  \
+                 Ejecuta el primer caso cuyo patrón coincide con el valor, _ coincide con todo"
+            }
+            (Lang::En, "is") => {
+                "value is Type\n  \
+                 True if the value has the type, like x is string or x is Name.\n  \
+                 value.type gives the type of the value, like string or its class"
+            }
+            (Lang::Es, "is") => {
+                "valor is Tipo\n  \
+                 Verdadero si el valor tiene el tipo, como x is string o x is Nombre.\n  \
+                 valor.type da el tipo del valor, como string o su clase"
+            }
+            (Lang::En, "return") => "return value\n  Ends the function and gives back the value",
+            (Lang::Es, "return") => "return valor\n  Termina la función y devuelve el valor",
+            (Lang::En, "break") => "break\n  Ends the loop where it is",
+            (Lang::Es, "break") => "break\n  Termina el ciclo donde está",
+            (Lang::En, "print") => "print(values...)\n  Writes the values separated by spaces",
+            (Lang::Es, "print") => "print(valores...)\n  Escribe los valores separados por espacios",
+            (Lang::En, "arg") => "arg(index)\n  Gives the argument of the command line in that position",
+            (Lang::Es, "arg") => "arg(índice)\n  Da el argumento de la línea de comandos en esa posición",
+            _ => return None,
+        };
+        Some(help)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_help_topics() {
+        for lang in [Lang::En, Lang::Es] {
+            let commands = lang.help("").unwrap();
+            let topics = commands.rsplit(": ").next().unwrap();
+            for topic in topics.split(", ") {
+                assert!(
+                    lang.help(topic).is_some(),
+                    "{:?} has no help for {}",
+                    lang,
+                    topic
+                );
+            }
+        }
+    }
+}
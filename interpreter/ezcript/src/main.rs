@@ -1,84 +1,631 @@
 extern crate clap;
-use clap::{App, Arg};
-use std::fs::File;
+use clap::{crate_version, App, AppSettings, Arg, ArgMatches, ErrorKind, SubCommand};
+use std::fs::{self, File};
 use std::io::prelude::*;
-use std::io::{stdin, stdout, BufReader};
+use std::io::{stdin, BufReader};
+use std::path::Path;
+use std::process;
+use std::thread;
 
-use ezcript_lexer::{
-    lexer::Lexer,
-    tokens::{Token, TokenKind},
+use ezcript_checker::checker::Checker;
+use ezcript_interpreter::{
+    compiler::Compiler, disassembler::disassemble, ezc, gc, interpreter::Interpreter,
+    resolver::Resolver, vm::Vm,
 };
-use ezcript_result::Result;
+use ezcript_lexer::{lexer::Lexer, tokens::Token};
+use ezcript_parser::{ast::Stmt, parser::Parser, printer};
+use ezcript_result::{Diagnostic, Error, Result, Warning};
 
-fn main() -> Result<()> {
-    let args = App::new("Ezcript-lang")
-        .version("[1.0]")
+mod dump;
+mod formatter;
+mod helper;
+mod i18n;
+mod repl;
+
+use dump::{dump_tokens, TokenFormat};
+use repl::{Repl, Session};
+
+/// How the errors are shown to the user
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+    /// The `Display` form of the error, made for humans
+    Human,
+    /// One JSON object per line, made for other programs
+    Json,
+}
+
+fn file_arg(help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name("file")
+        .value_name("FILE")
+        .help(help)
+        .required(true)
+        .takes_value(true)
+}
+
+fn decimal_arg() -> Arg<'static, 'static> {
+    Arg::with_name("decimal")
+        .long("decimal")
+        .help("Reads the numbers with a point as exact decimals and divides the integers exactly, so 0.1 + 0.2 and 1 / 10 + 2 / 10 are 0.3")
+}
+
+fn cli() -> App<'static, 'static> {
+    App::new("Ezcript-lang")
+        .version(crate_version!())
         .author("Author: DaBitwisersWay")
         .about("\"A friendly programming language whose purpose is to teach new users in the tech world about the basics of programming languages and their use.\"")
         .arg(Arg::with_name("file")
             .value_name("FILE")
-            .help("Sets the script for execute by the interpreter")
+            .help("Sets the script for execute by the interpreter, the same as 'run FILE', '-' reads it from the standard input")
             .required(false)
             .takes_value(true))
-        .get_matches();
+        .arg(Arg::with_name("eval")
+            .short("e")
+            .long("eval")
+            .value_name("CODE")
+            .help("Executes the code given in the command line")
+            .conflicts_with("file")
+            .takes_value(true))
+        .arg(decimal_arg())
+        .arg(Arg::with_name("error-format")
+            .long("error-format")
+            .value_name("FORMAT")
+            .help("Sets how the errors are reported")
+            .possible_values(&["human", "json"])
+            .default_value("human")
+            .takes_value(true)
+            .global(true))
+        .subcommand(SubCommand::with_name("run")
+            .about("Executes a script")
+            .setting(AppSettings::TrailingVarArg)
+            .arg(file_arg("Sets the script for execute by the interpreter, '-' reads it from the standard input"))
+            .arg(Arg::with_name("vm")
+                .long("vm")
+                .help("Compiles the script to bytecode and executes it in the virtual machine"))
+            .arg(decimal_arg())
+            .arg(Arg::with_name("gc-stats")
+                .long("gc-stats")
+                .help("Prints how many times the garbage collector ran and the size of the heap at the end"))
+            .arg(Arg::with_name("gc-stress")
+                .long("gc-stress")
+                .help("Runs the garbage collector on every allocation, to test it"))
+            .arg(Arg::with_name("args")
+                .value_name("ARGS")
+                .help("Sets the arguments that the script can read with 'arg'")
+                .multiple(true)))
+        .subcommand(SubCommand::with_name("compile")
+            .about("Compiles a script to bytecode that 'run' executes in the virtual machine")
+            .arg(file_arg("Sets the script to compile"))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("FILE")
+                .help("Sets the file where the bytecode is written, the script with the .ezc extension by default")
+                .takes_value(true))
+            .arg(decimal_arg()))
+        .subcommand(SubCommand::with_name("disasm")
+            .about("Prints the bytecode of a compiled script, or of a script after compiling it")
+            .arg(file_arg("Sets the compiled script (.ezc) or the script to disassemble"))
+            .arg(decimal_arg()))
+        .subcommand(SubCommand::with_name("repl")
+            .about("Starts the interactive prompt")
+            .arg(decimal_arg()))
+        .subcommand(SubCommand::with_name("tokens")
+            .about("Prints the tokens that the lexer creates from a script")
+            .arg(file_arg("Sets the script to split in tokens"))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets how the tokens are printed")
+                .possible_values(&["table", "json", "csv"])
+                .default_value("table")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("ast")
+            .about("Prints the syntax tree that the parser creates from a script")
+            .arg(file_arg("Sets the script to parse"))
+            .arg(Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets how the syntax tree is printed")
+                .possible_values(&["tree", "sexp", "json", "dot"])
+                .default_value("tree")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("check")
+            .about("Looks for syntax and type errors in a script without executing it")
+            .arg(file_arg("Sets the script to check"))
+            .arg(Arg::with_name("hover")
+                .long("hover")
+                .value_name("LINE:COLUMN")
+                .validator(|position| parse_position(&position).map(|_| ()))
+                .help("Prints the inferred type of the name at that position")
+                .takes_value(true)))
+        .subcommand(SubCommand::with_name("fmt")
+            .about("Prints a script with the canonical layout")
+            .arg(file_arg("Sets the script to format"))
+            .arg(Arg::with_name("check")
+                .long("check")
+                .help("Only tells if the script is already formatted, without printing it"))
+            .arg(Arg::with_name("write")
+                .long("write")
+                .short("w")
+                .conflicts_with("check")
+                .help("Writes the formatted script back to the file")))
+}
+
+/// The size of the stack of the thread that runs the scripts, the interpreter is recursive and
+/// deep recursion in a script needs a lot of it
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+fn main() {
+    let code = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("Failed to start the interpreter")
+        .join()
+        .unwrap_or(101);
+    process::exit(code);
+}
+
+/// Execute the command and give back the exit code of the process
+fn run() -> i32 {
+    let args = match cli().get_matches_safe() {
+        Ok(args) => args,
+        Err(err) => match err.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => {
+                println!("{}", err.message);
+                return 0;
+            }
+            _ => {
+                eprintln!("{}", err.message);
+                return Error::Usage.exit_code();
+            }
+        },
+    };
+
+    let (file_name, errors) = match args.subcommand() {
+        ("run", Some(run_args)) => {
+            let file_name = run_args.value_of("file");
+            let script_args = run_args
+                .values_of("args")
+                .map_or_else(Vec::new, |args| args.map(String::from).collect());
+            let format = error_format(run_args);
+            let vm = run_args.is_present("vm");
+            let decimals = run_args.is_present("decimal");
+            gc::set_stress(run_args.is_present("gc-stress"));
+            let errors = run_file(file_name.unwrap(), script_args, vm, decimals, format);
+            if run_args.is_present("gc-stats") {
+                gc::collect();
+                eprintln!("gc: {}", gc::stats());
+            }
+            (file_name, errors)
+        }
+        ("compile", Some(compile_args)) => {
+            let file_name = compile_args.value_of("file");
+            let output = compile_args.value_of("output");
+            let decimals = compile_args.is_present("decimal");
+            let format = error_format(compile_args);
+            let result = run_compile(file_name.unwrap(), output, decimals, format);
+            (file_name, result)
+        }
+        ("disasm", Some(disasm_args)) => {
+            let file_name = disasm_args.value_of("file");
+            let decimals = disasm_args.is_present("decimal");
+            let format = error_format(disasm_args);
+            (file_name, run_disasm(file_name.unwrap(), decimals, format))
+        }
+        ("repl", Some(repl_args)) => {
+            let decimals = repl_args.is_present("decimal");
+            (None, run_prompt(decimals, error_format(repl_args)))
+        }
+        ("tokens", Some(tokens_args)) => {
+            let file_name = tokens_args.value_of("file");
+            let token_format = tokens_args
+                .value_of("format")
+                .and_then(TokenFormat::from_name)
+                .unwrap_or(TokenFormat::Table);
+            (file_name, run_tokens(file_name.unwrap(), token_format))
+        }
+        ("ast", Some(ast_args)) => {
+            let file_name = ast_args.value_of("file");
+            let ast_format = ast_args.value_of("format").unwrap_or("tree");
+            (file_name, run_ast(file_name.unwrap(), ast_format))
+        }
+        ("check", Some(check_args)) => {
+            let file_name = check_args.value_of("file");
+            let format = error_format(check_args);
+            let hover = check_args
+                .value_of("hover")
+                .and_then(|position| parse_position(position).ok());
+            (file_name, run_check(file_name.unwrap(), hover, format))
+        }
+        ("fmt", Some(fmt_args)) => {
+            let file_name = fmt_args.value_of("file");
+            let result = run_fmt(
+                file_name.unwrap(),
+                fmt_args.is_present("check"),
+                fmt_args.is_present("write"),
+            );
+            (file_name, result)
+        }
+        _ => {
+            let decimals = args.is_present("decimal");
+            let format = error_format(&args);
+            match (args.value_of("eval"), args.value_of("file")) {
+                (Some(code), _) => {
+                    let result = run_source(code, EVAL, Vec::new(), false, decimals, format);
+                    (Some(EVAL), result)
+                }
+                (None, Some(file_name)) => {
+                    let result = run_file(file_name, Vec::new(), false, decimals, format);
+                    (Some(file_name), result)
+                }
+                (None, None) => (None, run_prompt(decimals, format)),
+            }
+        }
+    };
+
+    let format = match args.subcommand() {
+        (_, Some(sub_args)) => error_format(sub_args),
+        _ => error_format(&args),
+    };
+    let errors = match errors {
+        Ok(errors) => errors,
+        Err(err) => vec![err],
+    };
+    match errors.first() {
+        Some(err) => {
+            report(&errors, display_name(file_name.unwrap_or(STDIN)), format);
+            err.exit_code()
+        }
+        None => 0,
+    }
+}
 
-    let file_name = args.value_of("file");
+/// The name of the code given with `--eval` in the diagnostics
+const EVAL: &str = "<eval>";
+/// The name of the code read from the standard input in the diagnostics
+const STDIN: &str = "-";
 
-    if file_name == None {
-        run_prompt()?;
+/// The name of the file in the diagnostics, `-` is the standard input
+fn display_name(file_name: &str) -> &str {
+    if file_name == STDIN {
+        "<stdin>"
     } else {
-        run_file(file_name)?;
+        file_name
+    }
+}
+
+fn error_format(args: &ArgMatches) -> ErrorFormat {
+    match args.value_of("error-format") {
+        Some("json") => ErrorFormat::Json,
+        _ => ErrorFormat::Human,
+    }
+}
+
+/// Print the errors to the standard error in the requested format
+fn report(errors: &[Error], file_name: &str, format: ErrorFormat) {
+    for err in errors {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", err),
+            ErrorFormat::Json => eprintln!("{}", Diagnostic::from_error(err, file_name).to_json()),
+        }
+    }
+}
+
+/// Print the warnings to the standard error in the requested format
+fn warn(warnings: &[Warning], file_name: &str, format: ErrorFormat) {
+    for warning in warnings {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", warning),
+            ErrorFormat::Json => {
+                eprintln!("{}", Diagnostic::from_warning(warning, file_name).to_json())
+            }
+        }
+    }
+}
+
+/// Split the source code in tokens, the lexical errors are collected instead of stopping the
+/// lexer, so all of them can be reported at once
+fn tokenize(source: &str) -> (Vec<Token>, Vec<Error>) {
+    tokenize_with(Lexer::new(source.chars()))
+}
+
+fn tokenize_with(mut lexer: Lexer) -> (Vec<Token>, Vec<Error>) {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut errors: Vec<Error> = Vec::new();
+
+    while let Some(token) = lexer.next_token() {
+        match token {
+            Ok(token) => tokens.push(token),
+            Err(err) => errors.push(err),
+        }
+    }
+    (tokens, errors)
+}
+
+/// Split the source code in tokens and build the syntax tree with them
+fn parse(source: &str) -> std::result::Result<Vec<Stmt>, Vec<Error>> {
+    let (tokens, errors) = tokenize(source);
+    if !errors.is_empty() {
+        return Err(errors);
     }
+    Parser::new(tokens).parse().map_err(|err| vec![err])
+}
 
-    Ok(())
+/// Like `parse`, but the variables are also resolved, so the code is ready to be executed. The
+/// warnings of the parser and of the resolver are given back.
+fn parse_with_warnings(source: &str) -> std::result::Result<(Vec<Stmt>, Vec<Warning>), Vec<Error>> {
+    parse_with_warnings_from(Lexer::new(source.chars()), Vec::new())
+}
+
+/// Like `parse_with_warnings`, with the tokens of the lexer. `globals` are the variables that the
+/// top level already has, like the ones of the entries of the prompt before this one.
+fn parse_with_warnings_from(
+    lexer: Lexer,
+    globals: Vec<String>,
+) -> std::result::Result<(Vec<Stmt>, Vec<Warning>), Vec<Error>> {
+    let (tokens, errors) = tokenize_with(lexer);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse().map_err(|err| vec![err])?;
+    let mut resolver = Resolver::new().with_globals(globals);
+    let errors = resolver.resolve(&statements);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut warnings = parser.warnings().to_vec();
+    warnings.extend_from_slice(resolver.warnings());
+    Ok((statements, warnings))
 }
 
-fn run_prompt() -> Result<()> {
-    println!(
-        "Welcom to Ezcript v1.0.0
-Type \"help\" for more information"
-    );
+fn run_prompt(decimals: bool, format: ErrorFormat) -> Result<Vec<Error>> {
+    let mut session = Session::new(format).with_decimals(decimals);
+    session.welcome();
+    let mut repl = Repl::new();
     loop {
-        let mut source = String::new();
-        print!(">> ");
-        stdout().flush()?;
-        stdin().read_line(&mut source).expect("Failed to read line");
-        source.remove(source.len() - 1);
-        let mut lexer = Lexer::new(source.chars());
-        if source == "exit".to_string() {
+        repl.set_globals(session.globals());
+        let source = match repl.read_entry()? {
+            Some(source) => source,
+            None => break,
+        };
+        if !session.entry(&source) {
             break;
-        } else if source == "help" {
-            println!("Some help message");
-        } else {
-            let mut tokens: Vec<Token> = Vec::new();
-            for _i in 0..source.len() {
-                let token: Token = lexer.next_token().unwrap().unwrap();
-                if token.kind == TokenKind::Eof {
-                    break;
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Read the whole script, `-` reads it from the standard input
+fn read_file(file_name: &str) -> Result<String> {
+    source(read_bytes(file_name)?)
+}
+
+/// The source code in the bytes of a file, it must be UTF-8
+fn source(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|err| {
+        Error::IO(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            err.utf8_error(),
+        ))
+    })
+}
+
+/// Read the whole file, which can be a script or a compiled script
+fn read_bytes(file_name: &str) -> Result<Vec<u8>> {
+    let mut contents = Vec::new();
+    if file_name == STDIN {
+        stdin().read_to_end(&mut contents)?;
+        return Ok(contents);
+    }
+    let file = File::open(file_name)?;
+    BufReader::new(file).read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// Execute a script, or a compiled script in the VM
+fn run_file(
+    file_name: &str,
+    args: Vec<String>,
+    vm: bool,
+    decimals: bool,
+    format: ErrorFormat,
+) -> Result<Vec<Error>> {
+    let bytes = read_bytes(file_name)?;
+    if ezc::is_compiled(&bytes) {
+        // The decimals of a compiled script are the ones it was compiled with
+        Vm::new().with_args(args).interpret(ezc::decode(&bytes)?)?;
+        return Ok(Vec::new());
+    }
+    run_source(&source(bytes)?, file_name, args, vm, decimals, format)
+}
+
+/// Parse and check a script, the warnings are reported and the errors are given back. The numbers
+/// with a point are exact decimals if `decimals` is true.
+fn prepare(
+    source: &str,
+    file_name: &str,
+    decimals: bool,
+    format: ErrorFormat,
+) -> std::result::Result<Vec<Stmt>, Vec<Error>> {
+    let lexer = Lexer::new(source.chars()).with_decimals(decimals);
+    let (statements, warnings) = parse_with_warnings_from(lexer, Vec::new())?;
+    warn(&warnings, display_name(file_name), format);
+    let analysis = Checker::new().analyze(&statements);
+    warn(&analysis.warnings, display_name(file_name), format);
+    if !analysis.errors.is_empty() {
+        return Err(analysis.errors);
+    }
+    Ok(statements)
+}
+
+fn run_source(
+    source: &str,
+    file_name: &str,
+    args: Vec<String>,
+    vm: bool,
+    decimals: bool,
+    format: ErrorFormat,
+) -> Result<Vec<Error>> {
+    let statements = match prepare(source, file_name, decimals, format) {
+        Ok(statements) => statements,
+        Err(errors) => return Ok(errors),
+    };
+    if vm {
+        let script = Compiler::new()
+            .with_decimals(decimals)
+            .compile(&statements)?;
+        Vm::new().with_args(args).interpret(script)?;
+    } else {
+        Interpreter::new()
+            .with_args(args)
+            .with_decimals(decimals)
+            .interpret(&statements)?;
+    }
+    Ok(Vec::new())
+}
+
+/// Compile a script to bytecode and write it in a `.ezc` file
+fn run_compile(
+    file_name: &str,
+    output: Option<&str>,
+    decimals: bool,
+    format: ErrorFormat,
+) -> Result<Vec<Error>> {
+    let statements = match prepare(&read_file(file_name)?, file_name, decimals, format) {
+        Ok(statements) => statements,
+        Err(errors) => return Ok(errors),
+    };
+    let script = Compiler::new()
+        .with_decimals(decimals)
+        .compile(&statements)?;
+    let output = match output {
+        Some(output) => output.to_string(),
+        None if file_name == STDIN => "out.ezc".to_string(),
+        None => Path::new(file_name)
+            .with_extension("ezc")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    fs::write(output, ezc::encode(&script))?;
+    Ok(Vec::new())
+}
+
+/// Print the bytecode of a compiled script, a script is compiled first
+fn run_disasm(file_name: &str, decimals: bool, format: ErrorFormat) -> Result<Vec<Error>> {
+    let bytes = read_bytes(file_name)?;
+    let script = if ezc::is_compiled(&bytes) {
+        ezc::decode(&bytes)?
+    } else {
+        match prepare(&source(bytes)?, file_name, decimals, format) {
+            Ok(statements) => Compiler::new()
+                .with_decimals(decimals)
+                .compile(&statements)?,
+            Err(errors) => return Ok(errors),
+        }
+    };
+    print!("{}", disassemble(&script));
+    Ok(Vec::new())
+}
+
+fn run_tokens(file_name: &str, format: TokenFormat) -> Result<Vec<Error>> {
+    let (tokens, errors) = tokenize(&read_file(file_name)?);
+    print!("{}", dump_tokens(&tokens, format));
+    Ok(errors)
+}
+
+fn run_ast(file_name: &str, format: &str) -> Result<Vec<Error>> {
+    let statements = match parse(&read_file(file_name)?) {
+        Ok(statements) => statements,
+        Err(errors) => return Ok(errors),
+    };
+    let out = match format {
+        "sexp" => printer::sexp(&statements),
+        "json" => printer::json(&statements),
+        "dot" => printer::dot(&statements),
+        _ => printer::tree(&statements),
+    };
+    print!("{}", out);
+    Ok(Vec::new())
+}
+
+fn run_check(
+    file_name: &str,
+    hover: Option<(u64, u64)>,
+    format: ErrorFormat,
+) -> Result<Vec<Error>> {
+    match parse_with_warnings(&read_file(file_name)?) {
+        Ok((statements, warnings)) => {
+            warn(&warnings, display_name(file_name), format);
+            let analysis = Checker::new().analyze(&statements);
+            warn(&analysis.warnings, display_name(file_name), format);
+            if let Some((line, column)) = hover {
+                if let Some(found) = analysis.type_at(line, column) {
+                    println!("{}", found);
                 }
-                tokens.push(token);
-            }
-            for token in tokens {
-                println!("{}", token);
             }
+            Ok(analysis.errors)
         }
+        Err(errors) => Ok(errors),
     }
-    Ok(())
 }
 
-fn run_file(file_name: Option<&str>) -> Result<()> {
-    let file = File::open(file_name.unwrap())?;
-    let mut buf_reader = BufReader::new(file);
-    let mut contents = String::new();
-    buf_reader.read_to_string(&mut contents)?;
-    let mut lexer = Lexer::new(contents.chars());
-    for _i in 0..contents.len() {
-        let token = lexer.next_token().unwrap().unwrap();
-        if token.kind != TokenKind::Eof {
-            println!("{}", token);
-        } else {
-            break;
+/// The line and the column of a position written as `LINE:COLUMN`, both start at 1
+fn parse_position(position: &str) -> std::result::Result<(u64, u64), String> {
+    let error = || format!("'{}' is not a position like 3:14", position);
+    let (line, column) = position.split_once(':').ok_or_else(error)?;
+    match (line.parse(), column.parse()) {
+        (Ok(line), Ok(column)) if line > 0 && column > 0 => Ok((line, column)),
+        _ => Err(error()),
+    }
+}
+
+fn run_fmt(file_name: &str, check: bool, write: bool) -> Result<Vec<Error>> {
+    let source = read_file(file_name)?;
+    if let Err(errors) = parse(&source) {
+        return Ok(errors);
+    }
+
+    let (tokens, _) = tokenize_with(Lexer::new(source.chars()).with_comments());
+    let formatted = formatter::format(&source, &tokens);
+    if let Err(errors) = parse(&formatted) {
+        eprintln!(
+            "{} was left as it was, its formatted code would not parse",
+            display_name(file_name)
+        );
+        return Ok(errors);
+    }
+
+    if check {
+        if formatted != source {
+            eprintln!("{} is not formatted", display_name(file_name));
+            process::exit(1);
+        }
+    } else if write && file_name != STDIN {
+        fs::write(file_name, formatted)?;
+    } else {
+        print!("{}", formatted);
+    }
+    Ok(Vec::new())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::tokens::TokenKind;
+
+    #[test]
+    fn test_end_of_file() {
+        let (tokens, errors) = tokenize("print(");
+        assert!(errors.is_empty());
+        assert!(tokens
+            .last()
+            .is_some_and(|token| token.kind == TokenKind::Eof));
+
+        let errors = parse("print(").unwrap_err();
+        match &errors[..] {
+            [Error::Parse(span, ..)] => assert_eq!((span.column, span.start), (7, 6)),
+            errors => panic!("expected a parse error, got {:?}", errors),
         }
     }
-    Ok(())
 }
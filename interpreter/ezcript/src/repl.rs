@@ -0,0 +1,373 @@
+use std::cell::RefCell;
+use std::env;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use clap::crate_version;
+
+use rustyline::{error::ReadlineError, Editor};
+
+use crate::helper::EditorHelper;
+use crate::i18n::{Lang, Text};
+use crate::{
+    display_name, parse, parse_with_warnings_from, read_file, report, tokenize, warn, ErrorFormat,
+    STDIN,
+};
+use ezcript_checker::{checker::Checker, types::Type};
+use ezcript_interpreter::{environment::Environment, interpreter::Interpreter, value::Value};
+use ezcript_lexer::{lexer::Lexer, tokens::TokenKind};
+use ezcript_parser::{ast::Stmt, printer};
+use ezcript_result::{Error, Result, Warning};
+
+/// The prompt of the first line of an entry
+const PROMPT: &str = ">> ";
+/// The prompt of the lines that continue an entry
+const CONTINUATION: &str = ".. ";
+/// The name of the file in the home directory where the history is kept between sessions
+const HISTORY_FILE: &str = ".ezcript_history";
+
+/// The line editor of the interactive prompt, with arrow-key editing and a persistent history
+pub struct Repl {
+    editor: Editor<EditorHelper>,
+    history: Option<PathBuf>,
+}
+
+impl Repl {
+    pub fn new() -> Self {
+        let mut editor = Editor::<EditorHelper>::new();
+        editor.set_helper(Some(EditorHelper::default()));
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        if let Some(ref path) = history {
+            // There is no history the first time the prompt is used
+            let _ = editor.load_history(path);
+        }
+        Repl { editor, history }
+    }
+
+    /// Set the variables that the tab completion offers
+    pub fn set_globals(&mut self, globals: Rc<RefCell<Environment>>) {
+        if let Some(helper) = self.editor.helper_mut() {
+            helper.globals = Some(globals);
+        }
+    }
+
+    /// Read the next entry, asking for more lines while it is incomplete. Ctrl-C cancels the
+    /// current entry and Ctrl-D gives `None` to end the session.
+    pub fn read_entry(&mut self) -> Result<Option<String>> {
+        let mut source = String::new();
+        loop {
+            let prompt = if source.is_empty() {
+                PROMPT
+            } else {
+                CONTINUATION
+            };
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    if !source.is_empty() {
+                        source.push('\n');
+                    }
+                    source.push_str(&line);
+                    if !is_incomplete(&source) {
+                        break;
+                    }
+                }
+                Err(ReadlineError::Interrupted) => source.clear(),
+                Err(ReadlineError::Eof) if source.is_empty() => return Ok(None),
+                Err(ReadlineError::Eof) => break,
+                Err(ReadlineError::Io(err)) => return Err(Error::IO(err)),
+                Err(err) => return Err(Error::IO(std::io::Error::other(err))),
+            }
+        }
+
+        if !source.trim().is_empty() {
+            self.editor.add_history_entry(source.trim_end());
+        }
+        Ok(Some(source))
+    }
+}
+
+impl Drop for Repl {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.history {
+            // Losing the history is not a reason to fail when the session ends
+            let _ = self.editor.save_history(path);
+        }
+    }
+}
+
+/// The state of the interactive prompt: the variables of the entries and the language of the
+/// messages
+pub struct Session {
+    interpreter: Interpreter,
+    lang: Lang,
+    format: ErrorFormat,
+    /// If the numbers with a point are exact decimals, like in `run --decimal`
+    decimals: bool,
+}
+
+impl Session {
+    pub fn new(format: ErrorFormat) -> Self {
+        Session {
+            interpreter: Interpreter::new(),
+            lang: Lang::from_env(),
+            format,
+            decimals: false,
+        }
+    }
+
+    /// Read the numbers with a point as exact decimals and make the divisions of integers exact
+    pub fn with_decimals(mut self, decimals: bool) -> Self {
+        self.interpreter = Interpreter::new().with_decimals(decimals);
+        self.decimals = decimals;
+        self
+    }
+
+    /// The variables of the session
+    pub fn globals(&self) -> Rc<RefCell<Environment>> {
+        self.interpreter.globals.clone()
+    }
+
+    pub fn welcome(&self) {
+        println!(
+            "{}\n{}",
+            self.lang
+                .text(Text::Welcome)
+                .replace("{}", crate_version!()),
+            self.lang.text(Text::HelpHint)
+        );
+    }
+
+    /// Execute an entry or the command at its start, `false` means that the session is over
+    pub fn entry(&mut self, source: &str) -> bool {
+        let line = source.trim();
+        let (name, arg) = match line.strip_prefix(':') {
+            Some(command) => command
+                .split_once(char::is_whitespace)
+                .map_or((command, ""), |(name, arg)| (name, arg.trim())),
+            // The words that the first versions of the prompt understood
+            None if line == "exit" || line == "help" => (line, ""),
+            None => ("", source),
+        };
+
+        let errors = match name {
+            "exit" | "quit" => return false,
+            "" => self.eval(arg),
+            name => self.command(name, arg),
+        };
+        if let Some(errors) = errors {
+            report(&errors, display_name(STDIN), self.format);
+        }
+        true
+    }
+
+    /// Execute a meta-command, the errors of the code that it uses are given back
+    fn command(&mut self, name: &str, arg: &str) -> Option<Vec<Error>> {
+        let usage = match name {
+            "tokens" | "ast" | "type" if arg.is_empty() => {
+                format!(":{} {}", name, self.lang.text(Text::CodeArg))
+            }
+            "load" if arg.is_empty() => format!(":load {}", self.lang.text(Text::FileArg)),
+            "lang" if arg.is_empty() => ":lang es|en".to_string(),
+            _ => String::new(),
+        };
+        if !usage.is_empty() {
+            self.say(Text::Usage, &usage);
+            return None;
+        }
+
+        match name {
+            "tokens" => {
+                let (tokens, errors) = tokenize(arg);
+                for token in tokens {
+                    println!("{}", token);
+                }
+                return Some(errors).filter(|errors| !errors.is_empty());
+            }
+            "ast" => match parse(arg) {
+                Ok(statements) => print!("{}", printer::tree(&statements)),
+                Err(errors) => return Some(errors),
+            },
+            "type" => {
+                let statements = match parse(arg) {
+                    Ok(statements) => statements,
+                    Err(errors) => return Some(errors),
+                };
+                // The type is inferred, the code is not executed
+                let analysis = Checker::new()
+                    .with_globals(self.global_types())
+                    .analyze(&statements);
+                if !analysis.errors.is_empty() {
+                    return Some(analysis.errors);
+                }
+                if let Some(found) = analysis.last {
+                    println!("{}", found);
+                }
+            }
+            "load" => self.load(arg),
+            "reset" => {
+                self.interpreter = Interpreter::new().with_decimals(self.decimals);
+                println!("{}", self.lang.text(Text::Reset));
+            }
+            "env" => {
+                let globals = self.interpreter.globals.borrow();
+                let bindings: Vec<_> = globals
+                    .bindings()
+                    .into_iter()
+                    .filter(|(_, value)| !matches!(value, Value::Builtin(_) | Value::Type(_)))
+                    .collect();
+                if bindings.is_empty() {
+                    println!("{}", self.lang.text(Text::NoBindings));
+                }
+                for (name, value) in bindings {
+                    println!("{} = {}", name, value);
+                }
+            }
+            "help" => match self.lang.help(arg) {
+                Some(help) => println!("{}", help),
+                None => self.say(Text::NoHelp, arg),
+            },
+            "lang" => match Lang::from_name(arg) {
+                Some(lang) => {
+                    self.lang = lang;
+                    println!("{}", self.lang.text(Text::LangChanged));
+                }
+                None => self.say(Text::UnknownLang, arg),
+            },
+            _ => self.say(Text::UnknownCommand, name),
+        }
+        None
+    }
+
+    /// Execute the code and show the value of the expression at its end, if there is one
+    fn eval(&mut self, source: &str) -> Option<Vec<Error>> {
+        let statements = match self.parse(source) {
+            Ok((statements, warnings)) => {
+                warn(&warnings, display_name(STDIN), self.format);
+                statements
+            }
+            Err(errors) => return Some(errors),
+        };
+        match self.interpreter.interpret_entry(&statements) {
+            Ok(Some(Value::Null)) | Ok(None) => None,
+            Ok(Some(value)) => {
+                println!("{}", value);
+                None
+            }
+            Err(err) => Some(vec![err]),
+        }
+    }
+
+    /// Execute a script in the session, its errors are reported with the name of the file
+    fn load(&mut self, file_name: &str) {
+        let result = read_file(file_name).map(|source| self.parse(&source));
+        let errors = match result {
+            Ok(Ok((statements, warnings))) => {
+                warn(&warnings, display_name(file_name), self.format);
+                match self.interpreter.interpret(&statements) {
+                    Ok(()) => {
+                        self.say(Text::Loaded, file_name);
+                        return;
+                    }
+                    Err(err) => vec![err],
+                }
+            }
+            Ok(Err(errors)) => errors,
+            Err(err) => vec![err],
+        };
+        report(&errors, display_name(file_name), self.format);
+    }
+
+    /// Parse and resolve the code of an entry or of a loaded script
+    fn parse(&self, source: &str) -> std::result::Result<(Vec<Stmt>, Vec<Warning>), Vec<Error>> {
+        let globals = self.interpreter.globals.borrow();
+        let names = globals
+            .bindings()
+            .into_iter()
+            .map(|(name, _)| name.to_string());
+        let lexer = Lexer::new(source.chars()).with_decimals(self.decimals);
+        parse_with_warnings_from(lexer, names.collect())
+    }
+
+    /// The types of the variables of the session, for the checker
+    fn global_types(&self) -> Vec<(String, Type)> {
+        let globals = self.interpreter.globals.borrow();
+        globals
+            .bindings()
+            .into_iter()
+            .filter(|(_, value)| !matches!(value, Value::Builtin(_) | Value::Type(_)))
+            .map(|(name, value)| (name.to_string(), value_type(value)))
+            .collect()
+    }
+
+    fn say(&self, text: Text, arg: &str) {
+        println!("{}", self.lang.text(text).replace("{}", arg));
+    }
+}
+
+/// The type that the checker gives to a value
+fn value_type(value: &Value) -> Type {
+    match *value {
+        Value::Number(ref n) if n.is_integer() => Type::Integer,
+        Value::Number(_) => Type::Float,
+        Value::Instance(ref instance) => Type::Instance(instance.borrow().class.name.clone()),
+        _ => Type::from_name(value.type_name()).unwrap_or(Type::Any),
+    }
+}
+
+/// True if the source code ends inside of a string, a bracket or an indented block, so the prompt
+/// has to ask for more lines. A block ends with an empty line.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut lexer = Lexer::new(source.chars());
+    let mut depth: usize = 0;
+    let mut indented = false;
+    let mut opens_block = false;
+
+    while let Some(token) = lexer.next_token() {
+        let token = match token {
+            Ok(token) => token,
+            Err(Error::Lexical(_, ref msg, _)) if msg == "unterminated string" => return true,
+            // The other errors are reported when the entry is used
+            Err(_) => continue,
+        };
+        match token.kind {
+            TokenKind::Eof => break,
+            TokenKind::Indent => indented = true,
+            TokenKind::Dedent => continue,
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => depth += 1,
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                depth = depth.saturating_sub(1)
+            }
+            _ => (),
+        }
+        opens_block = token.kind == TokenKind::Keyword && token.lexeme == "do";
+    }
+
+    depth > 0 || ((opens_block || indented) && !source.ends_with('\n'))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::number::Number;
+
+    #[test]
+    fn test_is_incomplete() {
+        assert!(!is_incomplete("print(1 + 2)"));
+        assert!(!is_incomplete("if x do print(x)"));
+        assert!(is_incomplete("print(1,"));
+        assert!(is_incomplete("set s = \"multi"));
+        assert!(is_incomplete("while x > 0 do"));
+        assert!(is_incomplete("while x > 0 do\n    x -= 1"));
+        assert!(!is_incomplete("while x > 0 do\n    x -= 1\n"));
+    }
+
+    #[test]
+    fn test_value_type() {
+        assert_eq!(
+            value_type(&Value::Number(Number::Integer(3))),
+            Type::Integer
+        );
+        assert_eq!(value_type(&Value::Number(Number::Float(3.0))), Type::Float);
+        assert_eq!(value_type(&Value::String("a".to_string())), Type::String);
+    }
+}
@@ -0,0 +1,1312 @@
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use super::types::{Param, Signature, Type};
+use ezcript_lexer::tokens::{Literal, Token, TokenKind};
+use ezcript_parser::ast::{Expr, Function, If, Match, Pattern, Stmt, TypeExpr};
+use ezcript_result::{Error, Span, Warning};
+
+/// What the checker knows about a class or an interface
+#[derive(Debug, Default)]
+struct ClassInfo {
+    /// The names of the class and of the interfaces that it inherits
+    parents: Vec<String>,
+    /// The signatures of the methods, without `self`
+    methods: HashMap<String, Rc<Signature>>,
+}
+
+/// What the checker knows about a variable
+#[derive(Debug, Clone)]
+struct Variable {
+    /// The type of its annotation, the values saved in it must have it
+    declared: Option<Type>,
+    /// The type of the values saved in it until now
+    inferred: Type,
+    /// True if it was declared with `const`
+    constant: bool,
+    /// The type variables of its type that are new in every use, like the ones of the parameters
+    /// of a generic function
+    generics: Vec<usize>,
+}
+
+impl Variable {
+    fn new(declared: Option<Type>, inferred: Type) -> Self {
+        Variable {
+            declared,
+            inferred,
+            constant: false,
+            generics: Vec::new(),
+        }
+    }
+
+    fn value_type(&self) -> Type {
+        self.declared
+            .clone()
+            .unwrap_or_else(|| self.inferred.clone())
+    }
+}
+
+/// The function that is being checked
+#[derive(Debug)]
+struct Context {
+    name: String,
+    /// The type of the annotation after `->`
+    declared: Option<Type>,
+    /// The type of the values of its `return` statements until now
+    inferred: Option<Type>,
+}
+
+/// What the checker found in a program
+#[derive(Debug)]
+pub struct Analysis {
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Warning>,
+    /// The type of every name of the program, where it is declared and where it is used
+    pub types: Vec<(Span, Type)>,
+    /// The type of the last statement, if it is an expression
+    pub last: Option<Type>,
+}
+
+impl Analysis {
+    /// The type of the name at the position, for the editors that show it over the code
+    pub fn type_at(&self, line: u64, column: u64) -> Option<&Type> {
+        self.types
+            .iter()
+            .rev()
+            .find(|(span, _)| {
+                let width = (span.end - span.start) as u64;
+                span.line == line && span.column <= column && column < span.column + width
+            })
+            .map(|(_, found)| found)
+    }
+}
+
+/// A gradual type checker. The annotated variables, parameters and return types are checked
+/// before the program runs. The code without annotations is never reported, but the types of its
+/// values are inferred like in Hindley-Milner: the unknown types are variables that are unified
+/// as the values are used, so `set x = 1 + 2` is an `integer`, and the functions are generic,
+/// `func id(x) do return x` is a `func(a) -> a` that gives a `string` to `id("x")`.
+#[derive(Debug)]
+pub struct Checker {
+    /// The first scope has the builtins and the second one the variables of the program
+    scopes: Vec<HashMap<String, Variable>>,
+    classes: HashMap<String, ClassInfo>,
+    function: Option<Context>,
+    errors: Vec<Error>,
+    warnings: Vec<Warning>,
+    types: Vec<(Span, Type)>,
+    /// The type found for every type variable until now, by its number
+    vars: Vec<Option<Type>>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker::new()
+    }
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Checker {
+            scopes: vec![builtins(), HashMap::new()],
+            classes: HashMap::new(),
+            function: None,
+            errors: Vec::new(),
+            warnings: Vec::new(),
+            types: Vec::new(),
+            vars: Vec::new(),
+        }
+    }
+
+    /// Add variables whose types are already known, like the ones of a session of the prompt
+    pub fn with_globals(mut self, globals: Vec<(String, Type)>) -> Self {
+        for (name, value_type) in globals {
+            self.scopes[1].insert(name, Variable::new(None, value_type));
+        }
+        self
+    }
+
+    /// Check the whole program, all the mismatches that are found are given back
+    pub fn check(self, statements: &[Stmt]) -> Vec<Error> {
+        self.analyze(statements).errors
+    }
+
+    /// Check the whole program and infer the types of its names
+    pub fn analyze(mut self, statements: &[Stmt]) -> Analysis {
+        self.check_block(statements);
+        let last = match statements.last() {
+            Some(Stmt::Expression(expr)) => self.types_of_last(expr),
+            _ => None,
+        };
+        // The variables are only known at the end, when all the code was checked
+        let types = self
+            .types
+            .iter()
+            .map(|(span, found)| (*span, self.normalize(found)))
+            .collect();
+        let last = last.map(|last| self.normalize(&last));
+        Analysis {
+            errors: self.errors,
+            warnings: self.warnings,
+            types,
+            last,
+        }
+    }
+
+    /// The type of the expression that ends the program, it was already checked
+    fn types_of_last(&mut self, expr: &Expr) -> Option<Type> {
+        let errors = self.errors.len();
+        let last = self.type_of(expr);
+        self.errors.truncate(errors);
+        Some(last)
+    }
+
+    fn check_block(&mut self, statements: &[Stmt]) {
+        self.declare(statements);
+        for statement in statements {
+            self.check_stmt(statement);
+        }
+    }
+
+    /// Check a block in a scope of its own, like the ones of `if` and the loops
+    fn check_scoped(&mut self, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.check_block(statements);
+        self.scopes.pop();
+    }
+
+    /// Declare the functions and classes of a block before checking it, so they can be used in
+    /// the functions declared before them
+    fn declare(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            match statement {
+                Stmt::Class(class) => {
+                    let info = ClassInfo {
+                        parents: class.inherits.iter().map(|t| t.lexeme.clone()).collect(),
+                        methods: HashMap::new(),
+                    };
+                    self.classes.insert(class.name.lexeme.clone(), info);
+                }
+                Stmt::Interface(interface) => {
+                    self.classes
+                        .insert(interface.name.lexeme.clone(), ClassInfo::default());
+                }
+                _ => (),
+            }
+        }
+
+        for statement in statements {
+            match statement {
+                Stmt::Func(function) => {
+                    let signature = self.signature(function, false);
+                    self.define(&function.name, None, Type::Function(Some(signature)));
+                }
+                Stmt::Class(class) => {
+                    let methods: HashMap<String, Rc<Signature>> = class
+                        .methods
+                        .iter()
+                        .map(|method| (method.name.lexeme.clone(), self.signature(method, true)))
+                        .collect();
+                    if let Some(info) = self.classes.get_mut(&class.name.lexeme) {
+                        info.methods = methods;
+                    }
+                    // Calling the class gives an instance, with the arguments of `init`
+                    let params = match self.find_method(&class.name.lexeme, "init") {
+                        Some(init) => init
+                            .params
+                            .iter()
+                            .map(|param| Param {
+                                name: param.name.clone(),
+                                param_type: param.param_type.clone(),
+                                annotated: param.annotated,
+                            })
+                            .collect(),
+                        None => Vec::new(),
+                    };
+                    let constructor = Signature {
+                        params,
+                        returns: Type::Instance(class.name.lexeme.clone()),
+                    };
+                    let constructor = Type::Function(Some(Rc::new(constructor)));
+                    self.define(&class.name, None, constructor);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.type_of(expr);
+            }
+            Stmt::Set(name, annotation, value) => {
+                let value_type = value.as_ref().map(|value| (value, self.type_of(value)));
+                let declared = annotation
+                    .as_ref()
+                    .map(|annotation| self.resolve(annotation));
+                let inferred = match value_type {
+                    Some((value, value_type)) => {
+                        if let Some(ref declared) = declared {
+                            self.check_variable(name, declared, value, &value_type);
+                        }
+                        value_type
+                    }
+                    None => Type::Null,
+                };
+                self.define_variable(name, declared, inferred, false);
+            }
+            Stmt::Const(name, annotation, value) => {
+                let value_type = self.type_of(value);
+                let declared = annotation
+                    .as_ref()
+                    .map(|annotation| self.resolve(annotation));
+                if let Some(ref declared) = declared {
+                    self.check_variable(name, declared, value, &value_type);
+                }
+                self.define_variable(name, declared, value_type, true);
+            }
+            Stmt::Assign(target, operator, value) => {
+                let mut value_type = self.type_of(value);
+                if operator.kind != TokenKind::Equal {
+                    let current = self.type_of(target);
+                    value_type = self.binary(operator, &current, &value_type);
+                }
+                match target {
+                    Expr::Variable(name, _) => self.assign(name, value, value_type),
+                    target => {
+                        self.type_of(target);
+                    }
+                }
+            }
+            Stmt::Increment(target, _) => {
+                if let Expr::Variable(name, _) = target {
+                    self.check_constant(name);
+                }
+                self.type_of(target);
+            }
+            Stmt::If(if_body) => self.check_if(if_body),
+            Stmt::Match(match_body) => self.check_match(match_body),
+            Stmt::While(condition, body) | Stmt::DoWhile(body, condition) => {
+                self.type_of(condition);
+                self.check_scoped(body);
+            }
+            Stmt::ForEach(name, iterable, body) => {
+                let iterable = self.type_of(iterable);
+                let item = match self.prune(&iterable) {
+                    Type::String => Type::String,
+                    Type::Array(element) => *element,
+                    _ => Type::Any,
+                };
+                self.scopes.push(HashMap::new());
+                self.define_variable(name, None, item, false);
+                self.check_block(body);
+                self.scopes.pop();
+            }
+            Stmt::Func(function) => {
+                // The calls that come before the body is checked use the declared signature
+                let declared = self
+                    .scopes
+                    .last()
+                    .and_then(|scope| scope.get(&function.name.lexeme))
+                    .map(Variable::value_type);
+                let signature = Type::Function(Some(self.check_function(function, None)));
+                if let Some(declared) = declared {
+                    self.unify(&declared, &signature);
+                }
+                let signature = self.prune(&signature);
+                let generics = self.generalize(&signature, &function.name.lexeme);
+                self.define(&function.name, None, signature);
+                if let Some(variable) = self
+                    .scopes
+                    .last_mut()
+                    .and_then(|scope| scope.get_mut(&function.name.lexeme))
+                {
+                    variable.generics = generics;
+                }
+            }
+            Stmt::Class(class) => {
+                for method in &class.methods {
+                    let signature = self.check_function(method, Some(&class.name.lexeme));
+                    if let Some(info) = self.classes.get_mut(&class.name.lexeme) {
+                        info.methods.insert(method.name.lexeme.clone(), signature);
+                    }
+                }
+            }
+            Stmt::Interface(interface) => {
+                for field in &interface.fields {
+                    self.resolve(&field.type_expr);
+                }
+            }
+            Stmt::Return(keyword, value) => {
+                let (token, value_type) = match value {
+                    Some(value) => (value.token(), self.type_of(value)),
+                    None => (keyword, Type::Null),
+                };
+                let context = match self.function.take() {
+                    Some(context) => context,
+                    None => return,
+                };
+                if let Some(ref declared) = context.declared {
+                    if !self.is_assignable(declared, &value_type) {
+                        let msg = format!(
+                            "the function '{}' must return {}, not {}",
+                            context.name,
+                            declared,
+                            self.normalize(&value_type)
+                        );
+                        self.error(token, msg);
+                    }
+                }
+                let inferred = match context.inferred {
+                    Some(ref inferred) => self.join(inferred, &value_type),
+                    None => value_type,
+                };
+                self.function = Some(Context {
+                    inferred: Some(inferred),
+                    ..context
+                });
+            }
+            Stmt::Break(_) => (),
+        }
+    }
+
+    /// Save a value in a variable, the value must have the type of its annotation, and the
+    /// variables without one take every type that is saved in them
+    fn assign(&mut self, name: &Token, value: &Expr, value_type: Type) {
+        if self.check_constant(name) {
+            return;
+        }
+        let variable = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme));
+        let (declared, inferred) = match variable {
+            Some(variable) => (variable.declared.clone(), variable.inferred.clone()),
+            None => return,
+        };
+        match declared {
+            Some(declared) => {
+                self.types.push((name.span, declared.clone()));
+                self.check_variable(name, &declared, value, &value_type);
+            }
+            None => {
+                let inferred = self.join(&inferred, &value_type);
+                self.types.push((name.span, inferred.clone()));
+                let variable = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(&name.lexeme));
+                if let Some(variable) = variable {
+                    variable.inferred = inferred;
+                }
+            }
+        }
+    }
+
+    /// Report a change of a constant, true if the variable is one
+    fn check_constant(&mut self, name: &Token) -> bool {
+        let constant = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .is_some_and(|variable| variable.constant);
+        if constant {
+            self.error(
+                name,
+                format!("can not change the constant '{}'", name.lexeme),
+            );
+        }
+        constant
+    }
+
+    /// Report the value of an annotated variable that does not have its type
+    fn check_variable(&mut self, name: &Token, declared: &Type, value: &Expr, value_type: &Type) {
+        if !self.is_assignable(declared, value_type) {
+            let msg = format!(
+                "'{}' is declared as {}, but the value is {}",
+                name.lexeme,
+                declared,
+                self.normalize(value_type)
+            );
+            self.error(value.token(), msg);
+        }
+    }
+
+    fn check_if(&mut self, if_body: &If) {
+        for (condition, body) in &if_body.branches {
+            self.type_of(condition);
+            self.check_scoped(body);
+        }
+        if let Some(ref body) = if_body.otherwise {
+            self.check_scoped(body);
+        }
+    }
+
+    fn check_match(&mut self, match_body: &Match) {
+        self.type_of(&match_body.subject);
+        for arm in &match_body.arms {
+            self.scopes.push(HashMap::new());
+            self.define_bindings(&arm.pattern);
+            if let Some(ref guard) = arm.guard {
+                self.type_of(guard);
+            }
+            self.check_block(&arm.body);
+            self.scopes.pop();
+        }
+    }
+
+    /// The variables that a pattern makes, their type is not known
+    fn define_bindings(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => self.define_variable(name, None, Type::Any, false),
+            Pattern::Array(_, patterns) | Pattern::Tuple(_, patterns) => {
+                for pattern in patterns {
+                    self.define_bindings(pattern);
+                }
+            }
+            Pattern::Instance(_, fields) => {
+                for (_, pattern) in fields {
+                    self.define_bindings(pattern);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Literal(_) | Pattern::Range(_, _, _) => (),
+        }
+    }
+
+    /// Check the body of a function with its parameters, the first parameter of a method is an
+    /// instance of its class. The parameters without annotations have type variables, and the
+    /// signature has the return type inferred from the body when the function does not have one.
+    fn check_function(&mut self, function: &Function, class: Option<&str>) -> Rc<Signature> {
+        self.scopes.push(HashMap::new());
+        let mut params = Vec::with_capacity(function.params.len());
+        for (i, param) in function.params.iter().enumerate() {
+            let declared = param
+                .annotation
+                .as_ref()
+                .map(|annotation| self.resolve(annotation));
+            let param_type = match (&declared, class) {
+                (Some(declared), _) => declared.clone(),
+                (None, Some(class)) if i == 0 => Type::Instance(class.to_string()),
+                (None, Some(_)) => Type::Any,
+                (None, None) => self.fresh(),
+            };
+            if let Some(ref default) = param.default {
+                let default_type = self.type_of(default);
+                self.unify(&param_type, &default_type);
+                if !self.is_assignable(&param_type, &default_type) {
+                    let msg = format!(
+                        "the default value of '{}' must be {}, not {}",
+                        param.name.lexeme,
+                        param_type,
+                        self.normalize(&default_type)
+                    );
+                    self.error(default.token(), msg);
+                }
+            }
+            if class.is_none() || i > 0 {
+                params.push(Param {
+                    name: param.name.lexeme.clone(),
+                    param_type: param_type.clone(),
+                    annotated: declared.is_some(),
+                });
+            }
+            self.define(&param.name, declared, param_type);
+        }
+
+        let context = Context {
+            name: function.name().unwrap_or("func").to_string(),
+            declared: function
+                .return_type
+                .as_ref()
+                .map(|return_type| self.resolve(return_type)),
+            inferred: None,
+        };
+        let enclosing = self.function.replace(context);
+        self.check_block(&function.body);
+        let context = mem::replace(&mut self.function, enclosing).unwrap();
+        self.scopes.pop();
+
+        // A function that can end without a `return` gives back `null`, and what it gives back
+        // in the other paths is not known if it is still a variable
+        let returns = match (context.declared, context.inferred) {
+            (Some(declared), _) => declared,
+            (None, Some(inferred)) if always_returns(&function.body) => inferred,
+            (None, Some(inferred)) => match self.prune(&inferred) {
+                Type::Var(_) => Type::Any,
+                inferred => inferred.join(&Type::Null),
+            },
+            (None, None) => Type::Null,
+        };
+        Rc::new(Signature { params, returns })
+    }
+
+    /// The types of the parameters and of the return value of a function, without the first
+    /// parameter if it is a method. The unknown types are reported when the function is checked,
+    /// and the ones without annotations are variables, or `any` in the methods.
+    fn signature(&mut self, function: &Function, method: bool) -> Rc<Signature> {
+        let unknown = |checker: &mut Checker| match method {
+            true => Type::Any,
+            false => checker.fresh(),
+        };
+        let mut params = Vec::with_capacity(function.params.len());
+        for param in function.params.iter().skip(method as usize) {
+            let param_type = match param.annotation {
+                Some(ref annotation) => self.type_named(annotation).unwrap_or(Type::Any),
+                None => unknown(self),
+            };
+            params.push(Param {
+                name: param.name.lexeme.clone(),
+                param_type,
+                annotated: param.annotation.is_some(),
+            });
+        }
+        let returns = match function.return_type {
+            Some(ref return_type) => self.type_named(return_type).unwrap_or(Type::Any),
+            None => unknown(self),
+        };
+        Rc::new(Signature { params, returns })
+    }
+
+    fn type_of(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(token) => literal_type(token),
+            Expr::Variable(name, _) => {
+                let found = self.lookup(&name.lexeme);
+                self.types.push((name.span, found.clone()));
+                found
+            }
+            Expr::Grouping(expr) => self.type_of(expr),
+            Expr::Unary(operator, right) => {
+                let right = self.type_of(right);
+                let right = self.prune(&right);
+                match operator.kind {
+                    TokenKind::Minus if matches!(right, Type::Var(_) | Type::Any) => right,
+                    TokenKind::Minus if right.is_number() => right,
+                    TokenKind::Minus => {
+                        let msg = format!("can not use '-' with {}", right);
+                        self.error(operator, msg);
+                        Type::Any
+                    }
+                    _ => Type::Boolean,
+                }
+            }
+            Expr::Binary(left, operator, right) => {
+                let left = self.type_of(left);
+                let right = self.type_of(right);
+                self.binary(operator, &left, &right)
+            }
+            Expr::Logical(left, _, right) => {
+                let left = self.type_of(left);
+                let right = self.type_of(right);
+                self.join(&left, &right)
+            }
+            Expr::Call(callee, _, arguments) => {
+                let callee = self.type_of(callee);
+                let signature = match self.prune(&callee) {
+                    Type::Function(Some(signature)) => signature,
+                    // Calling a value whose type is not known yet makes it a function
+                    Type::Var(_) if arguments.iter().all(|argument| argument.name.is_none()) => {
+                        let params = arguments
+                            .iter()
+                            .map(|argument| Param {
+                                name: String::new(),
+                                param_type: self.type_of(&argument.value),
+                                annotated: false,
+                            })
+                            .collect();
+                        let returns = self.fresh();
+                        let signature = Signature {
+                            params,
+                            returns: returns.clone(),
+                        };
+                        self.unify(&callee, &Type::Function(Some(Rc::new(signature))));
+                        return returns;
+                    }
+                    _ => {
+                        for argument in arguments {
+                            self.type_of(&argument.value);
+                        }
+                        return Type::Any;
+                    }
+                };
+
+                for (i, argument) in arguments.iter().enumerate() {
+                    let argument_type = self.type_of(&argument.value);
+                    let param = match argument.name {
+                        Some(ref name) => signature.params.iter().find(|p| p.name == name.lexeme),
+                        None => signature.params.get(i),
+                    };
+                    let param = match param {
+                        Some(param) => param,
+                        None => continue,
+                    };
+                    self.unify(&param.param_type, &argument_type);
+                    if param.annotated && !self.is_assignable(&param.param_type, &argument_type) {
+                        let msg = format!(
+                            "the argument '{}' must be {}, not {}",
+                            param.name,
+                            param.param_type,
+                            self.normalize(&argument_type)
+                        );
+                        self.error(argument.value.token(), msg);
+                    }
+                }
+                signature.returns.clone()
+            }
+            Expr::Get(object, name) => match self.type_of(object) {
+                Type::Instance(class) => match self.find_method(&class, &name.lexeme) {
+                    Some(signature) => Type::Function(Some(signature)),
+                    None => Type::Any,
+                },
+                _ => Type::Any,
+            },
+            Expr::Index(object, _, index) => {
+                let object = self.type_of(object);
+                self.type_of(index);
+                match self.prune(&object) {
+                    Type::String => Type::String,
+                    Type::Array(element) => *element,
+                    _ => Type::Any,
+                }
+            }
+            Expr::Array(_, elements) => {
+                let mut element = self.fresh();
+                for value in elements {
+                    let value = self.type_of(value);
+                    element = self.join(&element, &value);
+                }
+                Type::Array(Box::new(element))
+            }
+            Expr::Tuple(_, elements) => {
+                for element in elements {
+                    self.type_of(element);
+                }
+                Type::Tuple
+            }
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.type_of(key);
+                    self.type_of(value);
+                }
+                Type::Object
+            }
+            Expr::Super(_, _) => Type::Any,
+            Expr::Function(function) => Type::Function(Some(self.check_function(function, None))),
+            Expr::If(if_body) => {
+                self.check_if(if_body);
+                Type::Any
+            }
+            Expr::Match(match_body) => {
+                self.check_match(match_body);
+                Type::Any
+            }
+        }
+    }
+
+    /// True if a value of type `actual` can be saved where `expected` is declared. A `number` can
+    /// be whole or not, so it is accepted as `integer` and as `float`.
+    fn is_assignable(&self, expected: &Type, actual: &Type) -> bool {
+        match (self.prune(expected), self.prune(actual)) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Var(_), _) | (_, Type::Var(_)) => true,
+            (Type::Function(_), Type::Function(_)) => true,
+            (Type::Array(expected), Type::Array(actual)) => self.is_assignable(&expected, &actual),
+            (Type::Number, actual) => actual.is_number(),
+            (Type::Integer, Type::Number) => true,
+            (Type::Float, actual) => actual.is_number(),
+            (Type::Instance(expected), Type::Instance(actual)) => self.inherits(&actual, &expected),
+            (expected, actual) => expected == actual,
+        }
+    }
+
+    /// True if the class is the other one, or inherits from it directly or through others
+    fn inherits(&self, class: &str, other: &str) -> bool {
+        class == other
+            || self.classes.get(class).is_some_and(|info| {
+                info.parents
+                    .iter()
+                    .any(|parent| self.inherits(parent, other))
+            })
+    }
+
+    /// The signature of a method of the class or of the ones that it inherits
+    fn find_method(&self, class: &str, name: &str) -> Option<Rc<Signature>> {
+        let info = self.classes.get(class)?;
+        if let Some(signature) = info.methods.get(name) {
+            return Some(signature.clone());
+        }
+        info.parents
+            .iter()
+            .find_map(|parent| self.find_method(parent, name))
+    }
+
+    /// The type of an annotation, the unknown names are reported and are `any`
+    fn resolve(&mut self, type_expr: &TypeExpr) -> Type {
+        match self.type_named(type_expr) {
+            Some(found) => found,
+            None => {
+                let TypeExpr::Name(ref name) = *type_expr;
+                let msg = format!("unknown type '{}'", name.lexeme);
+                self.error(name, msg);
+                Type::Any
+            }
+        }
+    }
+
+    fn type_named(&self, type_expr: &TypeExpr) -> Option<Type> {
+        let TypeExpr::Name(ref name) = *type_expr;
+        if let Some(builtin) = Type::from_name(&name.lexeme) {
+            return Some(builtin);
+        }
+        if self.classes.contains_key(&name.lexeme) {
+            return Some(Type::Instance(name.lexeme.clone()));
+        }
+        None
+    }
+
+    fn define(&mut self, name: &Token, declared: Option<Type>, inferred: Type) {
+        let variable = Variable::new(declared, inferred);
+        self.types.push((name.span, variable.value_type()));
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), variable);
+        }
+    }
+
+    /// Create a variable of `set`, `const`, `for each` or a pattern. A constant can not be
+    /// declared again in its scope, and hiding a variable of an outer scope is warned about.
+    fn define_variable(
+        &mut self,
+        name: &Token,
+        declared: Option<Type>,
+        inferred: Type,
+        constant: bool,
+    ) {
+        let last = self.scopes.len() - 1;
+        let redeclared = self.scopes[last]
+            .get(&name.lexeme)
+            .is_some_and(|variable| variable.constant);
+        if redeclared {
+            let msg = format!("the constant '{}' can not be declared again", name.lexeme);
+            self.error(name, msg);
+        } else if self.scopes[1..last]
+            .iter()
+            .any(|scope| scope.contains_key(&name.lexeme))
+        {
+            let msg = format!("'{}' hides a variable of an outer scope", name.lexeme);
+            self.warnings.push(Warning::new(name.span, &msg));
+        }
+
+        self.define(name, declared, inferred);
+        if let Some(variable) = self.scopes[last].get_mut(&name.lexeme) {
+            variable.constant = constant;
+        }
+    }
+
+    /// The type of a variable, with new type variables in place of its generic ones
+    fn lookup(&mut self, name: &str) -> Type {
+        let (found, generics) = match self.scopes.iter().rev().find_map(|scope| scope.get(name)) {
+            Some(variable) => (variable.value_type(), variable.generics.clone()),
+            None => return Type::Any,
+        };
+        if generics.is_empty() {
+            return found;
+        }
+        let fresh = generics.into_iter().map(|id| (id, self.fresh())).collect();
+        substitute(&self.prune(&found), &fresh)
+    }
+
+    /// A new type variable
+    fn fresh(&mut self) -> Type {
+        self.vars.push(None);
+        Type::Var(self.vars.len() - 1)
+    }
+
+    /// The type with the types found for its variables in place of them
+    fn prune(&self, found: &Type) -> Type {
+        match found {
+            Type::Var(id) => match self.vars[*id] {
+                Some(ref bound) => self.prune(bound),
+                None => found.clone(),
+            },
+            Type::Array(element) => Type::Array(Box::new(self.prune(element))),
+            Type::Function(Some(signature)) => {
+                let params = signature
+                    .params
+                    .iter()
+                    .map(|param| Param {
+                        name: param.name.clone(),
+                        param_type: self.prune(&param.param_type),
+                        annotated: param.annotated,
+                    })
+                    .collect();
+                let returns = self.prune(&signature.returns);
+                Type::Function(Some(Rc::new(Signature { params, returns })))
+            }
+            found => found.clone(),
+        }
+    }
+
+    /// Make the two types the same, the variables in them take the types in the other one. The
+    /// types that do not match are left as they are, they are only reported against annotations,
+    /// and `any` does not say anything about a variable.
+    fn unify(&mut self, a: &Type, b: &Type) {
+        match (self.prune(a), self.prune(b)) {
+            (Type::Var(a), Type::Var(b)) if a == b => (),
+            (Type::Var(a), Type::Var(b)) => self.vars[a.max(b)] = Some(Type::Var(a.min(b))),
+            (Type::Var(_), Type::Any) | (Type::Any, Type::Var(_)) => (),
+            (Type::Var(id), other) | (other, Type::Var(id)) => {
+                let mut inside = Vec::new();
+                variables(&other, &mut inside);
+                // A type can not have itself inside, like the one of `f` in `f(f)`
+                self.vars[id] = Some(match inside.contains(&id) {
+                    true => Type::Any,
+                    false => other,
+                });
+            }
+            (Type::Array(a), Type::Array(b)) => self.unify(&a, &b),
+            (Type::Function(Some(a)), Type::Function(Some(b)))
+                if a.params.len() == b.params.len() =>
+            {
+                for (a, b) in a.params.iter().zip(&b.params) {
+                    self.unify(&a.param_type, &b.param_type);
+                }
+                self.unify(&a.returns, &b.returns);
+            }
+            _ => (),
+        }
+    }
+
+    /// The type of a value that can be of both types, a variable takes the other type
+    fn join(&mut self, a: &Type, b: &Type) -> Type {
+        match (self.prune(a), self.prune(b)) {
+            (a @ Type::Var(_), b) | (b, a @ Type::Var(_)) => {
+                self.unify(&a, &b);
+                self.prune(&a)
+            }
+            (Type::Array(a), Type::Array(b)) => Type::Array(Box::new(self.join(&a, &b))),
+            (a, b) => a.join(&b),
+        }
+    }
+
+    /// The type of the result of an operator. An operand whose type is not known takes the type
+    /// of the other one, so `n - 1` makes `n` an integer, and two unknown operands have the same
+    /// type, so `func add(a, b) do return a + b` is a `func(a, a) -> a`.
+    fn binary(&mut self, operator: &Token, left: &Type, right: &Type) -> Type {
+        use TokenKind::*;
+
+        let (left, right) = (self.prune(left), self.prune(right));
+        if incompatible(operator, &left, &right) {
+            let msg = format!(
+                "can not use '{}' with {} and {}",
+                operator.lexeme,
+                self.normalize(&left),
+                self.normalize(&right)
+            );
+            self.error(operator, msg);
+            return Type::Any;
+        }
+        if is_comparison(operator) {
+            return Type::Boolean;
+        }
+        match (operator.kind, &left, &right) {
+            (Slash | SlashEqual, _, _) => {
+                for operand in [&left, &right].iter() {
+                    if let Type::Var(_) = operand {
+                        self.unify(operand, &Type::Number);
+                    }
+                }
+            }
+            (_, Type::Var(_), Type::Var(_)) => {
+                self.unify(&left, &right);
+                return self.prune(&left);
+            }
+            (Plus | PlusEqual, Type::Array(a), Type::Array(b)) => {
+                return Type::Array(Box::new(self.join(a, b)));
+            }
+            (kind, Type::Var(_), other) | (kind, other, Type::Var(_)) => {
+                let joins = matches!(other, Type::String | Type::Array(_) | Type::Tuple);
+                if other.is_number() || (joins && matches!(kind, Plus | PlusEqual)) {
+                    let other = other.clone();
+                    self.unify(&left, &other);
+                    self.unify(&right, &other);
+                }
+            }
+            _ => (),
+        }
+        binary_type(operator, &self.prune(&left), &self.prune(&right))
+    }
+
+    /// The type variables of the type that no other variable of the scopes has, the uses of a
+    /// function declared with them can give them different types
+    fn generalize(&self, found: &Type, name: &str) -> Vec<usize> {
+        let mut used = Vec::new();
+        let last = self.scopes.len() - 1;
+        for (i, scope) in self.scopes.iter().enumerate() {
+            for (variable_name, variable) in scope {
+                if i == last && variable_name == name {
+                    continue;
+                }
+                let mut inside = Vec::new();
+                variables(&self.prune(&variable.value_type()), &mut inside);
+                used.extend(
+                    inside
+                        .into_iter()
+                        .filter(|id| !variable.generics.contains(id)),
+                );
+            }
+        }
+        if let Some(ref context) = self.function {
+            for found in context.declared.iter().chain(context.inferred.iter()) {
+                variables(&self.prune(found), &mut used);
+            }
+        }
+
+        let mut generics = Vec::new();
+        variables(found, &mut generics);
+        generics.retain(|id| !used.contains(id));
+        generics
+    }
+
+    /// The type as it is shown, with what is known of its variables and the rest of them
+    /// numbered from 0
+    fn normalize(&self, found: &Type) -> Type {
+        let found = self.prune(found);
+        let mut ids = Vec::new();
+        variables(&found, &mut ids);
+        let numbered = ids
+            .into_iter()
+            .enumerate()
+            .map(|(i, id)| (id, Type::Var(i)))
+            .collect();
+        substitute(&found, &numbered)
+    }
+
+    fn error(&mut self, token: &Token, msg: String) {
+        self.errors
+            .push(Error::Type(token.span, msg, token.lexeme.clone()));
+    }
+}
+
+/// True if the block ends with a `return` in all of its paths
+fn always_returns(body: &[Stmt]) -> bool {
+    match body.last() {
+        Some(Stmt::Return(_, _)) => true,
+        Some(Stmt::If(if_body)) => match if_body.otherwise {
+            Some(ref otherwise) => {
+                always_returns(otherwise)
+                    && if_body
+                        .branches
+                        .iter()
+                        .all(|(_, body)| always_returns(body))
+            }
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+/// The functions that come with the interpreter, their arguments are not checked
+fn builtins() -> HashMap<String, Variable> {
+    let function = |param: &str, returns: Type| {
+        let param = Param {
+            name: param.to_string(),
+            param_type: Type::Any,
+            annotated: false,
+        };
+        let signature = Signature {
+            params: vec![param],
+            returns,
+        };
+        Variable::new(None, Type::Function(Some(Rc::new(signature))))
+    };
+    let mut scope = HashMap::new();
+    scope.insert("print".to_string(), function("value", Type::Null));
+    scope.insert("arg".to_string(), function("position", Type::Any));
+    scope.insert("len".to_string(), function("value", Type::Integer));
+    scope
+}
+
+/// Add the type variables of the type that are not in the list yet
+fn variables(found: &Type, ids: &mut Vec<usize>) {
+    match found {
+        Type::Var(id) if !ids.contains(id) => ids.push(*id),
+        Type::Array(element) => variables(element, ids),
+        Type::Function(Some(signature)) => {
+            for param in &signature.params {
+                variables(&param.param_type, ids);
+            }
+            variables(&signature.returns, ids);
+        }
+        _ => (),
+    }
+}
+
+/// The type with some of its variables replaced
+fn substitute(found: &Type, replaced: &HashMap<usize, Type>) -> Type {
+    match found {
+        Type::Var(id) => replaced.get(id).cloned().unwrap_or(Type::Var(*id)),
+        Type::Array(element) => Type::Array(Box::new(substitute(element, replaced))),
+        Type::Function(Some(signature)) => {
+            let params = signature
+                .params
+                .iter()
+                .map(|param| Param {
+                    name: param.name.clone(),
+                    param_type: substitute(&param.param_type, replaced),
+                    annotated: param.annotated,
+                })
+                .collect();
+            let returns = substitute(&signature.returns, replaced);
+            Type::Function(Some(Rc::new(Signature { params, returns })))
+        }
+        found => found.clone(),
+    }
+}
+
+/// True if the operator compares its operands, so it gives a boolean
+fn is_comparison(operator: &Token) -> bool {
+    use TokenKind::*;
+
+    match operator.kind {
+        EqEqual | BangEqual | Less | LessEqual | Greater | GreaterEqual => true,
+        Keyword => operator.lexeme == "in" || operator.lexeme == "is",
+        _ => false,
+    }
+}
+
+/// True if the operator can not be used with the operands, like `1 + "a"` or `-` with a string.
+/// The operands whose types are not known are not checked.
+fn incompatible(operator: &Token, left: &Type, right: &Type) -> bool {
+    use TokenKind::*;
+
+    let known = |operand: &Type| !matches!(operand, Type::Any | Type::Var(_));
+    let allowed = |operand: &Type| match operator.kind {
+        EqEqual | BangEqual | Keyword => true,
+        Less | LessEqual | Greater | GreaterEqual => {
+            operand.is_number() || *operand == Type::String
+        }
+        Plus | PlusEqual => {
+            operand.is_number() || matches!(operand, Type::String | Type::Array(_) | Type::Tuple)
+        }
+        _ => operand.is_number(),
+    };
+    if [left, right]
+        .iter()
+        .any(|operand| known(operand) && !allowed(operand))
+    {
+        return true;
+    }
+    // Both operands must be numbers, or of the same type, like two strings
+    let same = match (left, right) {
+        (Type::String, Type::String) | (Type::Array(_), Type::Array(_)) => true,
+        (Type::Tuple, Type::Tuple) => true,
+        (left, right) => left.is_number() && right.is_number(),
+    };
+    let compares = matches!(operator.kind, EqEqual | BangEqual | Keyword);
+    !compares && known(left) && known(right) && !same
+}
+
+/// A number with a `.` is a `float`, like `3.0`, and without it is an `integer`. The exact
+/// decimals of the decimal mode are floats too.
+fn literal_type(token: &Token) -> Type {
+    match token.literal {
+        Some(Literal::Number(ref n)) if n.is_integer() => Type::Integer,
+        Some(Literal::Number(_)) => Type::Float,
+        Some(Literal::String(_)) => Type::String,
+        _ if token.kind == TokenKind::Boolean => Type::Boolean,
+        _ => Type::Null,
+    }
+}
+
+/// The type of the result of an operator, `any` when it is not known
+fn binary_type(operator: &Token, left: &Type, right: &Type) -> Type {
+    use TokenKind::*;
+
+    if is_comparison(operator) {
+        return Type::Boolean;
+    }
+
+    match (operator.kind, left, right) {
+        (_, Type::Any, _) | (_, _, Type::Any) => Type::Any,
+        (Plus | PlusEqual, Type::String, Type::String) => Type::String,
+        (Plus | PlusEqual, Type::Array(a), Type::Array(b)) => Type::Array(Box::new(a.join(b))),
+        (Plus | PlusEqual, Type::Tuple, Type::Tuple) => Type::Tuple,
+        (Slash | SlashEqual, left, right) if left.is_number() && right.is_number() => Type::Float,
+        (DoubleStar, left, right) if left.is_number() && right.is_number() => Type::Number,
+        (_, Type::Integer, Type::Integer) => Type::Integer,
+        (_, Type::Float, right) if right.is_number() => Type::Float,
+        (_, left, Type::Float) if left.is_number() => Type::Float,
+        (_, left, right) if left.is_number() && right.is_number() => Type::Number,
+        _ => Type::Any,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::lexer::Lexer;
+    use ezcript_parser::parser::Parser;
+
+    fn analyze(source: &str) -> Analysis {
+        let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        Checker::new().analyze(&statements)
+    }
+
+    fn check(source: &str) -> Vec<String> {
+        analyze(source)
+            .errors
+            .into_iter()
+            .map(|err| match err {
+                Error::Type(_, msg, _) => msg,
+                err => panic!("expected a type error, got {}", err),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_annotations() {
+        assert!(check(
+            "set num: Integer = 34\nset n: number = num / 2\nset s = \"a\"\ns = 1\n\
+             func f(x: number, y: string = \"b\") -> string do return y\nset t: string = f(1)"
+        )
+        .is_empty());
+        assert_eq!(
+            check("set num: Integer = \"34\""),
+            vec!["'num' is declared as integer, but the value is string"]
+        );
+        assert_eq!(
+            check("func f(x: number) -> string do return x + 1\nf(\"a\")\nf(x = true)"),
+            vec![
+                "the function 'f' must return string, not number",
+                "the argument 'x' must be number, not string",
+                "the argument 'x' must be number, not boolean",
+            ]
+        );
+        assert_eq!(check("set x: Foo = 1"), vec!["unknown type 'Foo'"]);
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = "class A do func init(self, n: integer) do self.n = n\n\
+                      class B inherit A do func f(self) -> A do return self\n\
+                      set a: A = B(1)\nset b: B = A(2.5)";
+
+        assert_eq!(
+            check(source),
+            vec![
+                "the argument 'n' must be integer, not float",
+                "'b' is declared as B, but the value is A",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_constants_and_scopes() {
+        assert_eq!(
+            check("const k = 1\nk = 2\nk++\nset k = 3"),
+            vec![
+                "can not change the constant 'k'",
+                "can not change the constant 'k'",
+                "the constant 'k' can not be declared again",
+            ]
+        );
+        let analysis = analyze("set x = 1\nif x do\n    set x = 2\n    set y = 3\nset y = 4");
+        assert!(analysis.errors.is_empty());
+        let warnings: Vec<_> = analysis.warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec!["Warning [line 3] 'x' hides a variable of an outer scope"]
+        );
+    }
+
+    #[test]
+    fn test_operators() {
+        assert!(check(
+            "set s = \"a\" + \"b\"\nset xs = [1] + [2.5]\nset b = s < \"c\" and 1 <= 2.5\n\
+             set e = s == 1 or 1 in xs or s is string\nset n = -(2 ** 3) // 2 % 5\nset t = not s"
+        )
+        .is_empty());
+        assert_eq!(
+            check("set y = 1 + \"a\"\nset x = 1\nx + \"a\"\nx += [1]\n-\"a\""),
+            vec![
+                "can not use '+' with integer and string",
+                "can not use '+' with integer and string",
+                "can not use '+=' with integer and array[integer]",
+                "can not use '-' with string",
+            ]
+        );
+        assert_eq!(
+            check("set s = \"a\"\ns - 1\ns * 2\n2.5 / s\n1 // true\ns % 2\ns ** 2\n[1] - [2]"),
+            vec![
+                "can not use '-' with string and integer",
+                "can not use '*' with string and integer",
+                "can not use '/' with float and string",
+                "can not use '//' with integer and boolean",
+                "can not use '%' with string and integer",
+                "can not use '**' with string and integer",
+                "can not use '-' with array[integer] and array[integer]",
+            ]
+        );
+        assert_eq!(
+            check("1 < \"a\"\n[1] >= [2]\nnull > 1\nfunc f(n) do return n - \"a\""),
+            vec![
+                "can not use '<' with integer and string",
+                "can not use '>=' with array[integer] and array[integer]",
+                "can not use '>' with null and integer",
+                "can not use '-' with a and string",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inference() {
+        let analysis = analyze(
+            "set x = 1 + 2\nset y = x * 0.5\nfunc half(n: number) do\n    return n / 2\n\
+             set h = half(x)\nset z = x\nz = 1.5\nz",
+        );
+        assert!(analysis.errors.is_empty());
+        assert_eq!(analysis.type_at(1, 5), Some(&Type::Integer));
+        assert_eq!(analysis.type_at(2, 9), Some(&Type::Integer));
+        assert_eq!(analysis.type_at(2, 5), Some(&Type::Float));
+        assert_eq!(analysis.type_at(5, 5), Some(&Type::Float));
+        assert_eq!(analysis.type_at(5, 3), None);
+        assert_eq!(analysis.last, Some(Type::Number));
+        // Without `else`, `f` can also give back `null`
+        let analysis = analyze("func f(b) do\n    if b do return 1\n\nf(true)");
+        assert_eq!(analysis.last, Some(Type::Any));
+        let analysis = analyze("func f(b) do\n    if b do return 1\n    return 2\n\nf(true)");
+        assert_eq!(analysis.last, Some(Type::Integer));
+    }
+
+    #[test]
+    fn test_generic_functions() {
+        let analysis = analyze(
+            "func add(a, b) do return a + b\nfunc id(x) do return x\n\
+             func apply(f, v) do return f(v)\nset n = add(1, 2)\nset s = id(\"x\")\n\
+             set xs = [1, 2]\nset y = apply(id, xs[0])",
+        );
+        assert!(analysis.errors.is_empty());
+        let shown = |line, column| analysis.type_at(line, column).map(|t| t.to_string());
+        assert_eq!(shown(1, 6), Some("func(a, a) -> a".to_string()));
+        assert_eq!(shown(3, 6), Some("func(func(a) -> b, a) -> b".to_string()));
+        assert_eq!(shown(4, 5), Some("integer".to_string()));
+        assert_eq!(shown(5, 5), Some("string".to_string()));
+        assert_eq!(shown(6, 5), Some("array[integer]".to_string()));
+        assert_eq!(shown(7, 5), Some("integer".to_string()));
+        // The parameters without annotations are not checked, `fact` also works with floats
+        let analysis = analyze(
+            "func fact(n) do\n    if n < 2 do return 1\n    return n * fact(n - 1)\n\nfact(2.5)",
+        );
+        assert!(analysis.errors.is_empty());
+        assert_eq!(
+            analysis.type_at(1, 6).unwrap().to_string(),
+            "func(integer) -> integer"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// The type of a value as the checker knows it before the program runs
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    /// The type of the code without annotations, it can be any value
+    Any,
+    Null,
+    Boolean,
+    /// A number that can be whole or not
+    Number,
+    /// A whole number, like `34`
+    Integer,
+    /// A number with decimals, like `3.5`
+    Float,
+    String,
+    /// A function, with its signature when it is known
+    Function(Option<Rc<Signature>>),
+    /// An array, with the type of its elements
+    Array(Box<Type>),
+    Object,
+    Tuple,
+    /// The instances of a class, or of the classes that inherit an interface, by its name
+    Instance(String),
+    /// A type that is not known yet, the checker finds it from how the values are used. The
+    /// parameter of `func id(x) do return x` has one, so `id` gives back what it receives.
+    Var(usize),
+}
+
+impl Type {
+    /// The type of the language with the name, in any case, like `String` or `integer`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let found = match name.to_lowercase().as_ref() {
+            // The checker does not know the classes of the values of type `instance`, nor the
+            // types that are values
+            "any" | "instance" | "class" | "interface" | "type" => Type::Any,
+            "null" => Type::Null,
+            "boolean" => Type::Boolean,
+            "number" => Type::Number,
+            "integer" => Type::Integer,
+            "float" => Type::Float,
+            "string" => Type::String,
+            "function" => Type::Function(None),
+            "array" => Type::Array(Box::new(Type::Any)),
+            "object" => Type::Object,
+            "tuple" => Type::Tuple,
+            _ => return None,
+        };
+        Some(found)
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Type::Number | Type::Integer | Type::Float)
+    }
+
+    /// The type of a value that can be of both types, like the one of a variable that saves
+    /// both. Two different numbers are a `number`, the other different types are `any`.
+    pub fn join(&self, other: &Type) -> Type {
+        match (self, other) {
+            (Type::Function(_), Type::Function(_)) => Type::Function(None),
+            (Type::Array(a), Type::Array(b)) => Type::Array(Box::new(a.join(b))),
+            (a, b) if a == b => a.clone(),
+            (a, b) if a.is_number() && b.is_number() => Type::Number,
+            _ => Type::Any,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Null => write!(f, "null"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Number => write!(f, "number"),
+            Type::Integer => write!(f, "integer"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Function(None) => write!(f, "function"),
+            Type::Function(Some(signature)) => {
+                let params: Vec<String> = signature
+                    .params
+                    .iter()
+                    .map(|param| param.param_type.to_string())
+                    .collect();
+                write!(f, "func({}) -> {}", params.join(", "), signature.returns)
+            }
+            Type::Array(element) => match **element {
+                Type::Any | Type::Var(_) => write!(f, "array"),
+                ref element => write!(f, "array[{}]", element),
+            },
+            Type::Object => write!(f, "object"),
+            Type::Tuple => write!(f, "tuple"),
+            Type::Instance(name) => write!(f, "{}", name),
+            // The variables are numbered from 0 in each type that is shown, so they are letters
+            Type::Var(id) if *id < 26 => write!(f, "{}", (b'a' + *id as u8) as char),
+            Type::Var(id) => write!(f, "t{}", id),
+        }
+    }
+}
+
+/// The types of the parameters and of the value that a function gives back
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    pub params: Vec<Param>,
+    pub returns: Type,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub param_type: Type,
+    /// False if the type was inferred, the arguments are only checked against annotations
+    pub annotated: bool,
+}
@@ -0,0 +1,79 @@
+use std::io::Write;
+
+use super::value::{Builtin, Value};
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::Token;
+use ezcript_result::{Error, Result};
+
+/// What the builtins need from the one that runs the script, the interpreter or the VM
+pub trait Host {
+    /// Where `print` writes
+    fn out(&mut self) -> &mut dyn Write;
+    /// The arguments that the script receives from the command line
+    fn args(&self) -> &[String];
+}
+
+/// The functions that every script can use without declaring them
+pub fn builtins() -> Vec<Builtin> {
+    vec![
+        Builtin {
+            name: "print",
+            arity: None,
+            function: print,
+        },
+        Builtin {
+            name: "arg",
+            arity: Some(1),
+            function: arg,
+        },
+        Builtin {
+            name: "len",
+            arity: Some(1),
+            function: len,
+        },
+    ]
+}
+
+/// `print(values...)` writes the values separated by spaces and a new line
+fn print(host: &mut dyn Host, _paren: &Token, args: Vec<Value>) -> Result<Value> {
+    let line: Vec<String> = args.iter().map(|value| value.to_string()).collect();
+    writeln!(host.out(), "{}", line.join(" "))?;
+    Ok(Value::Null)
+}
+
+/// `arg(index)` gives the argument of the command line in that position, or `null`
+fn arg(host: &mut dyn Host, paren: &Token, args: Vec<Value>) -> Result<Value> {
+    match args[0] {
+        Value::Number(ref n) if n.is_whole() && *n >= Number::Integer(0) => Ok(n
+            .to_i64()
+            .and_then(|n| host.args().get(n as usize))
+            .map_or(Value::Null, |arg| Value::String(arg.clone()))),
+        ref value => Err(Error::Runtime(
+            paren.span,
+            format!(
+                "the index of 'arg' must be a positive whole number, not {}",
+                value
+            ),
+            paren.lexeme.clone(),
+        )),
+    }
+}
+
+/// `len(value)` gives how many elements an array, object or tuple has, or how many characters a
+/// string has
+fn len(_host: &mut dyn Host, paren: &Token, args: Vec<Value>) -> Result<Value> {
+    let len = match args[0] {
+        Value::String(ref s) => s.chars().count(),
+        Value::Array(ref array) => array.borrow().len(),
+        Value::Map(ref map) => map.borrow().len(),
+        Value::Tuple(ref tuple) => tuple.len(),
+        ref value => {
+            return Err(Error::Runtime(
+                paren.span,
+                format!("a value of type {} has no length", value.type_name()),
+                paren.lexeme.clone(),
+            ))
+        }
+    };
+    Ok(Value::Number(Number::Integer(len as i64)))
+}
@@ -0,0 +1,194 @@
+use std::rc::Rc;
+
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::Token;
+
+/// An instruction of the virtual machine. The numbers are positions in the pools of the chunk, in
+/// the local slots of the function or in its code, the jumps go to an absolute position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Push a value of the constants pool
+    Constant(u16),
+    Null,
+    True,
+    False,
+    Pop,
+    /// Push the value of a local slot of the current call
+    GetLocal(u16),
+    /// Pop a value into a local slot
+    SetLocal(u16),
+    /// Push the value of a variable captured by the closure
+    GetUpvalue(u16),
+    /// Pop a value into a variable captured by the closure
+    SetUpvalue(u16),
+    /// Push the value of the global variable with the name of the token
+    GetGlobal(u16),
+    /// Pop a value into an existing global variable
+    SetGlobal(u16),
+    /// Pop a value into a new global variable
+    DefineGlobal(u16),
+    /// Pop a value into a new global constant
+    DefineConst(u16),
+    /// The closures that captured the local slots from this one up keep their own copy
+    Close(u16),
+    /// `-` or `not` on the value on the top, the token is the operator
+    Unary(u16),
+    /// An operator with the two values on the top
+    Binary(u16),
+    Jump(u32),
+    /// Pop the condition and jump if it is false
+    JumpIfFalse(u32),
+    /// Jump and keep the value on the top if it is false, pop it if not, for `and`
+    JumpIfFalseOrPop(u32),
+    /// Jump and keep the value on the top if it is true, pop it if not, for `or`
+    JumpIfTrueOrPop(u32),
+    /// Call with the arguments on the top, the number is a position in the calls of the chunk
+    Call(u16),
+    /// Create a closure of the function in the constants pool, capturing its upvalues
+    Closure(u16),
+    /// Give back the value on the top to the caller
+    Return,
+    /// Create an array with that many values from the top
+    Array(u16),
+    /// Create a tuple with that many values from the top
+    Tuple(u16),
+    /// Create an associative array with that many keys and values from the top
+    Map(u16),
+    /// Check that the value on the top can be a key, the token is the one of the key
+    Key(u16),
+    /// Push the element of the object in the index, both on the top
+    Index(u16),
+    /// Change the element of the object in the index, the value is below them
+    SetIndex(u16),
+    /// Push the attribute with the name of the token
+    GetAttribute(u16),
+    /// Change the attribute with the name of the token, the value is below the object
+    SetAttribute(u16),
+    /// Replace the value on the top with the array of values that a `for each` goes through
+    Iterate(u16),
+    /// Push the next value of the array in the local slot, its position is in the slot after it.
+    /// Jump when there are no more values.
+    ForEach(u16, u32),
+    /// Jump if the parameter was given in the call, to skip its default value
+    IfGiven(u16, u32),
+    /// Keep how many values there are in the stack in a local slot
+    Mark(u16),
+    /// Remove the values of the stack that came after the mark in a local slot, for the jumps that
+    /// leave an expression
+    Unwind(u16),
+    /// Replace the value on the top with true if it is an array of that many elements
+    MatchArray(u16),
+    /// Replace the value on the top with true if it is a tuple of that many elements
+    MatchTuple(u16),
+    /// Replace the value on the top with true if it is a number between the two constants
+    MatchRange(u16, u16),
+    /// Replace the array or tuple on the top with its element in the position
+    Element(u16),
+    /// Push the class that the class of the site extends, or `null`, the values that it inherits
+    /// are on the top and stay there
+    Superclass(u16),
+    /// Create the class of the site with the values that it inherits and its methods on the top
+    Class(u16),
+    /// Create the interface of the site with the types of its fields on the top
+    Interface(u16),
+    /// Push the value of the global variable with the name of the token, `null` if there is none,
+    /// for the types of the fields that are not a type of the language
+    TryGlobal(u16),
+    /// Replace the superclass and the `self` on the top with the method of the superclass that
+    /// has the name of the second token, the first one is the `super`
+    Super(u16, u16),
+    /// Replace the class and the value on the top with true if the value is an instance of it, the
+    /// token is the name of the class
+    MatchInstance(u16),
+    /// Replace the instance on the top with true if it has the field with the name of the token
+    HasField(u16),
+}
+
+/// A value known when the code is compiled
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Number(Number),
+    String(String),
+    Function(Rc<Prototype>),
+}
+
+/// A call in the code, with the names of its keyword arguments
+#[derive(Debug, Clone, PartialEq)]
+pub struct CallSite {
+    /// The token of the parenthesis, in the tokens of the chunk
+    pub paren: u16,
+    /// The name of each argument in the tokens of the chunk, `None` if it is not a keyword
+    pub names: Vec<Option<u16>>,
+}
+
+/// A class in the code, with the names of what it inherits
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassSite {
+    /// The token of the name of the class, in the tokens of the chunk
+    pub name: u16,
+    /// The tokens of the classes and interfaces that it inherits
+    pub inherits: Vec<u16>,
+    /// How many methods it has, their closures are on the stack
+    pub methods: u16,
+}
+
+/// An interface in the code, with its fields
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceSite {
+    /// The token of the name of the interface, in the tokens of the chunk
+    pub name: u16,
+    pub fields: Vec<FieldSite>,
+}
+
+/// A field of an interface, with the tokens of its name and of its type
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSite {
+    pub name: u16,
+    pub field_type: u16,
+    pub required: bool,
+}
+
+/// The code of a function and the pools that its instructions point to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    /// The line of the source code of each instruction
+    pub lines: Vec<u32>,
+    pub constants: Vec<Constant>,
+    /// The tokens of the names and operators, for the variables and the errors
+    pub tokens: Vec<Token>,
+    pub calls: Vec<CallSite>,
+    pub classes: Vec<ClassSite>,
+    pub interfaces: Vec<InterfaceSite>,
+}
+
+/// A parameter of a compiled function
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    /// True if the function gives it a value when the call does not
+    pub default: bool,
+}
+
+/// Where a closure takes a variable from when it is created: a local slot of the function that
+/// creates it, or one of the variables that this function captured
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capture {
+    pub local: bool,
+    pub index: u16,
+}
+
+/// A function compiled to bytecode, the script itself is one without parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prototype {
+    /// The name of the function, `None` if it is anonymous
+    pub name: Option<String>,
+    /// The parameters, in the first local slots
+    pub params: Vec<Parameter>,
+    /// How many local slots a call needs
+    pub slots: u16,
+    pub captures: Vec<Capture>,
+    /// If the divisions of integers give exact decimals, for the scripts compiled with decimals
+    pub decimals: bool,
+    pub chunk: Chunk,
+}
@@ -0,0 +1,1024 @@
+use std::convert::TryFrom;
+use std::rc::Rc;
+
+use super::chunk::{
+    CallSite, Capture, Chunk, ClassSite, Constant, FieldSite, InterfaceSite, Op, Parameter,
+    Prototype,
+};
+use super::value::Type;
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::{Literal, Token, TokenKind};
+use ezcript_parser::ast::{self, Arm, Expr, Function, If, Match, Pattern, Stmt};
+use ezcript_result::{Error, Result};
+
+/// A variable in a local slot of the function being compiled
+struct Local {
+    /// The name of the variable, empty for the slots that the compiler uses itself
+    name: String,
+    depth: usize,
+    constant: bool,
+    /// True if a closure captured it, its slot must be closed when the scope ends
+    captured: bool,
+    /// False for the parameters after the one whose default value is being compiled
+    ready: bool,
+}
+
+/// A loop being compiled, the `break`s inside of it jump to its end
+struct Loop {
+    /// The slot with the size of the stack when the loop started
+    mark: u16,
+    depth: usize,
+    breaks: Vec<usize>,
+}
+
+/// An `if` or `match` used as a value, the `return`s inside of it give the value
+struct Value {
+    /// The slot where the value is kept
+    result: u16,
+    /// The slot with the size of the stack when the expression started
+    mark: u16,
+    depth: usize,
+    exits: Vec<usize>,
+}
+
+/// The state of a function being compiled
+struct State {
+    name: Option<String>,
+    params: Vec<Parameter>,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    slots: usize,
+    /// The variables that the function captures, and if they are constants
+    captures: Vec<(Capture, bool)>,
+    depth: usize,
+    loops: Vec<Loop>,
+    values: Vec<Value>,
+}
+
+impl State {
+    fn new(name: Option<String>) -> Self {
+        State {
+            name,
+            params: Vec::new(),
+            chunk: Chunk::default(),
+            locals: Vec::new(),
+            slots: 0,
+            captures: Vec::new(),
+            depth: 0,
+            loops: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    /// The slot of the innermost local variable with the name
+    fn resolve(&self, name: &str) -> Option<(u16, bool)> {
+        self.locals
+            .iter()
+            .rposition(|local| local.ready && local.name == name)
+            .map(|slot| (slot as u16, self.locals[slot].constant))
+    }
+}
+
+/// Where the value of a variable is
+enum Variable {
+    Local(u16),
+    Upvalue(u16),
+    Global,
+}
+
+/// Compiles the statements made by the `Parser` into the bytecode that the `Vm` executes
+pub struct Compiler {
+    /// The function being compiled is the last one, the ones before it contain it
+    states: Vec<State>,
+    /// The line of the code being compiled, for the line table
+    line: u32,
+    /// If the divisions of integers give exact decimals
+    decimals: bool,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {
+            states: Vec::new(),
+            line: 1,
+            decimals: false,
+        }
+    }
+
+    /// Make the divisions of integers exact decimals, for the scripts read with decimals
+    pub fn with_decimals(mut self, decimals: bool) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    /// Compile a whole program into a function without parameters, its variables are globals
+    pub fn compile(mut self, statements: &[Stmt]) -> Result<Rc<Prototype>> {
+        self.states.push(State::new(None));
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        self.emit(Op::Null);
+        self.emit(Op::Return);
+        Ok(Rc::new(self.finish()))
+    }
+
+    fn state(&mut self) -> &mut State {
+        self.states.last_mut().unwrap()
+    }
+
+    fn finish(&mut self) -> Prototype {
+        let state = self.states.pop().unwrap();
+        Prototype {
+            name: state.name,
+            params: state.params,
+            slots: state.slots as u16,
+            captures: state
+                .captures
+                .into_iter()
+                .map(|(capture, _)| capture)
+                .collect(),
+            decimals: self.decimals,
+            chunk: state.chunk,
+        }
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let line = self.line;
+        let chunk = &mut self.state().chunk;
+        chunk.code.push(op);
+        chunk.lines.push(line);
+        chunk.code.len() - 1
+    }
+
+    /// The position of the next instruction, where the jumps to it go
+    fn here(&mut self) -> u32 {
+        self.state().chunk.code.len() as u32
+    }
+
+    /// Make the jump in the position go to the next instruction
+    fn patch(&mut self, jump: usize) {
+        let target = self.here();
+        match self.state().chunk.code[jump] {
+            Op::Jump(ref mut to)
+            | Op::JumpIfFalse(ref mut to)
+            | Op::JumpIfFalseOrPop(ref mut to)
+            | Op::JumpIfTrueOrPop(ref mut to)
+            | Op::ForEach(_, ref mut to)
+            | Op::IfGiven(_, ref mut to) => *to = target,
+            _ => unreachable!("only the jumps can be patched"),
+        }
+    }
+
+    fn patch_all(&mut self, jumps: Vec<usize>) {
+        for jump in jumps {
+            self.patch(jump);
+        }
+    }
+
+    fn token(&mut self, token: &Token) -> Result<u16> {
+        let tokens = &mut self.state().chunk.tokens;
+        // The values of the literals are in the constants, the tokens are only for the names and
+        // the errors
+        tokens.push(Token {
+            literal: None,
+            ..token.clone()
+        });
+        index(tokens.len() - 1, token, "names")
+    }
+
+    fn constant(&mut self, constant: Constant, token: &Token) -> Result<u16> {
+        let constants = &mut self.state().chunk.constants;
+        let position = match constants.iter().position(|c| match (c, &constant) {
+            // The functions are never the same, and `1` must stay apart from `1.0`
+            (Constant::Number(a), Constant::Number(b)) => a.is_identical(b),
+            (Constant::String(a), Constant::String(b)) => a == b,
+            _ => false,
+        }) {
+            Some(position) => position,
+            None => {
+                constants.push(constant);
+                constants.len() - 1
+            }
+        };
+        index(position, token, "constants")
+    }
+
+    fn begin_scope(&mut self) {
+        self.state().depth += 1;
+    }
+
+    /// Remove the variables of the scope, the first slot is given back if a closure captured any
+    /// of them
+    fn end_scope(&mut self) -> Option<u16> {
+        let state = self.state();
+        state.depth -= 1;
+        let depth = state.depth;
+        let first = state.locals.iter().position(|local| local.depth > depth)?;
+        let captured = state.locals[first..].iter().any(|local| local.captured);
+        state.locals.truncate(first);
+        if captured {
+            self.emit(Op::Close(first as u16));
+            return Some(first as u16);
+        }
+        None
+    }
+
+    /// Close the slots of the variables declared deeper than the depth, for the jumps that leave
+    /// their scopes. A closure declared after the jump can still capture them, so they are closed
+    /// even if none is captured yet.
+    fn close_after(&mut self, depth: usize) {
+        let first = self
+            .state()
+            .locals
+            .iter()
+            .position(|local| local.depth > depth);
+        if let Some(first) = first {
+            self.emit(Op::Close(first as u16));
+        }
+    }
+
+    /// A new local slot in the current scope
+    fn add_local(&mut self, name: &str, constant: bool, token: &Token) -> Result<u16> {
+        let state = self.state();
+        state.locals.push(Local {
+            name: name.to_string(),
+            depth: state.depth,
+            constant,
+            captured: false,
+            ready: true,
+        });
+        state.slots = state.slots.max(state.locals.len());
+        index(state.locals.len() - 1, token, "local variables")
+    }
+
+    /// A slot that only the compiler uses, the scripts can not see it
+    fn hidden(&mut self, token: &Token) -> Result<u16> {
+        self.add_local("", false, token)
+    }
+
+    /// The slot of a variable declared with `set`, `const` or `func` in the current scope, the same
+    /// one if it was already declared in it
+    fn declare(&mut self, name: &Token, constant: bool) -> Result<u16> {
+        let state = self.state();
+        let depth = state.depth;
+        let existing = state
+            .locals
+            .iter()
+            .rposition(|local| local.depth == depth && local.name == name.lexeme);
+        match existing {
+            Some(slot) if state.locals[slot].constant => Err(Error::Runtime(
+                name.span,
+                format!("the constant '{}' can not be declared again", name.lexeme),
+                name.lexeme.clone(),
+            )),
+            Some(slot) => {
+                state.locals[slot].constant = constant;
+                Ok(slot as u16)
+            }
+            None => self.add_local(&name.lexeme, constant, name),
+        }
+    }
+
+    /// Where the variable with the name is, the upvalues are added to the functions between the
+    /// current one and the one that declares it
+    fn resolve(&mut self, name: &Token) -> Result<(Variable, bool)> {
+        let level = self.states.len() - 1;
+        if let Some((slot, constant)) = self.states[level].resolve(&name.lexeme) {
+            return Ok((Variable::Local(slot), constant));
+        }
+        match self.resolve_upvalue(level, name)? {
+            Some((index, constant)) => Ok((Variable::Upvalue(index), constant)),
+            None => Ok((Variable::Global, false)),
+        }
+    }
+
+    fn resolve_upvalue(&mut self, level: usize, name: &Token) -> Result<Option<(u16, bool)>> {
+        if level == 0 {
+            return Ok(None);
+        }
+        let capture = match self.states[level - 1].resolve(&name.lexeme) {
+            Some((slot, constant)) => {
+                self.states[level - 1].locals[slot as usize].captured = true;
+                (
+                    Capture {
+                        local: true,
+                        index: slot,
+                    },
+                    constant,
+                )
+            }
+            None => match self.resolve_upvalue(level - 1, name)? {
+                Some((index, constant)) => (
+                    Capture {
+                        local: false,
+                        index,
+                    },
+                    constant,
+                ),
+                None => return Ok(None),
+            },
+        };
+
+        let captures = &mut self.states[level].captures;
+        let position = match captures.iter().position(|c| c.0 == capture.0) {
+            Some(position) => position,
+            None => {
+                captures.push(capture);
+                captures.len() - 1
+            }
+        };
+        Ok(Some((
+            index(position, name, "captured variables")?,
+            capture.1,
+        )))
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<()> {
+        let line = self.line;
+        self.line = statement_line(stmt).unwrap_or(line);
+        let result = self.compile_statement(stmt);
+        self.line = line;
+        result
+    }
+
+    fn compile_statement(&mut self, stmt: &Stmt) -> Result<()> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.expression(expr)?;
+                self.emit(Op::Pop);
+            }
+            Stmt::Set(name, _, value) => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => {
+                        self.emit(Op::Null);
+                    }
+                }
+                self.define(name, false)?;
+            }
+            Stmt::Const(name, _, value) => {
+                self.expression(value)?;
+                self.define(name, true)?;
+            }
+            Stmt::Assign(target, operator, value) => {
+                if operator.kind != TokenKind::Equal {
+                    self.expression(target)?;
+                    self.expression(value)?;
+                    let operator = self.token(operator)?;
+                    self.emit(Op::Binary(operator));
+                } else {
+                    self.expression(value)?;
+                }
+                self.assign(target)?;
+            }
+            Stmt::Increment(target, operator) => {
+                self.expression(target)?;
+                let one = self.constant(Constant::Number(Number::Integer(1)), operator)?;
+                self.emit(Op::Constant(one));
+                let operator = self.token(operator)?;
+                self.emit(Op::Binary(operator));
+                self.assign(target)?;
+            }
+            Stmt::If(if_body) => self.if_statement(if_body)?,
+            Stmt::Match(match_body) => self.match_statement(match_body)?,
+            Stmt::While(condition, body) => {
+                self.begin_loop(condition.token())?;
+                let start = self.here();
+                self.expression(condition)?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.block(body)?;
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.end_loop();
+            }
+            Stmt::DoWhile(body, condition) => {
+                self.begin_loop(condition.token())?;
+                let start = self.here();
+                self.block(body)?;
+                self.expression(condition)?;
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.end_loop();
+            }
+            Stmt::ForEach(name, iterable, body) => {
+                self.begin_loop(iterable.token())?;
+                self.expression(iterable)?;
+                let token = self.token(iterable.token())?;
+                self.emit(Op::Iterate(token));
+                let items = self.hidden(name)?;
+                let position = self.hidden(name)?;
+                self.emit(Op::SetLocal(items));
+                let zero = self.constant(Constant::Number(Number::Integer(0)), name)?;
+                self.emit(Op::Constant(zero));
+                self.emit(Op::SetLocal(position));
+
+                let start = self.here();
+                let exit = self.emit(Op::ForEach(items, 0));
+                // Every round has its own variable, the closures keep the value of their round
+                self.begin_scope();
+                let slot = self.add_local(&name.lexeme, false, name)?;
+                self.emit(Op::SetLocal(slot));
+                self.statements(body)?;
+                self.end_scope();
+                self.emit(Op::Jump(start));
+                self.patch(exit);
+                self.end_loop();
+            }
+            Stmt::Func(declaration) => {
+                if self.state().depth == 0 {
+                    self.function(declaration)?;
+                    let name = self.token(&declaration.name)?;
+                    self.emit(Op::DefineGlobal(name));
+                } else {
+                    // The slot was declared with the block, so it is the same one
+                    let slot = self.declare(&declaration.name, false)?;
+                    self.function(declaration)?;
+                    self.emit(Op::SetLocal(slot));
+                }
+            }
+            Stmt::Class(class) => self.class(class)?,
+            Stmt::Interface(interface) => {
+                let mut fields = Vec::with_capacity(interface.fields.len());
+                for field in &interface.fields {
+                    let ast::TypeExpr::Name(ref type_name) = field.type_expr;
+                    // The types of the language do not need a value, the rest are variables
+                    match Type::builtin(&type_name.lexeme) {
+                        Some(_) => {
+                            self.emit(Op::Null);
+                        }
+                        None => match self.resolve(type_name)?.0 {
+                            Variable::Global => {
+                                let name = self.token(type_name)?;
+                                self.emit(Op::TryGlobal(name));
+                            }
+                            _ => self.variable(type_name)?,
+                        },
+                    }
+                    fields.push(FieldSite {
+                        name: self.token(&field.name)?,
+                        field_type: self.token(type_name)?,
+                        required: field.required,
+                    });
+                }
+                let name = self.token(&interface.name)?;
+                let interfaces = &mut self.state().chunk.interfaces;
+                interfaces.push(InterfaceSite { name, fields });
+                let site = index(interfaces.len() - 1, &interface.name, "interfaces")?;
+                self.emit(Op::Interface(site));
+                self.define(&interface.name, false)?;
+            }
+            Stmt::Return(_, value) => {
+                match value {
+                    Some(value) => self.expression(value)?,
+                    None => {
+                        self.emit(Op::Null);
+                    }
+                }
+                self.return_value()?;
+            }
+            Stmt::Break(keyword) => {
+                let (mark, depth) = match self.state().loops.last() {
+                    Some(current) => (current.mark, current.depth),
+                    None => {
+                        return Err(Error::Runtime(
+                            keyword.span,
+                            "'break' outside of a loop".to_string(),
+                            keyword.lexeme.clone(),
+                        ))
+                    }
+                };
+                self.emit(Op::Unwind(mark));
+                self.close_after(depth);
+                let jump = self.emit(Op::Jump(0));
+                self.state().loops.last_mut().unwrap().breaks.push(jump);
+            }
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> Result<()> {
+        // The functions and classes of a block have their slots before any of them is compiled,
+        // so they can use the ones declared after them, like the interpreter does
+        if self.state().depth > 0 {
+            for statement in statements {
+                let name = match statement {
+                    Stmt::Func(declaration) => &declaration.name,
+                    Stmt::Class(class) => &class.name,
+                    _ => continue,
+                };
+                let slot = self.declare(name, false)?;
+                self.emit(Op::Null);
+                self.emit(Op::SetLocal(slot));
+            }
+        }
+        for statement in statements {
+            self.statement(statement)?;
+        }
+        Ok(())
+    }
+
+    /// Compile the statements in a scope of their own
+    fn block(&mut self, statements: &[Stmt]) -> Result<()> {
+        self.begin_scope();
+        self.statements(statements)?;
+        self.end_scope();
+        Ok(())
+    }
+
+    /// Compile a class, its methods capture `super` from a scope of their own, the superclass or
+    /// `null` if it does not extend one
+    fn class(&mut self, class: &ast::Class) -> Result<()> {
+        self.begin_scope();
+        let mut inherits = Vec::with_capacity(class.inherits.len());
+        for name in &class.inherits {
+            self.variable(name)?;
+            inherits.push(self.token(name)?);
+        }
+        let name = self.token(&class.name)?;
+        let methods = index(class.methods.len(), &class.name, "methods")?;
+        let classes = &mut self.state().chunk.classes;
+        classes.push(ClassSite {
+            name,
+            inherits,
+            methods,
+        });
+        let site = index(classes.len() - 1, &class.name, "classes")?;
+
+        let superclass = self.add_local("super", false, &class.name)?;
+        self.emit(match class.inherits.is_empty() {
+            true => Op::Null,
+            false => Op::Superclass(site),
+        });
+        self.emit(Op::SetLocal(superclass));
+        for method in &class.methods {
+            self.function(method)?;
+        }
+        self.emit(Op::Class(site));
+        self.end_scope();
+        self.define(&class.name, false)
+    }
+
+    /// Push the value of the variable with the name
+    fn variable(&mut self, name: &Token) -> Result<()> {
+        let op = match self.resolve(name)?.0 {
+            Variable::Local(slot) => Op::GetLocal(slot),
+            Variable::Upvalue(index) => Op::GetUpvalue(index),
+            Variable::Global => Op::GetGlobal(self.token(name)?),
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    /// Store the value on the top in a new variable, a global one outside of the blocks
+    fn define(&mut self, name: &Token, constant: bool) -> Result<()> {
+        if self.state().depth == 0 {
+            let token = self.token(name)?;
+            self.emit(if constant {
+                Op::DefineConst(token)
+            } else {
+                Op::DefineGlobal(token)
+            });
+        } else {
+            let slot = self.declare(name, constant)?;
+            self.emit(Op::SetLocal(slot));
+        }
+        Ok(())
+    }
+
+    /// Store the value on the top in the target of an assignment
+    fn assign(&mut self, target: &Expr) -> Result<()> {
+        match target {
+            Expr::Variable(name, _) => match self.resolve(name)? {
+                (_, true) => {
+                    return Err(Error::Runtime(
+                        name.span,
+                        format!("can not change the constant '{}'", name.lexeme),
+                        name.lexeme.clone(),
+                    ))
+                }
+                (Variable::Local(slot), _) => {
+                    self.emit(Op::SetLocal(slot));
+                }
+                (Variable::Upvalue(index), _) => {
+                    self.emit(Op::SetUpvalue(index));
+                }
+                (Variable::Global, _) => {
+                    let name = self.token(name)?;
+                    self.emit(Op::SetGlobal(name));
+                }
+            },
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                let name = self.token(name)?;
+                self.emit(Op::SetAttribute(name));
+            }
+            Expr::Index(object, bracket, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                let bracket = self.token(bracket)?;
+                self.emit(Op::SetIndex(bracket));
+            }
+            _ => {
+                return Err(Error::Runtime(
+                    target.token().span,
+                    "invalid assignment target".to_string(),
+                    target.token().lexeme.clone(),
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    /// Give back the value on the top, from the `if` or `match` used as a value that contains the
+    /// `return`, or else from the function
+    fn return_value(&mut self) -> Result<()> {
+        let (result, mark, depth) = match self.state().values.last() {
+            Some(value) => (value.result, value.mark, value.depth),
+            None => {
+                self.emit(Op::Return);
+                return Ok(());
+            }
+        };
+        self.emit(Op::SetLocal(result));
+        self.emit(Op::Unwind(mark));
+        self.close_after(depth);
+        let jump = self.emit(Op::Jump(0));
+        self.state().values.last_mut().unwrap().exits.push(jump);
+        Ok(())
+    }
+
+    /// Start a loop in a scope for its hidden slots
+    fn begin_loop(&mut self, token: &Token) -> Result<()> {
+        self.begin_scope();
+        let mark = self.hidden(token)?;
+        self.emit(Op::Mark(mark));
+        let depth = self.state().depth;
+        self.state().loops.push(Loop {
+            mark,
+            depth,
+            breaks: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn end_loop(&mut self) {
+        let current = self.state().loops.pop().unwrap();
+        self.patch_all(current.breaks);
+        self.end_scope();
+    }
+
+    fn if_statement(&mut self, if_body: &If) -> Result<()> {
+        let mut ends = Vec::new();
+        for (condition, body) in &if_body.branches {
+            self.expression(condition)?;
+            let next = self.emit(Op::JumpIfFalse(0));
+            self.block(body)?;
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(next);
+        }
+        if let Some(ref body) = if_body.otherwise {
+            self.block(body)?;
+        }
+        self.patch_all(ends);
+        Ok(())
+    }
+
+    /// Try the arms in order, the subject is kept in a hidden slot
+    fn match_statement(&mut self, match_body: &Match) -> Result<()> {
+        self.begin_scope();
+        self.expression(&match_body.subject)?;
+        let subject = self.hidden(&match_body.keyword)?;
+        self.emit(Op::SetLocal(subject));
+
+        let mut ends = Vec::new();
+        for arm in &match_body.arms {
+            let mut fails = Vec::new();
+            self.begin_scope();
+            self.arm(arm, subject, &mut fails)?;
+            let closed = self.end_scope();
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch_all(fails);
+            if let Some(first) = closed {
+                self.emit(Op::Close(first));
+            }
+        }
+        self.patch_all(ends);
+        self.end_scope();
+        Ok(())
+    }
+
+    fn arm(&mut self, arm: &Arm, subject: u16, fails: &mut Vec<usize>) -> Result<()> {
+        self.pattern(&arm.pattern, subject, fails)?;
+        if let Some(ref guard) = arm.guard {
+            self.expression(guard)?;
+            fails.push(self.emit(Op::JumpIfFalse(0)));
+        }
+        self.statements(&arm.body)
+    }
+
+    /// Check that the value in the slot matches the pattern and store its variables, the jumps
+    /// that are taken when it does not match are added to `fails`
+    fn pattern(&mut self, pattern: &Pattern, slot: u16, fails: &mut Vec<usize>) -> Result<()> {
+        match pattern {
+            Pattern::Wildcard(_) => (),
+            Pattern::Binding(name) => {
+                self.emit(Op::GetLocal(slot));
+                let binding = self.declare(name, false)?;
+                self.emit(Op::SetLocal(binding));
+            }
+            Pattern::Literal(token) => {
+                self.emit(Op::GetLocal(slot));
+                self.literal(token)?;
+                let equal = Token {
+                    kind: TokenKind::EqEqual,
+                    lexeme: "==".to_string(),
+                    literal: None,
+                    span: token.span,
+                };
+                let equal = self.token(&equal)?;
+                self.emit(Op::Binary(equal));
+                fails.push(self.emit(Op::JumpIfFalse(0)));
+            }
+            Pattern::Range(low, _, high) => {
+                let number = |token: &Token| match token.literal {
+                    Some(Literal::Number(ref n)) => n.clone(),
+                    _ => Number::Float(f64::NAN),
+                };
+                let low_constant = self.constant(Constant::Number(number(low)), low)?;
+                let high_constant = self.constant(Constant::Number(number(high)), high)?;
+                self.emit(Op::GetLocal(slot));
+                self.emit(Op::MatchRange(low_constant, high_constant));
+                fails.push(self.emit(Op::JumpIfFalse(0)));
+            }
+            Pattern::Array(token, patterns) | Pattern::Tuple(token, patterns) => {
+                let len = index(patterns.len(), token, "elements")?;
+                self.emit(Op::GetLocal(slot));
+                self.emit(match pattern {
+                    Pattern::Array(..) => Op::MatchArray(len),
+                    _ => Op::MatchTuple(len),
+                });
+                fails.push(self.emit(Op::JumpIfFalse(0)));
+                for (position, pattern) in patterns.iter().enumerate() {
+                    if let Pattern::Wildcard(_) = pattern {
+                        continue;
+                    }
+                    self.emit(Op::GetLocal(slot));
+                    self.emit(Op::Element(position as u16));
+                    let element = self.hidden(token)?;
+                    self.emit(Op::SetLocal(element));
+                    self.pattern(pattern, element, fails)?;
+                }
+            }
+            Pattern::Instance(name, fields) => {
+                self.emit(Op::GetLocal(slot));
+                self.variable(name)?;
+                let class = self.token(name)?;
+                self.emit(Op::MatchInstance(class));
+                fails.push(self.emit(Op::JumpIfFalse(0)));
+                for (field, pattern) in fields {
+                    let field = self.token(field)?;
+                    self.emit(Op::GetLocal(slot));
+                    self.emit(Op::HasField(field));
+                    fails.push(self.emit(Op::JumpIfFalse(0)));
+                    if let Pattern::Wildcard(_) = pattern {
+                        continue;
+                    }
+                    self.emit(Op::GetLocal(slot));
+                    self.emit(Op::GetAttribute(field));
+                    let value = self.hidden(name)?;
+                    self.emit(Op::SetLocal(value));
+                    self.pattern(pattern, value, fails)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile an `if` or `match` used as a value, the `return`s inside of it give the value
+    fn value(&mut self, keyword: &Token, body: impl FnOnce(&mut Self) -> Result<()>) -> Result<()> {
+        self.begin_scope();
+        let result = self.hidden(keyword)?;
+        let mark = self.hidden(keyword)?;
+        self.emit(Op::Mark(mark));
+        let depth = self.state().depth;
+        self.state().values.push(Value {
+            result,
+            mark,
+            depth,
+            exits: Vec::new(),
+        });
+
+        body(self)?;
+        // Without a `return` the value is `null`
+        self.emit(Op::Null);
+        self.emit(Op::SetLocal(result));
+
+        let value = self.state().values.pop().unwrap();
+        self.patch_all(value.exits);
+        self.end_scope();
+        self.emit(Op::GetLocal(result));
+        Ok(())
+    }
+
+    fn literal(&mut self, token: &Token) -> Result<()> {
+        let op = match token.literal {
+            None | Some(Literal::Null) => Op::Null,
+            Some(Literal::Boolean(true)) => Op::True,
+            Some(Literal::Boolean(false)) => Op::False,
+            Some(Literal::Number(ref n)) => {
+                Op::Constant(self.constant(Constant::Number(n.clone()), token)?)
+            }
+            Some(Literal::String(ref s)) => {
+                Op::Constant(self.constant(Constant::String(s.clone()), token)?)
+            }
+        };
+        self.emit(op);
+        Ok(())
+    }
+
+    /// Compile a function and push a closure of it
+    fn function(&mut self, declaration: &Function) -> Result<()> {
+        let mut state = State::new(declaration.name().map(str::to_string));
+        state.depth = 1;
+        self.states.push(state);
+
+        for param in &declaration.params {
+            self.state().params.push(Parameter {
+                name: param.name.lexeme.clone(),
+                default: param.default.is_some(),
+            });
+            self.add_local(&param.name.lexeme, false, &param.name)?;
+        }
+        // The default values only see the parameters before them
+        for (position, param) in declaration.params.iter().enumerate() {
+            if let Some(ref default) = param.default {
+                for local in &mut self.state().locals[position..] {
+                    local.ready = false;
+                }
+                let given = self.emit(Op::IfGiven(position as u16, 0));
+                self.expression(default)?;
+                self.emit(Op::SetLocal(position as u16));
+                self.patch(given);
+                for local in &mut self.state().locals[position..] {
+                    local.ready = true;
+                }
+            }
+        }
+        self.statements(&declaration.body)?;
+        self.emit(Op::Null);
+        self.emit(Op::Return);
+
+        let prototype = self.finish();
+        let function = self.constant(Constant::Function(Rc::new(prototype)), &declaration.name)?;
+        self.emit(Op::Closure(function));
+        Ok(())
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<()> {
+        let line = self.line;
+        self.line = expr.token().span.line as u32;
+        let result = self.compile_expression(expr);
+        self.line = line;
+        result
+    }
+
+    fn compile_expression(&mut self, expr: &Expr) -> Result<()> {
+        match expr {
+            Expr::Literal(token) => self.literal(token)?,
+            Expr::Variable(name, _) => self.variable(name)?,
+            Expr::Grouping(expr) => self.expression(expr)?,
+            Expr::Unary(operator, right) => {
+                self.expression(right)?;
+                let operator = self.token(operator)?;
+                self.emit(Op::Unary(operator));
+            }
+            Expr::Binary(left, operator, right) => {
+                self.expression(left)?;
+                self.expression(right)?;
+                let operator = self.token(operator)?;
+                self.emit(Op::Binary(operator));
+            }
+            Expr::Logical(left, operator, right) => {
+                self.expression(left)?;
+                let jump = match operator.lexeme.as_ref() {
+                    "or" => self.emit(Op::JumpIfTrueOrPop(0)),
+                    _ => self.emit(Op::JumpIfFalseOrPop(0)),
+                };
+                self.expression(right)?;
+                self.patch(jump);
+            }
+            Expr::Call(callee, paren, arguments) => {
+                self.expression(callee)?;
+                let mut names = Vec::with_capacity(arguments.len());
+                for argument in arguments {
+                    self.expression(&argument.value)?;
+                    names.push(match argument.name {
+                        Some(ref name) => Some(self.token(name)?),
+                        None => None,
+                    });
+                }
+                let paren = self.token(paren)?;
+                let calls = &mut self.state().chunk.calls;
+                calls.push(CallSite { paren, names });
+                let call = index(calls.len() - 1, expr.token(), "calls")?;
+                self.emit(Op::Call(call));
+            }
+            Expr::Function(declaration) => self.function(declaration)?,
+            Expr::Get(object, name) => {
+                self.expression(object)?;
+                let name = self.token(name)?;
+                self.emit(Op::GetAttribute(name));
+            }
+            Expr::Super(keyword, name) => {
+                // Outside of a class there is no `super`, the VM gives the same error as in a
+                // class that does not extend another one
+                match self.resolve(keyword)?.0 {
+                    Variable::Global => {
+                        self.emit(Op::Null);
+                    }
+                    _ => self.variable(keyword)?,
+                }
+                let receiver = Token {
+                    kind: TokenKind::Ident,
+                    lexeme: "self".to_string(),
+                    ..keyword.clone()
+                };
+                self.variable(&receiver)?;
+                let keyword = self.token(keyword)?;
+                let name = self.token(name)?;
+                self.emit(Op::Super(keyword, name));
+            }
+            Expr::Index(object, bracket, index) => {
+                self.expression(object)?;
+                self.expression(index)?;
+                let bracket = self.token(bracket)?;
+                self.emit(Op::Index(bracket));
+            }
+            Expr::Array(token, elements) | Expr::Tuple(token, elements) => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+                let len = index(elements.len(), token, "elements")?;
+                self.emit(match expr {
+                    Expr::Array(..) => Op::Array(len),
+                    _ => Op::Tuple(len),
+                });
+            }
+            Expr::Map(token, entries) => {
+                for (key, value) in entries {
+                    self.expression(key)?;
+                    let key_token = self.token(key.token())?;
+                    self.emit(Op::Key(key_token));
+                    self.expression(value)?;
+                }
+                let len = index(entries.len(), token, "elements")?;
+                self.emit(Op::Map(len));
+            }
+            Expr::If(if_body) => {
+                self.value(&if_body.keyword, |compiler| compiler.if_statement(if_body))?
+            }
+            Expr::Match(match_body) => self.value(&match_body.keyword, |compiler| {
+                compiler.match_statement(match_body)
+            })?,
+        }
+        Ok(())
+    }
+}
+
+/// The line where a statement starts, the blocks only have the lines of their statements
+fn statement_line(stmt: &Stmt) -> Option<u32> {
+    let token = match stmt {
+        Stmt::Expression(expr) | Stmt::Assign(expr, _, _) | Stmt::While(expr, _) => expr.token(),
+        Stmt::Set(name, _, _) | Stmt::Const(name, _, _) | Stmt::ForEach(name, _, _) => name,
+        Stmt::Increment(_, operator) => operator,
+        Stmt::If(if_body) => &if_body.keyword,
+        Stmt::Match(match_body) => &match_body.keyword,
+        Stmt::DoWhile(_, condition) => condition.token(),
+        Stmt::Func(declaration) => &declaration.name,
+        Stmt::Class(class) => &class.name,
+        Stmt::Interface(interface) => &interface.name,
+        Stmt::Return(keyword, _) | Stmt::Break(keyword) => keyword,
+    };
+    match token.span.line {
+        0 => None,
+        line => Some(line as u32),
+    }
+}
+
+/// The position in a pool as an operand, the pools of a function have a limit
+fn index(position: usize, token: &Token, what: &str) -> Result<u16> {
+    u16::try_from(position).map_err(|_| {
+        Error::Runtime(
+            token.span,
+            format!("a function can not have more than {} {}", u16::MAX, what),
+            token.lexeme.clone(),
+        )
+    })
+}
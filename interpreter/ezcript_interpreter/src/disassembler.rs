@@ -0,0 +1,161 @@
+use std::fmt::Write;
+
+use super::chunk::{Chunk, Constant, Op, Prototype};
+
+/// A listing of the bytecode of a compiled script, one instruction per line with its position,
+/// its line in the source code and what its operands point to. The functions of the script are
+/// listed after it.
+pub fn disassemble(script: &Prototype) -> String {
+    let mut out = String::new();
+    function(&mut out, "<script>", script);
+    out
+}
+
+fn function(out: &mut String, title: &str, prototype: &Prototype) {
+    writeln!(out, "== {} ==", title).unwrap();
+    let decimals = match prototype.decimals {
+        true => ", decimals",
+        false => "",
+    };
+    writeln!(
+        out,
+        "slots: {}, captures: {}{}",
+        prototype.slots,
+        prototype.captures.len(),
+        decimals
+    )
+    .unwrap();
+
+    let chunk = &prototype.chunk;
+    for (position, op) in chunk.code.iter().enumerate() {
+        let line = chunk.lines.get(position).copied().unwrap_or(0);
+        let same_line = position > 0 && chunk.lines.get(position - 1) == Some(&line);
+        let line = match same_line {
+            true => "|".to_string(),
+            false => line.to_string(),
+        };
+        let name = format!("{:?}", op);
+        let name = name.split('(').next().unwrap();
+        let operands = operands(chunk, *op);
+        let text = format!("{:04} {:>4} {:<16} {}", position, line, name, operands);
+        writeln!(out, "{}", text.trim_end()).unwrap();
+    }
+
+    for constant in &chunk.constants {
+        if let Constant::Function(function_prototype) = constant {
+            let params: Vec<String> = function_prototype
+                .params
+                .iter()
+                .map(|param| match param.default {
+                    true => format!("{}=", param.name),
+                    false => param.name.clone(),
+                })
+                .collect();
+            let name = function_prototype.name.as_deref().unwrap_or("<func>");
+            writeln!(out).unwrap();
+            function(
+                out,
+                &format!("{}({})", name, params.join(", ")),
+                function_prototype,
+            );
+        }
+    }
+}
+
+/// The operands of the instruction, with what they point to
+fn operands(chunk: &Chunk, op: Op) -> String {
+    let token = |index: u16| format!("{:<4} '{}'", index, chunk.tokens[index as usize].lexeme);
+    let constant = |index: u16| {
+        let value = match chunk.constants[index as usize] {
+            Constant::Number(ref n) => n.to_string(),
+            Constant::String(ref s) => format!("{:?}", s),
+            Constant::Function(ref prototype) => match prototype.name {
+                Some(ref name) => format!("<func {}>", name),
+                None => "<func>".to_string(),
+            },
+        };
+        format!("{:<4} {}", index, value)
+    };
+
+    match op {
+        Op::Null | Op::True | Op::False | Op::Pop | Op::Return => String::new(),
+        Op::Constant(index) | Op::Closure(index) => constant(index),
+        Op::GetLocal(index)
+        | Op::SetLocal(index)
+        | Op::GetUpvalue(index)
+        | Op::SetUpvalue(index)
+        | Op::Close(index)
+        | Op::Array(index)
+        | Op::Tuple(index)
+        | Op::Map(index)
+        | Op::Mark(index)
+        | Op::Unwind(index)
+        | Op::MatchArray(index)
+        | Op::MatchTuple(index)
+        | Op::Element(index) => index.to_string(),
+        Op::GetGlobal(index)
+        | Op::SetGlobal(index)
+        | Op::DefineGlobal(index)
+        | Op::DefineConst(index)
+        | Op::Unary(index)
+        | Op::Binary(index)
+        | Op::Key(index)
+        | Op::Index(index)
+        | Op::SetIndex(index)
+        | Op::GetAttribute(index)
+        | Op::SetAttribute(index)
+        | Op::Iterate(index)
+        | Op::TryGlobal(index)
+        | Op::MatchInstance(index)
+        | Op::HasField(index) => token(index),
+        Op::Super(_, name) => token(name),
+        Op::Superclass(index) | Op::Class(index) => {
+            let class = &chunk.classes[index as usize];
+            let inherits: Vec<&str> = class
+                .inherits
+                .iter()
+                .map(|name| chunk.tokens[*name as usize].lexeme.as_str())
+                .collect();
+            let name = &chunk.tokens[class.name as usize].lexeme;
+            match inherits.is_empty() {
+                true => format!("{:<4} '{}'", index, name),
+                false => format!("{:<4} '{}' inherits {}", index, name, inherits.join(", ")),
+            }
+        }
+        Op::Interface(index) => {
+            let interface = &chunk.interfaces[index as usize];
+            format!(
+                "{:<4} '{}'",
+                index, chunk.tokens[interface.name as usize].lexeme
+            )
+        }
+        Op::Jump(to) | Op::JumpIfFalse(to) | Op::JumpIfFalseOrPop(to) | Op::JumpIfTrueOrPop(to) => {
+            format!("-> {:04}", to)
+        }
+        Op::ForEach(index, to) | Op::IfGiven(index, to) => format!("{:<4} -> {:04}", index, to),
+        Op::MatchRange(low, high) => {
+            let number = |index: u16| match chunk.constants[index as usize] {
+                Constant::Number(ref n) => n.to_string(),
+                _ => "?".to_string(),
+            };
+            format!("{}..{}", number(low), number(high))
+        }
+        Op::Call(index) => {
+            let call = &chunk.calls[index as usize];
+            let keywords: Vec<&str> = call
+                .names
+                .iter()
+                .flatten()
+                .map(|name| chunk.tokens[*name as usize].lexeme.as_str())
+                .collect();
+            let arguments = match call.names.len() {
+                1 => "1 argument".to_string(),
+                len => format!("{} arguments", len),
+            };
+            match keywords.is_empty() {
+                true => arguments,
+                false => format!("{}, keywords: {}", arguments, keywords.join(", ")),
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use super::gc;
+use super::value::Value;
+use ezcript_lexer::tokens::Token;
+use ezcript_parser::ast::Slot;
+use ezcript_result::{suggest, Error, Result};
+
+/// The variables of a scope and a reference to the scope that contains it. The values are kept in
+/// the order in which they were declared, so the resolver can point at them by position.
+#[derive(Debug, Default)]
+pub struct Environment {
+    values: Vec<Value>,
+    /// The position of every variable in `values`
+    slots: HashMap<String, usize>,
+    /// The positions of the variables declared with `const`
+    constants: HashSet<usize>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new(enclosing: Option<Rc<RefCell<Environment>>>) -> Self {
+        Environment {
+            values: Vec::new(),
+            slots: HashMap::new(),
+            constants: HashSet::new(),
+            enclosing,
+        }
+    }
+
+    /// A new scope that the garbage collector knows about, the way that the interpreter creates
+    /// them
+    pub fn shared(enclosing: Option<Rc<RefCell<Environment>>>) -> Rc<RefCell<Environment>> {
+        gc::allocate(RefCell::new(Environment::new(enclosing)))
+    }
+
+    /// The values of the scope and the scope that contains it, for the garbage collector
+    pub(crate) fn references(&self) -> (&[Value], Option<&Rc<RefCell<Environment>>>) {
+        (&self.values, self.enclosing.as_ref())
+    }
+
+    /// Create a variable in this scope, or replace the value of the one with the same name
+    pub fn define(&mut self, name: &str, value: Value) {
+        let slot = self.slot(name, value);
+        self.constants.remove(&slot);
+    }
+
+    /// Create a variable in this scope that can not change
+    pub fn define_const(&mut self, name: &str, value: Value) {
+        let slot = self.slot(name, value);
+        self.constants.insert(slot);
+    }
+
+    /// Save the value in the position of the variable, a new variable goes after the others
+    fn slot(&mut self, name: &str, value: Value) -> usize {
+        match self.slots.get(name) {
+            Some(&slot) => {
+                self.values[slot] = value;
+                slot
+            }
+            None => {
+                self.slots.insert(name.to_string(), self.values.len());
+                self.values.push(value);
+                self.values.len() - 1
+            }
+        }
+    }
+
+    /// True if the variable of this scope with the name was declared with `const`
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.slots
+            .get(name)
+            .is_some_and(|slot| self.constants.contains(slot))
+    }
+
+    /// Look for the variable in this scope and then in the ones that contain it
+    pub fn get(&self, name: &Token) -> Result<Value> {
+        self.lookup(&name.lexeme)
+            .ok_or_else(|| self.undefined(name))
+    }
+
+    fn lookup(&self, name: &str) -> Option<Value> {
+        match self.slots.get(name) {
+            Some(&slot) => Some(self.values[slot].clone()),
+            None => self.enclosing.as_ref()?.borrow().lookup(name),
+        }
+    }
+
+    /// The variable in the position that the resolver found, `depth` scopes above this one
+    pub fn get_at(&self, slot: Slot, name: &Token) -> Result<Value> {
+        if slot.depth > 0 {
+            return match self.enclosing {
+                Some(ref enclosing) => enclosing.borrow().get_at(slot.up(), name),
+                None => Err(self.undefined(name)),
+            };
+        }
+        match self.values.get(slot.index) {
+            Some(value) => Ok(value.clone()),
+            None => Err(self.undefined(name)),
+        }
+    }
+
+    /// Change the variable in the position that the resolver found
+    pub fn assign_at(&mut self, slot: Slot, name: &Token, value: Value) -> Result<()> {
+        if slot.depth > 0 {
+            return match self.enclosing {
+                Some(ref enclosing) => enclosing.borrow_mut().assign_at(slot.up(), name, value),
+                None => Err(self.undefined(name)),
+            };
+        }
+        if self.constants.contains(&slot.index) {
+            return Err(constant(name));
+        }
+        match self.values.get_mut(slot.index) {
+            Some(current) => {
+                *current = value;
+                Ok(())
+            }
+            None => Err(self.undefined(name)),
+        }
+    }
+
+    /// The variables of this scope sorted by name, without the ones of the scopes that contain it
+    pub fn bindings(&self) -> Vec<(&str, &Value)> {
+        let mut bindings: Vec<(&str, &Value)> = self
+            .slots
+            .iter()
+            .map(|(name, &slot)| (name.as_str(), &self.values[slot]))
+            .collect();
+        bindings.sort_by_key(|&(name, _)| name);
+        bindings
+    }
+
+    /// Change the value of a variable that already exists, the constants can not change
+    pub fn assign(&mut self, name: &Token, value: Value) -> Result<()> {
+        match self.assign_existing(name, value) {
+            Some(result) => result,
+            None => Err(self.undefined(name)),
+        }
+    }
+
+    /// `None` if there is no variable with the name in this scope or the ones that contain it
+    fn assign_existing(&mut self, name: &Token, value: Value) -> Option<Result<()>> {
+        if let Some(&slot) = self.slots.get(&name.lexeme) {
+            if self.constants.contains(&slot) {
+                return Some(Err(constant(name)));
+            }
+            self.values[slot] = value;
+            return Some(Ok(()));
+        }
+
+        self.enclosing
+            .as_ref()?
+            .borrow_mut()
+            .assign_existing(name, value)
+    }
+
+    /// The names of the variables of this scope and of the ones that contain it
+    fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.slots.keys().cloned().collect();
+        if let Some(ref enclosing) = self.enclosing {
+            names.extend(enclosing.borrow().names());
+        }
+        names
+    }
+
+    /// The error of a variable that does not exist, with the name that was probably meant
+    fn undefined(&self, name: &Token) -> Error {
+        let names = self.names();
+        let similar = suggest(&name.lexeme, names.iter().map(String::as_str));
+        Error::Undefined(name.span, name.lexeme.clone(), similar.map(str::to_string))
+    }
+}
+
+fn constant(name: &Token) -> Error {
+    Error::Runtime(
+        name.span,
+        format!("can not change the constant '{}'", name.lexeme),
+        name.lexeme.clone(),
+    )
+}
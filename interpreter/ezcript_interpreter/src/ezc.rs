@@ -0,0 +1,778 @@
+//! The `.ezc` files, a compiled script that the VM runs without the source code.
+//!
+//! The file starts with the magic bytes `EZC\0` and the version of the format, then comes the
+//! script as a function: its name, parameters, slots and captures, and its chunk with the
+//! constants pool, the tokens, the calls, the classes, the interfaces, the code and the line
+//! table. The numbers are little endian and the texts are UTF-8 after their length.
+
+use std::convert::TryFrom;
+use std::io;
+use std::rc::Rc;
+
+use super::chunk::{
+    CallSite, Capture, Chunk, ClassSite, Constant, FieldSite, InterfaceSite, Op, Parameter,
+    Prototype,
+};
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::{Token, TokenKind};
+use ezcript_result::{Error, Result, Span};
+
+/// The bytes at the start of every `.ezc` file
+pub const MAGIC: &[u8; 4] = b"EZC\0";
+/// The version of the format, files of other versions are not read
+pub const VERSION: u16 = 3;
+
+/// The kinds of the tokens, their position is the number in the file
+const KINDS: [TokenKind; 46] = [
+    TokenKind::Keyword,
+    TokenKind::Ident,
+    TokenKind::Number,
+    TokenKind::String,
+    TokenKind::Float,
+    TokenKind::Boolean,
+    TokenKind::Null,
+    TokenKind::Not,
+    TokenKind::Plus,
+    TokenKind::Minus,
+    TokenKind::Star,
+    TokenKind::Slash,
+    TokenKind::Indent,
+    TokenKind::Dedent,
+    TokenKind::LParen,
+    TokenKind::RParen,
+    TokenKind::LBracket,
+    TokenKind::RBracket,
+    TokenKind::LBrace,
+    TokenKind::RBrace,
+    TokenKind::Colon,
+    TokenKind::Comma,
+    TokenKind::Equal,
+    TokenKind::Less,
+    TokenKind::Greater,
+    TokenKind::Dot,
+    TokenKind::Percent,
+    TokenKind::Hashtag,
+    TokenKind::EqEqual,
+    TokenKind::BangEqual,
+    TokenKind::LessEqual,
+    TokenKind::GreaterEqual,
+    TokenKind::DoublePlus,
+    TokenKind::DoubleMinus,
+    TokenKind::DoubleStar,
+    TokenKind::DoubleSlash,
+    TokenKind::PlusEqual,
+    TokenKind::MinEqual,
+    TokenKind::StarEqual,
+    TokenKind::SlashEqual,
+    TokenKind::PercentEqual,
+    TokenKind::RArrow,
+    TokenKind::DoubleDot,
+    TokenKind::Comment,
+    TokenKind::Illegal,
+    TokenKind::Eof,
+];
+
+/// True if the bytes are a compiled script and not source code
+pub fn is_compiled(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// The bytes of the `.ezc` file of a compiled script
+pub fn encode(script: &Prototype) -> Vec<u8> {
+    let mut writer = Writer { bytes: Vec::new() };
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u16(VERSION);
+    writer.prototype(script);
+    writer.bytes
+}
+
+/// The compiled script of the bytes of a `.ezc` file
+pub fn decode(bytes: &[u8]) -> Result<Rc<Prototype>> {
+    if !is_compiled(bytes) {
+        return Err(invalid("it is not a compiled script"));
+    }
+    let mut reader = Reader {
+        bytes,
+        position: MAGIC.len(),
+    };
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(invalid(&format!(
+            "it was compiled with the version {} of the format, this ezcript reads the version {}",
+            version, VERSION
+        )));
+    }
+    let script = reader.prototype()?;
+    if reader.position != bytes.len() {
+        return Err(invalid("it has bytes after the script"));
+    }
+    Ok(Rc::new(script))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::IO(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("the compiled file can not be read, {}", reason),
+    ))
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, n: u8) {
+        self.bytes.push(n);
+    }
+
+    fn u16(&mut self, n: u16) {
+        self.bytes.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn u32(&mut self, n: u32) {
+        self.bytes.extend_from_slice(&n.to_le_bytes());
+    }
+
+    fn u64(&mut self, n: u64) {
+        self.bytes.extend_from_slice(&n.to_le_bytes());
+    }
+
+    /// The lengths of the lists, the pools of a function never have more than `u16::MAX` elements
+    /// but the code and the texts can
+    fn len(&mut self, len: usize) {
+        self.u32(len as u32);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.len(s.len());
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    fn prototype(&mut self, prototype: &Prototype) {
+        match prototype.name {
+            Some(ref name) => {
+                self.u8(1);
+                self.string(name);
+            }
+            None => self.u8(0),
+        }
+        self.len(prototype.params.len());
+        for param in &prototype.params {
+            self.string(&param.name);
+            self.u8(param.default as u8);
+        }
+        self.u16(prototype.slots);
+        self.u8(prototype.decimals as u8);
+        self.len(prototype.captures.len());
+        for capture in &prototype.captures {
+            self.u8(capture.local as u8);
+            self.u16(capture.index);
+        }
+        self.chunk(&prototype.chunk);
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.len(chunk.constants.len());
+        for constant in &chunk.constants {
+            match constant {
+                Constant::Number(Number::Float(n)) => {
+                    self.u8(0);
+                    self.u64(n.to_bits());
+                }
+                Constant::Number(Number::Integer(n)) => {
+                    self.u8(3);
+                    self.u64(*n as u64);
+                }
+                // The big integers and the decimals are kept as they are written
+                Constant::Number(n @ Number::Big(_)) => {
+                    self.u8(4);
+                    self.string(&n.to_string());
+                }
+                Constant::Number(n @ Number::Decimal(_)) => {
+                    self.u8(5);
+                    self.string(&n.to_string());
+                }
+                Constant::String(s) => {
+                    self.u8(1);
+                    self.string(s);
+                }
+                Constant::Function(prototype) => {
+                    self.u8(2);
+                    self.prototype(prototype);
+                }
+            }
+        }
+
+        self.len(chunk.tokens.len());
+        for token in &chunk.tokens {
+            let kind = KINDS.iter().position(|kind| *kind == token.kind).unwrap();
+            self.u8(kind as u8);
+            self.string(&token.lexeme);
+            self.u64(token.span.line);
+            self.u64(token.span.column);
+            self.u64(token.span.start as u64);
+            self.u64(token.span.end as u64);
+        }
+
+        self.len(chunk.calls.len());
+        for call in &chunk.calls {
+            self.u16(call.paren);
+            self.len(call.names.len());
+            for name in &call.names {
+                match *name {
+                    Some(name) => {
+                        self.u8(1);
+                        self.u16(name);
+                    }
+                    None => self.u8(0),
+                }
+            }
+        }
+
+        self.len(chunk.classes.len());
+        for class in &chunk.classes {
+            self.u16(class.name);
+            self.len(class.inherits.len());
+            for name in &class.inherits {
+                self.u16(*name);
+            }
+            self.u16(class.methods);
+        }
+
+        self.len(chunk.interfaces.len());
+        for interface in &chunk.interfaces {
+            self.u16(interface.name);
+            self.len(interface.fields.len());
+            for field in &interface.fields {
+                self.u16(field.name);
+                self.u16(field.field_type);
+                self.u8(field.required as u8);
+            }
+        }
+
+        self.len(chunk.code.len());
+        for op in &chunk.code {
+            self.op(*op);
+        }
+
+        // The line table keeps the runs of instructions of the same line
+        let mut runs: Vec<(u32, u32)> = Vec::new();
+        for line in &chunk.lines {
+            match runs.last_mut() {
+                Some((last, count)) if last == line => *count += 1,
+                _ => runs.push((*line, 1)),
+            }
+        }
+        self.len(runs.len());
+        for (line, count) in runs {
+            self.u32(line);
+            self.u32(count);
+        }
+    }
+
+    fn op(&mut self, op: Op) {
+        self.u8(opcode(op));
+        match op {
+            Op::Null | Op::True | Op::False | Op::Pop | Op::Return => (),
+            Op::Jump(to)
+            | Op::JumpIfFalse(to)
+            | Op::JumpIfFalseOrPop(to)
+            | Op::JumpIfTrueOrPop(to) => self.u32(to),
+            Op::ForEach(index, to) | Op::IfGiven(index, to) => {
+                self.u16(index);
+                self.u32(to);
+            }
+            Op::MatchRange(first, second) | Op::Super(first, second) => {
+                self.u16(first);
+                self.u16(second);
+            }
+            Op::Constant(index)
+            | Op::GetLocal(index)
+            | Op::SetLocal(index)
+            | Op::GetUpvalue(index)
+            | Op::SetUpvalue(index)
+            | Op::GetGlobal(index)
+            | Op::SetGlobal(index)
+            | Op::DefineGlobal(index)
+            | Op::DefineConst(index)
+            | Op::Close(index)
+            | Op::Unary(index)
+            | Op::Binary(index)
+            | Op::Call(index)
+            | Op::Closure(index)
+            | Op::Array(index)
+            | Op::Tuple(index)
+            | Op::Map(index)
+            | Op::Key(index)
+            | Op::Index(index)
+            | Op::SetIndex(index)
+            | Op::GetAttribute(index)
+            | Op::SetAttribute(index)
+            | Op::Iterate(index)
+            | Op::Mark(index)
+            | Op::Unwind(index)
+            | Op::MatchArray(index)
+            | Op::MatchTuple(index)
+            | Op::Element(index)
+            | Op::Superclass(index)
+            | Op::Class(index)
+            | Op::Interface(index)
+            | Op::TryGlobal(index)
+            | Op::MatchInstance(index)
+            | Op::HasField(index) => self.u16(index),
+        }
+    }
+}
+
+/// The number of the instruction in the file
+fn opcode(op: Op) -> u8 {
+    match op {
+        Op::Constant(_) => 0,
+        Op::Null => 1,
+        Op::True => 2,
+        Op::False => 3,
+        Op::Pop => 4,
+        Op::GetLocal(_) => 5,
+        Op::SetLocal(_) => 6,
+        Op::GetUpvalue(_) => 7,
+        Op::SetUpvalue(_) => 8,
+        Op::GetGlobal(_) => 9,
+        Op::SetGlobal(_) => 10,
+        Op::DefineGlobal(_) => 11,
+        Op::DefineConst(_) => 12,
+        Op::Close(_) => 13,
+        Op::Unary(_) => 14,
+        Op::Binary(_) => 15,
+        Op::Jump(_) => 16,
+        Op::JumpIfFalse(_) => 17,
+        Op::JumpIfFalseOrPop(_) => 18,
+        Op::JumpIfTrueOrPop(_) => 19,
+        Op::Call(_) => 20,
+        Op::Closure(_) => 21,
+        Op::Return => 22,
+        Op::Array(_) => 23,
+        Op::Tuple(_) => 24,
+        Op::Map(_) => 25,
+        Op::Key(_) => 26,
+        Op::Index(_) => 27,
+        Op::SetIndex(_) => 28,
+        Op::GetAttribute(_) => 29,
+        Op::SetAttribute(_) => 30,
+        Op::Iterate(_) => 31,
+        Op::ForEach(_, _) => 32,
+        Op::IfGiven(_, _) => 33,
+        Op::Mark(_) => 34,
+        Op::Unwind(_) => 35,
+        Op::MatchArray(_) => 36,
+        Op::MatchTuple(_) => 37,
+        Op::MatchRange(_, _) => 38,
+        Op::Element(_) => 39,
+        Op::Superclass(_) => 40,
+        Op::Class(_) => 41,
+        Op::Interface(_) => 42,
+        Op::TryGlobal(_) => 43,
+        Op::Super(_, _) => 44,
+        Op::MatchInstance(_) => 45,
+        Op::HasField(_) => 46,
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len());
+        match end {
+            Some(end) => {
+                let bytes = &self.bytes[self.position..end];
+                self.position = end;
+                Ok(bytes)
+            }
+            None => Err(invalid("it ends too soon")),
+        }
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(
+            <[u8; 2]>::try_from(self.take(2)?).unwrap(),
+        ))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(
+            <[u8; 4]>::try_from(self.take(4)?).unwrap(),
+        ))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(
+            <[u8; 8]>::try_from(self.take(8)?).unwrap(),
+        ))
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("it has a flag that is not 0 or 1")),
+        }
+    }
+
+    fn len(&mut self) -> Result<usize> {
+        let len = self.u32()? as usize;
+        // Every element takes at least a byte, a longer list is a damaged file
+        if len > self.bytes.len() - self.position {
+            return Err(invalid("it ends too soon"));
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.len()?;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| invalid("it has a text that is not UTF-8"))
+    }
+
+    fn prototype(&mut self) -> Result<Prototype> {
+        let name = match self.bool()? {
+            true => Some(self.string()?),
+            false => None,
+        };
+        let mut params = Vec::new();
+        for _ in 0..self.len()? {
+            params.push(Parameter {
+                name: self.string()?,
+                default: self.bool()?,
+            });
+        }
+        let slots = self.u16()?;
+        let decimals = self.bool()?;
+        let mut captures = Vec::new();
+        for _ in 0..self.len()? {
+            captures.push(Capture {
+                local: self.bool()?,
+                index: self.u16()?,
+            });
+        }
+        let chunk = self.chunk()?;
+        let prototype = Prototype {
+            name,
+            params,
+            slots,
+            captures,
+            decimals,
+            chunk,
+        };
+        check(&prototype)?;
+        Ok(prototype)
+    }
+
+    fn chunk(&mut self) -> Result<Chunk> {
+        let mut chunk = Chunk::default();
+        for _ in 0..self.len()? {
+            let kind = self.u8()?;
+            let constant = match kind {
+                0 => Constant::Number(Number::Float(f64::from_bits(self.u64()?))),
+                3 => Constant::Number(Number::Integer(self.u64()? as i64)),
+                4 | 5 => {
+                    let decimal = kind == 5;
+                    Number::parse(&self.string()?, decimal)
+                        .map(Constant::Number)
+                        .ok_or_else(|| invalid("it has a number that can not be read"))?
+                }
+                1 => Constant::String(self.string()?),
+                2 => Constant::Function(Rc::new(self.prototype()?)),
+                _ => return Err(invalid("it has a constant of an unknown kind")),
+            };
+            chunk.constants.push(constant);
+        }
+
+        for _ in 0..self.len()? {
+            let kind = *KINDS
+                .get(self.u8()? as usize)
+                .ok_or_else(|| invalid("it has a token of an unknown kind"))?;
+            let lexeme = self.string()?;
+            let line = self.u64()?;
+            let column = self.u64()?;
+            let start = self.u64()? as usize;
+            let end = self.u64()? as usize;
+            chunk.tokens.push(Token {
+                kind,
+                lexeme,
+                literal: None,
+                span: Span::new(line, column, start, end),
+            });
+        }
+
+        for _ in 0..self.len()? {
+            let paren = self.u16()?;
+            let mut names = Vec::new();
+            for _ in 0..self.len()? {
+                names.push(match self.bool()? {
+                    true => Some(self.u16()?),
+                    false => None,
+                });
+            }
+            chunk.calls.push(CallSite { paren, names });
+        }
+
+        for _ in 0..self.len()? {
+            let name = self.u16()?;
+            let mut inherits = Vec::new();
+            for _ in 0..self.len()? {
+                inherits.push(self.u16()?);
+            }
+            let methods = self.u16()?;
+            chunk.classes.push(ClassSite {
+                name,
+                inherits,
+                methods,
+            });
+        }
+
+        for _ in 0..self.len()? {
+            let name = self.u16()?;
+            let mut fields = Vec::new();
+            for _ in 0..self.len()? {
+                fields.push(FieldSite {
+                    name: self.u16()?,
+                    field_type: self.u16()?,
+                    required: self.bool()?,
+                });
+            }
+            chunk.interfaces.push(InterfaceSite { name, fields });
+        }
+
+        for _ in 0..self.len()? {
+            let op = self.op()?;
+            chunk.code.push(op);
+        }
+
+        for _ in 0..self.len()? {
+            let line = self.u32()?;
+            let count = self.u32()? as usize;
+            if chunk.lines.len() + count > chunk.code.len() {
+                return Err(invalid("its line table is longer than its code"));
+            }
+            chunk.lines.resize(chunk.lines.len() + count, line);
+        }
+        if chunk.lines.len() != chunk.code.len() {
+            return Err(invalid("its line table is shorter than its code"));
+        }
+        Ok(chunk)
+    }
+
+    fn op(&mut self) -> Result<Op> {
+        let op = match self.u8()? {
+            0 => Op::Constant(self.u16()?),
+            1 => Op::Null,
+            2 => Op::True,
+            3 => Op::False,
+            4 => Op::Pop,
+            5 => Op::GetLocal(self.u16()?),
+            6 => Op::SetLocal(self.u16()?),
+            7 => Op::GetUpvalue(self.u16()?),
+            8 => Op::SetUpvalue(self.u16()?),
+            9 => Op::GetGlobal(self.u16()?),
+            10 => Op::SetGlobal(self.u16()?),
+            11 => Op::DefineGlobal(self.u16()?),
+            12 => Op::DefineConst(self.u16()?),
+            13 => Op::Close(self.u16()?),
+            14 => Op::Unary(self.u16()?),
+            15 => Op::Binary(self.u16()?),
+            16 => Op::Jump(self.u32()?),
+            17 => Op::JumpIfFalse(self.u32()?),
+            18 => Op::JumpIfFalseOrPop(self.u32()?),
+            19 => Op::JumpIfTrueOrPop(self.u32()?),
+            20 => Op::Call(self.u16()?),
+            21 => Op::Closure(self.u16()?),
+            22 => Op::Return,
+            23 => Op::Array(self.u16()?),
+            24 => Op::Tuple(self.u16()?),
+            25 => Op::Map(self.u16()?),
+            26 => Op::Key(self.u16()?),
+            27 => Op::Index(self.u16()?),
+            28 => Op::SetIndex(self.u16()?),
+            29 => Op::GetAttribute(self.u16()?),
+            30 => Op::SetAttribute(self.u16()?),
+            31 => Op::Iterate(self.u16()?),
+            32 => Op::ForEach(self.u16()?, self.u32()?),
+            33 => Op::IfGiven(self.u16()?, self.u32()?),
+            34 => Op::Mark(self.u16()?),
+            35 => Op::Unwind(self.u16()?),
+            36 => Op::MatchArray(self.u16()?),
+            37 => Op::MatchTuple(self.u16()?),
+            38 => Op::MatchRange(self.u16()?, self.u16()?),
+            39 => Op::Element(self.u16()?),
+            40 => Op::Superclass(self.u16()?),
+            41 => Op::Class(self.u16()?),
+            42 => Op::Interface(self.u16()?),
+            43 => Op::TryGlobal(self.u16()?),
+            44 => Op::Super(self.u16()?, self.u16()?),
+            45 => Op::MatchInstance(self.u16()?),
+            46 => Op::HasField(self.u16()?),
+            _ => return Err(invalid("it has an unknown instruction")),
+        };
+        Ok(op)
+    }
+}
+
+/// Check that the instructions only point inside of the pools, the slots and the code of their
+/// function, so a damaged file can not make the VM read outside of them. What the instructions
+/// find in the stack and in the slots is checked by the VM, that stops with an error.
+fn check(prototype: &Prototype) -> Result<()> {
+    let chunk = &prototype.chunk;
+    let slots = prototype.slots as usize;
+    let constant = |index: u16| match chunk.constants.get(index as usize) {
+        Some(Constant::Function(_)) | None => false,
+        Some(_) => true,
+    };
+    // The captures of a function point to the slots or the captures of the one that creates it
+    let closure = |index: u16| match chunk.constants.get(index as usize) {
+        Some(Constant::Function(function)) => function.captures.iter().all(|capture| {
+            let limit = match capture.local {
+                true => slots,
+                false => prototype.captures.len(),
+            };
+            (capture.index as usize) < limit
+        }),
+        _ => false,
+    };
+    let number = |index: u16| {
+        matches!(
+            chunk.constants.get(index as usize),
+            Some(Constant::Number(_))
+        )
+    };
+    let token = |index: u16| (index as usize) < chunk.tokens.len();
+    let slot = |index: u16| (index as usize) < slots;
+    // The code always ends with a `Return`, so the jumps never go past it
+    let target = |to: u32| (to as usize) < chunk.code.len();
+
+    let valid = prototype.params.len() <= slots
+        && chunk
+            .calls
+            .iter()
+            .all(|call| token(call.paren) && call.names.iter().flatten().all(|name| token(*name)))
+        && chunk
+            .classes
+            .iter()
+            .all(|class| token(class.name) && class.inherits.iter().all(|name| token(*name)))
+        && chunk.interfaces.iter().all(|interface| {
+            token(interface.name)
+                && interface
+                    .fields
+                    .iter()
+                    .all(|field| token(field.name) && token(field.field_type))
+        })
+        && chunk.code.last() == Some(&Op::Return)
+        && chunk.code.iter().all(|op| match *op {
+            Op::Constant(index) => constant(index),
+            Op::Closure(index) => closure(index),
+            Op::MatchRange(low, high) => number(low) && number(high),
+            Op::GetLocal(index)
+            | Op::SetLocal(index)
+            | Op::Close(index)
+            | Op::Mark(index)
+            | Op::Unwind(index) => slot(index),
+            Op::ForEach(index, to) => (index as usize) + 1 < slots && target(to),
+            Op::IfGiven(index, to) => (index as usize) < prototype.params.len() && target(to),
+            Op::GetUpvalue(index) | Op::SetUpvalue(index) => {
+                (index as usize) < prototype.captures.len()
+            }
+            Op::GetGlobal(index)
+            | Op::SetGlobal(index)
+            | Op::DefineGlobal(index)
+            | Op::DefineConst(index)
+            | Op::Unary(index)
+            | Op::Binary(index)
+            | Op::Key(index)
+            | Op::Index(index)
+            | Op::SetIndex(index)
+            | Op::GetAttribute(index)
+            | Op::SetAttribute(index)
+            | Op::Iterate(index)
+            | Op::TryGlobal(index)
+            | Op::MatchInstance(index)
+            | Op::HasField(index) => token(index),
+            Op::Super(keyword, name) => token(keyword) && token(name),
+            Op::Call(index) => (index as usize) < chunk.calls.len(),
+            Op::Superclass(index) | Op::Class(index) => (index as usize) < chunk.classes.len(),
+            Op::Interface(index) => (index as usize) < chunk.interfaces.len(),
+            Op::Jump(to)
+            | Op::JumpIfFalse(to)
+            | Op::JumpIfFalseOrPop(to)
+            | Op::JumpIfTrueOrPop(to) => target(to),
+            _ => true,
+        });
+    if !valid {
+        return Err(invalid("its code points outside of its function"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compiler::Compiler;
+    use crate::disassembler::disassemble;
+    use ezcript_lexer::lexer::Lexer;
+    use ezcript_parser::parser::Parser;
+
+    fn compile(source: &str) -> Rc<Prototype> {
+        let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        Compiler::new().compile(&statements).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let script = compile(
+            "func add(a, b = 2) do\n    return a + b\n\
+             set total = 0\nfor each n in [1, 2.5, -0, 1.0] do total += add(n, b = 1)\n\
+             set kind = match total\n    0..10 do return \"few\"\n    _ do return \"many\"\n\
+             print(kind, { k: \"v\" })",
+        );
+        let bytes = encode(&script);
+
+        assert!(is_compiled(&bytes));
+        assert_eq!(decode(&bytes).unwrap(), script);
+        // `1` and `1.0` are equal but they are different constants
+        let constants = &decode(&bytes).unwrap().chunk.constants;
+        assert_eq!(
+            format!("{:?}", constants),
+            format!("{:?}", script.chunk.constants)
+        );
+        let listing = disassemble(&script);
+        assert!(listing.starts_with("== <script> ==\n"));
+        assert!(listing.contains("== add(a, b=) ==\n"));
+        assert!(listing.contains("    2 GetLocal         0\n"));
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let bytes = encode(&compile("print(1)"));
+
+        assert!(decode(b"print(1)").is_err());
+        assert!(decode(&bytes[..bytes.len() - 1]).is_err());
+        let mut newer = bytes.clone();
+        newer[MAGIC.len()] = VERSION as u8 + 1;
+        let err = decode(&newer).unwrap_err().to_string();
+        assert!(err.contains(&format!("version {}", VERSION + 1)), "{}", err);
+        // A jump out of the code is found before the VM runs it
+        let mut script = (*compile("print(1)")).clone();
+        script.chunk.code.insert(0, Op::Jump(99));
+        script.chunk.lines.insert(0, 1);
+        assert!(decode(&encode(&script)).is_err());
+    }
+}
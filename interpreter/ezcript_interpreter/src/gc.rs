@@ -0,0 +1,420 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+use indexmap::IndexMap;
+
+use super::environment::Environment;
+use super::value::{Class, Closure, Instance, Interface, Key, Method, Type, Value};
+use super::vm::{self, Upvalue};
+
+/// How many objects the heap can have before the first collection
+const THRESHOLD: usize = 1000;
+
+/// What the garbage collector did until now
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub collections: usize,
+    /// The objects that were in a cycle that nothing else could reach
+    pub freed: usize,
+    /// The objects that are alive now
+    pub heap: usize,
+    /// The most objects that were alive at the same time
+    pub peak: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} collections, {} objects freed, {} objects in the heap ({} at most)",
+            self.collections, self.freed, self.heap, self.peak
+        )
+    }
+}
+
+/// The objects that can be part of a cycle, like arrays or scopes. The values are counted
+/// references, so the ones that are not in a cycle are freed as soon as nothing uses them, the
+/// collector only looks for the cycles that nothing outside of them reaches.
+struct Heap {
+    objects: RefCell<Vec<Box<dyn Object>>>,
+    /// How many objects there can be before the next collection
+    next: Cell<usize>,
+    /// Collect on every allocation, to find the bugs of the collector
+    stress: Cell<bool>,
+    stats: Cell<Stats>,
+}
+
+thread_local! {
+    static HEAP: Heap = Heap {
+        objects: RefCell::new(Vec::new()),
+        next: Cell::new(THRESHOLD),
+        stress: Cell::new(false),
+        stats: Cell::new(Stats::default()),
+    };
+}
+
+/// Collect on every allocation, or go back to collecting when the heap grows
+pub fn set_stress(stress: bool) {
+    HEAP.with(|heap| heap.stress.set(stress));
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|heap| {
+        let mut stats = heap.stats.get();
+        stats.heap = heap
+            .objects
+            .borrow()
+            .iter()
+            .filter(|o| o.count() > 0)
+            .count();
+        stats
+    })
+}
+
+/// Create an object in the heap, this can start a collection
+pub(crate) fn allocate<T: Trace + 'static>(object: T) -> Rc<T> {
+    let object = Rc::new(object);
+    track(&object);
+    object
+}
+
+fn track<T: Trace + 'static>(object: &Rc<T>) {
+    let collect = HEAP.with(|heap| {
+        let mut objects = heap.objects.borrow_mut();
+        objects.push(Box::new(Rc::downgrade(object)));
+        heap.stress.get() || objects.len() > heap.next.get()
+    });
+    if collect {
+        self::collect();
+    }
+}
+
+/// Free the cycles of objects that can not be reached, and give back how many objects they had.
+///
+/// The references that an object has from other objects of the heap are taken from its count, the
+/// ones that still have references after that are used from somewhere else, like a variable of
+/// the interpreter. Everything that those reach is alive, the rest are cycles that are broken by
+/// emptying the objects in them.
+pub fn collect() -> usize {
+    let mut objects = HEAP.with(|heap| mem::take(&mut *heap.objects.borrow_mut()));
+    objects.retain(|object| object.count() > 0);
+
+    let positions: HashMap<usize, usize> = objects
+        .iter()
+        .enumerate()
+        .map(|(position, object)| (object.address(), position))
+        .collect();
+    let mut counts: Vec<usize> = objects.iter().map(|object| object.count()).collect();
+    let mut edges = vec![Vec::new(); objects.len()];
+    let mut addresses: Vec<usize> = Vec::new();
+    for (position, object) in objects.iter().enumerate() {
+        addresses.clear();
+        // An object that is being changed can not be looked into, so it stays alive
+        if !object.references(&mut addresses) {
+            continue;
+        }
+        for address in &addresses {
+            if let Some(&child) = positions.get(address) {
+                counts[child] = counts[child].saturating_sub(1);
+                edges[position].push(child);
+            }
+        }
+    }
+
+    let mut alive = vec![false; objects.len()];
+    let mut pending: Vec<usize> = (0..objects.len()).filter(|&i| counts[i] > 0).collect();
+    while let Some(position) = pending.pop() {
+        if !alive[position] {
+            alive[position] = true;
+            pending.extend(edges[position].iter().filter(|&&child| !alive[child]));
+        }
+    }
+
+    let peak = objects.len();
+    let mut freed = 0;
+    for (object, _) in objects.iter().zip(&alive).filter(|(_, &alive)| !alive) {
+        object.clear();
+        freed += 1;
+    }
+    objects.retain(|object| object.count() > 0);
+
+    HEAP.with(|heap| {
+        let mut tracked = heap.objects.borrow_mut();
+        let live = objects.len();
+        objects.append(&mut tracked);
+        *tracked = objects;
+        heap.next.set(THRESHOLD.max(live * 2));
+
+        let mut stats = heap.stats.get();
+        stats.collections += 1;
+        stats.freed += freed;
+        stats.peak = stats.peak.max(peak);
+        heap.stats.set(stats);
+    });
+    freed
+}
+
+/// An object of the heap, through a weak reference so the heap does not keep it alive
+trait Object {
+    /// How many references there are to the object, 0 if it was freed
+    fn count(&self) -> usize;
+    fn address(&self) -> usize;
+    /// Add the addresses of the objects that it references, false if it can not be read now
+    fn references(&self, addresses: &mut Vec<usize>) -> bool;
+    /// Drop what the object holds, to break the cycles that it is in
+    fn clear(&self);
+}
+
+impl<T: Trace> Object for Weak<T> {
+    fn count(&self) -> usize {
+        self.strong_count()
+    }
+
+    fn address(&self) -> usize {
+        self.as_ptr() as *const () as usize
+    }
+
+    fn references(&self, addresses: &mut Vec<usize>) -> bool {
+        match self.upgrade() {
+            Some(object) => object.trace(addresses),
+            None => true,
+        }
+    }
+
+    fn clear(&self) {
+        if let Some(object) = self.upgrade() {
+            object.clear();
+        }
+    }
+}
+
+/// The objects that hold values, with the references that they keep to other objects
+pub(crate) trait Trace {
+    /// Add the address of every object that it holds a reference to, false if it is borrowed to
+    /// be changed
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool;
+
+    /// Drop the values that it holds, if it can change
+    fn clear(&self) {}
+}
+
+fn address<T>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+/// The object that the value references, if it has one
+fn trace_value(value: &Value, addresses: &mut Vec<usize>) {
+    match value {
+        Value::Function(closure) => addresses.push(address(closure)),
+        Value::Compiled(closure) => addresses.push(address(closure)),
+        Value::Array(array) => addresses.push(address(array)),
+        Value::Map(map) => addresses.push(address(map)),
+        Value::Tuple(tuple) => addresses.push(address(tuple)),
+        Value::Class(class) => addresses.push(address(class)),
+        Value::Instance(instance) => addresses.push(address(instance)),
+        Value::Method(method) => addresses.push(address(method)),
+        Value::Interface(interface) => addresses.push(address(interface)),
+        Value::Type(value_type) => trace_type(value_type, addresses),
+        Value::Null | Value::Boolean(_) | Value::Number(_) | Value::String(_) => {}
+        Value::Builtin(_) => {}
+    }
+}
+
+fn trace_type(value_type: &Type, addresses: &mut Vec<usize>) {
+    match value_type {
+        Type::Class(class) => addresses.push(address(class)),
+        Type::Interface(interface) => addresses.push(address(interface)),
+        Type::Builtin(_) => {}
+    }
+}
+
+impl Trace for RefCell<Vec<Value>> {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        match self.try_borrow() {
+            Ok(values) => values
+                .iter()
+                .for_each(|value| trace_value(value, addresses)),
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn clear(&self) {
+        let _values = match self.try_borrow_mut() {
+            Ok(mut values) => mem::take(&mut *values),
+            Err(_) => return,
+        };
+    }
+}
+
+impl Trace for RefCell<IndexMap<Key, Value>> {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        match self.try_borrow() {
+            Ok(entries) => entries
+                .values()
+                .for_each(|value| trace_value(value, addresses)),
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn clear(&self) {
+        let _entries = match self.try_borrow_mut() {
+            Ok(mut entries) => mem::take(&mut *entries),
+            Err(_) => return,
+        };
+    }
+}
+
+impl Trace for Vec<Value> {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        self.iter().for_each(|value| trace_value(value, addresses));
+        true
+    }
+}
+
+impl Trace for RefCell<Instance> {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        match self.try_borrow() {
+            Ok(instance) => {
+                addresses.push(address(&instance.class));
+                instance
+                    .fields
+                    .values()
+                    .for_each(|value| trace_value(value, addresses));
+            }
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn clear(&self) {
+        let _fields = match self.try_borrow_mut() {
+            Ok(mut instance) => mem::take(&mut instance.fields),
+            Err(_) => return,
+        };
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        match self.try_borrow() {
+            Ok(environment) => {
+                let (values, enclosing) = environment.references();
+                values
+                    .iter()
+                    .for_each(|value| trace_value(value, addresses));
+                addresses.extend(enclosing.map(address));
+            }
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn clear(&self) {
+        let _environment = match self.try_borrow_mut() {
+            Ok(mut environment) => mem::take(&mut *environment),
+            Err(_) => return,
+        };
+    }
+}
+
+impl Trace for Closure {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        addresses.push(address(&self.closure));
+        true
+    }
+}
+
+impl Trace for Method {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        trace_value(&self.receiver, addresses);
+        trace_value(&self.function, addresses);
+        true
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        addresses.extend(self.superclass.iter().map(address));
+        addresses.extend(self.interfaces.iter().map(address));
+        for method in self.methods.values() {
+            trace_value(method, addresses);
+        }
+        true
+    }
+}
+
+impl Trace for Interface {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        for field in &self.fields {
+            trace_type(&field.field_type, addresses);
+        }
+        true
+    }
+}
+
+impl Trace for vm::Closure {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        addresses.extend(self.upvalues.iter().map(address));
+        true
+    }
+}
+
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, addresses: &mut Vec<usize>) -> bool {
+        match self.try_borrow() {
+            Ok(upvalue) => {
+                if let Upvalue::Closed(ref value) = *upvalue {
+                    trace_value(value, addresses);
+                }
+            }
+            Err(_) => return false,
+        }
+        true
+    }
+
+    fn clear(&self) {
+        let _value = match self.try_borrow_mut() {
+            Ok(mut upvalue) => match *upvalue {
+                Upvalue::Closed(ref mut value) => mem::replace(value, Value::Null),
+                Upvalue::Open(_) => return,
+            },
+            Err(_) => return,
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_collect_cycles() {
+        let kept = Value::array(Vec::new());
+        if let Value::Array(ref array) = kept {
+            array.borrow_mut().push(kept.clone());
+        }
+        let lost = Value::array(Vec::new());
+        let mut entries = IndexMap::new();
+        entries.insert(Key::String("array".to_string()), lost.clone());
+        if let Value::Array(ref array) = lost {
+            array.borrow_mut().push(Value::map(entries));
+        }
+        let weak = match lost {
+            Value::Array(ref array) => Rc::downgrade(array),
+            _ => unreachable!(),
+        };
+        drop(lost);
+
+        assert_eq!(weak.strong_count(), 1);
+        assert_eq!(collect(), 2);
+        assert_eq!(weak.strong_count(), 0);
+        assert_eq!(collect(), 0);
+        assert_eq!(stats().heap, 1);
+        assert_eq!(kept.to_string(), "[[...]]");
+    }
+}
@@ -0,0 +1,941 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::mem;
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+
+use super::builtins::{self, Host};
+use super::environment::Environment;
+use super::gc;
+use super::operations::{self, check_arity, MAX_DEPTH};
+use super::value::{Closure, Field, Instance, Interface, Method, Type, Value, BUILTIN_TYPES};
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::{Literal, Token, TokenKind};
+use ezcript_parser::ast::{self, Arm, Expr, If, Match, Pattern, Stmt};
+use ezcript_result::{Error, Result};
+
+/// What the interpreter has to do after executing a statement
+enum Flow {
+    /// Continue with the next statement
+    Next,
+    /// Stop the current function and give back the value
+    Return(Value),
+}
+
+/// A tree-walking interpreter that executes the statements made by the `Parser`
+pub struct Interpreter {
+    pub globals: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    /// The arguments that the script receives from the command line
+    args: Vec<String>,
+    /// Where `print` writes
+    out: Box<dyn Write>,
+    /// If the divisions of integers give exact decimals
+    decimals: bool,
+    depth: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Interpreter::new()
+    }
+}
+
+impl Host for Interpreter {
+    fn out(&mut self) -> &mut dyn Write {
+        &mut self.out
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        let globals = Environment::shared(None);
+        for builtin in builtins::builtins() {
+            globals
+                .borrow_mut()
+                .define(builtin.name, Value::Builtin(Rc::new(builtin)));
+        }
+        for name in BUILTIN_TYPES.iter() {
+            globals
+                .borrow_mut()
+                .define(name, Value::Type(Type::Builtin(name)));
+        }
+
+        Interpreter {
+            environment: globals.clone(),
+            globals,
+            args: Vec::new(),
+            out: Box::new(io::stdout()),
+            decimals: false,
+            depth: 0,
+        }
+    }
+
+    /// Set the arguments that the script can read with `arg`
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Set where `print` writes, the standard output by default
+    pub fn with_output(mut self, out: Box<dyn Write>) -> Self {
+        self.out = out;
+        self
+    }
+
+    /// Make the divisions of integers exact decimals, for the scripts read with decimals
+    pub fn with_decimals(mut self, decimals: bool) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
+    /// Execute a whole program
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<()> {
+        for statement in statements {
+            if let Flow::Return(_) = self.execute(statement)? {
+                let keyword = match statement {
+                    Stmt::Return(keyword, _) => keyword.clone(),
+                    _ => Token::default(),
+                };
+                return Err(Error::Runtime(
+                    keyword.span,
+                    "'return' outside of a function".to_string(),
+                    keyword.lexeme,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Execute an entry of the interactive prompt, in the same environment as the entries before
+    /// it. The value of the last statement is given back when it is an expression, to show it.
+    pub fn interpret_entry(&mut self, statements: &[Stmt]) -> Result<Option<Value>> {
+        match statements.split_last() {
+            Some((Stmt::Expression(expr), rest)) => {
+                self.interpret(rest)?;
+                self.evaluate(expr).map(Some)
+            }
+            _ => self.interpret(statements).map(|_| None),
+        }
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<Flow> {
+        match stmt {
+            Stmt::Expression(expr) => {
+                self.evaluate(expr)?;
+            }
+            Stmt::Set(name, _, value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Null,
+                };
+                self.declare(name, value, false)?;
+            }
+            Stmt::Const(name, _, value) => {
+                let value = self.evaluate(value)?;
+                self.declare(name, value, true)?;
+            }
+            Stmt::Assign(target, operator, value) => {
+                let value = match operator.kind {
+                    TokenKind::Equal => self.evaluate(value)?,
+                    _ => {
+                        let current = self.evaluate(target)?;
+                        let value = self.evaluate(value)?;
+                        operations::binary(operator, current, value, self.decimals)?
+                    }
+                };
+                self.assign(target, value)?;
+            }
+            Stmt::Increment(target, operator) => {
+                let current = self.evaluate(target)?;
+                let one = Value::Number(Number::Integer(1));
+                let value = operations::binary(operator, current, one, self.decimals)?;
+                self.assign(target, value)?;
+            }
+            Stmt::If(if_body) => return self.execute_if(if_body),
+            Stmt::Match(match_body) => return self.execute_match(match_body),
+            Stmt::While(condition, body) => {
+                while self.evaluate(condition)?.is_truthy() {
+                    match self.execute_loop_body(body, self.scope())? {
+                        Some(Flow::Next) => (),
+                        Some(flow) => return Ok(flow),
+                        None => break,
+                    }
+                }
+            }
+            Stmt::DoWhile(body, condition) => loop {
+                match self.execute_loop_body(body, self.scope())? {
+                    Some(Flow::Next) => (),
+                    Some(flow) => return Ok(flow),
+                    None => break,
+                }
+                if !self.evaluate(condition)?.is_truthy() {
+                    break;
+                }
+            },
+            Stmt::ForEach(name, iterable, body) => {
+                let iterable_value = self.evaluate(iterable)?;
+                let items = operations::iterate(iterable.token(), iterable_value)?;
+                for item in items {
+                    let scope = self.scope();
+                    scope.borrow_mut().define(&name.lexeme, item);
+                    match self.execute_loop_body(body, scope)? {
+                        Some(Flow::Next) => (),
+                        Some(flow) => return Ok(flow),
+                        None => break,
+                    }
+                }
+            }
+            Stmt::Func(declaration) => {
+                let function = Value::Function(gc::allocate(Closure {
+                    declaration: declaration.clone(),
+                    closure: self.environment.clone(),
+                }));
+                self.environment
+                    .borrow_mut()
+                    .define(&declaration.name.lexeme, function);
+            }
+            Stmt::Class(class) => self.execute_class(class)?,
+            Stmt::Interface(interface) => self.execute_interface(interface)?,
+            Stmt::Return(_, value) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Null,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Break(keyword) => return Err(Error::Break(keyword.span)),
+        }
+
+        Ok(Flow::Next)
+    }
+
+    /// Create a class, its methods see `super` when it inherits from another one
+    fn execute_class(&mut self, class: &ast::Class) -> Result<()> {
+        let mut inherited = Vec::with_capacity(class.inherits.len());
+        for name in &class.inherits {
+            inherited.push((name, self.environment.borrow().get(name)?));
+        }
+        let (superclass, interfaces) = operations::inherits(inherited)?;
+
+        // The methods always have a scope of their own, with `super` if there is a superclass, so
+        // the resolver knows how far their variables are
+        let mut environment = Environment::new(Some(self.environment.clone()));
+        if let Some(ref superclass) = superclass {
+            environment.define("super", Value::Class(superclass.clone()));
+        }
+        let closure = gc::allocate(RefCell::new(environment));
+        let methods: HashMap<String, Value> = class
+            .methods
+            .iter()
+            .map(|method| {
+                let closure = Closure {
+                    declaration: method.clone(),
+                    closure: closure.clone(),
+                };
+                (
+                    method.name.lexeme.clone(),
+                    Value::Function(gc::allocate(closure)),
+                )
+            })
+            .collect();
+
+        let value = operations::class(&class.name, superclass, interfaces, methods)?;
+        self.environment
+            .borrow_mut()
+            .define(&class.name.lexeme, value);
+        Ok(())
+    }
+
+    /// Create an interface, the types of its fields must exist
+    fn execute_interface(&mut self, interface: &ast::Interface) -> Result<()> {
+        let mut fields = Vec::with_capacity(interface.fields.len());
+        for field in &interface.fields {
+            let ast::TypeExpr::Name(ref type_name) = field.type_expr;
+            fields.push(Field {
+                name: field.name.lexeme.clone(),
+                field_type: self.resolve_type(type_name)?,
+                required: field.required,
+            });
+        }
+
+        let value = Value::Interface(gc::allocate(Interface {
+            name: interface.name.lexeme.clone(),
+            fields,
+        }));
+        self.environment
+            .borrow_mut()
+            .define(&interface.name.lexeme, value);
+        Ok(())
+    }
+
+    /// The type with the name, a type of the language or a class or interface of the script
+    fn resolve_type(&mut self, name: &Token) -> Result<Type> {
+        let value = match Type::builtin(&name.lexeme) {
+            Some(_) => None,
+            None => self.environment.borrow().get(name).ok(),
+        };
+        operations::field_type(name, value)
+    }
+
+    fn execute_block(&mut self, statements: &[Stmt]) -> Result<Flow> {
+        for statement in statements {
+            if let Flow::Return(value) = self.execute(statement)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Create a variable with `set` or `const`, a constant can not be declared again in the same
+    /// scope
+    fn declare(&mut self, name: &Token, value: Value, constant: bool) -> Result<()> {
+        let mut environment = self.environment.borrow_mut();
+        if environment.is_constant(&name.lexeme) {
+            return Err(Error::Runtime(
+                name.span,
+                format!("the constant '{}' can not be declared again", name.lexeme),
+                name.lexeme.clone(),
+            ));
+        }
+        if constant {
+            environment.define_const(&name.lexeme, value);
+        } else {
+            environment.define(&name.lexeme, value);
+        }
+        Ok(())
+    }
+
+    /// A new scope inside of the current one, for the variables of a block
+    fn scope(&self) -> Rc<RefCell<Environment>> {
+        Environment::shared(Some(self.environment.clone()))
+    }
+
+    /// Execute a block in a scope of its own, its variables are removed when it ends
+    fn execute_scoped(&mut self, statements: &[Stmt]) -> Result<Flow> {
+        let scope = self.scope();
+        self.execute_with(statements, scope)
+    }
+
+    /// Execute the body of a loop in the scope, `None` means that a `break` stopped it
+    fn execute_loop_body(
+        &mut self,
+        body: &[Stmt],
+        scope: Rc<RefCell<Environment>>,
+    ) -> Result<Option<Flow>> {
+        match self.execute_with(body, scope) {
+            Ok(flow) => Ok(Some(flow)),
+            Err(Error::Break(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Execute the statements with another environment, the current one is restored after
+    fn execute_with(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Flow> {
+        let previous = mem::replace(&mut self.environment, environment);
+        let result = self.execute_block(statements);
+        self.environment = previous;
+        result
+    }
+
+    fn execute_if(&mut self, if_body: &If) -> Result<Flow> {
+        for (condition, body) in &if_body.branches {
+            if self.evaluate(condition)?.is_truthy() {
+                return self.execute_scoped(body);
+            }
+        }
+
+        match if_body.otherwise {
+            Some(ref body) => self.execute_scoped(body),
+            None => Ok(Flow::Next),
+        }
+    }
+
+    /// Execute the first arm whose pattern matches the value and whose guard is true, nothing is
+    /// executed if there is none
+    fn execute_match(&mut self, match_body: &Match) -> Result<Flow> {
+        let subject = self.evaluate(&match_body.subject)?;
+        for arm in &match_body.arms {
+            let mut bindings = Vec::new();
+            if !self.matches(&arm.pattern, &subject, &mut bindings)? {
+                continue;
+            }
+            // The variables of the pattern only exist in the guard and the body of the arm
+            let scope = self.scope();
+            for (name, value) in bindings {
+                scope.borrow_mut().define(&name.lexeme, value);
+            }
+            let previous = mem::replace(&mut self.environment, scope);
+            let result = self.execute_arm(arm);
+            self.environment = previous;
+            if let Some(flow) = result? {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    /// Execute the body of the arm if its guard is true, `None` if it is false
+    fn execute_arm(&mut self, arm: &Arm) -> Result<Option<Flow>> {
+        let guard = match arm.guard {
+            Some(ref guard) => self.evaluate(guard)?.is_truthy(),
+            None => true,
+        };
+        if !guard {
+            return Ok(None);
+        }
+        self.execute_block(&arm.body).map(Some)
+    }
+
+    /// True if the value matches the pattern, the variables that the pattern makes are added to
+    /// `bindings`
+    fn matches<'p>(
+        &mut self,
+        pattern: &'p Pattern,
+        value: &Value,
+        bindings: &mut Vec<(&'p Token, Value)>,
+    ) -> Result<bool> {
+        let matched = match (pattern, value) {
+            (Pattern::Wildcard(_), _) => true,
+            (Pattern::Binding(name), value) => {
+                bindings.push((name, value.clone()));
+                true
+            }
+            (Pattern::Literal(token), value) => {
+                token.literal.as_ref().map_or(Value::Null, Value::from) == *value
+            }
+            (Pattern::Range(low, _, high), Value::Number(n)) => {
+                let number = |token: &Token| match token.literal {
+                    Some(Literal::Number(ref n)) => n.clone(),
+                    _ => Number::Float(f64::NAN),
+                };
+                number(low) <= *n && *n <= number(high)
+            }
+            (Pattern::Array(_, patterns), Value::Array(array)) => {
+                let array = array.borrow().clone();
+                self.matches_all(patterns, &array, bindings)?
+            }
+            (Pattern::Tuple(_, patterns), Value::Tuple(tuple)) => {
+                self.matches_all(patterns, tuple, bindings)?
+            }
+            (Pattern::Instance(name, fields), value) => {
+                let class = self.environment.borrow().get(name)?;
+                let instance = match value {
+                    _ if !operations::is_instance(name, class, value)? => return Ok(false),
+                    Value::Instance(instance) => instance,
+                    _ => return Ok(false),
+                };
+                for (field, pattern) in fields {
+                    let value = instance.borrow().fields.get(&field.lexeme).cloned();
+                    match value {
+                        Some(value) if self.matches(pattern, &value, bindings)? => (),
+                        _ => return Ok(false),
+                    }
+                }
+                true
+            }
+            _ => false,
+        };
+        Ok(matched)
+    }
+
+    fn matches_all<'p>(
+        &mut self,
+        patterns: &'p [Pattern],
+        values: &[Value],
+        bindings: &mut Vec<(&'p Token, Value)>,
+    ) -> Result<bool> {
+        if patterns.len() != values.len() {
+            return Ok(false);
+        }
+        for (pattern, value) in patterns.iter().zip(values) {
+            if !self.matches(pattern, value, bindings)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn assign(&mut self, target: &Expr, value: Value) -> Result<()> {
+        match target {
+            Expr::Variable(name, slot) => match slot.get() {
+                Some(slot) => self.environment.borrow_mut().assign_at(slot, name, value),
+                None => self.environment.borrow_mut().assign(name, value),
+            },
+            Expr::Get(object, name) => {
+                let object = self.evaluate(object)?;
+                operations::set_attribute(name, object, value)
+            }
+            Expr::Index(object, bracket, index) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                operations::set_index(bracket, object, index, value)
+            }
+            _ => Err(Error::Runtime(
+                target.token().span,
+                "invalid assignment target".to_string(),
+                target.token().lexeme.clone(),
+            )),
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
+        match expr {
+            Expr::Literal(token) => Ok(token.literal.as_ref().map_or(Value::Null, Value::from)),
+            Expr::Variable(name, slot) => match slot.get() {
+                Some(slot) => self.environment.borrow().get_at(slot, name),
+                None => self.environment.borrow().get(name),
+            },
+            Expr::Grouping(expr) => self.evaluate(expr),
+            Expr::Unary(operator, right) => {
+                let right = self.evaluate(right)?;
+                operations::unary(operator, right)
+            }
+            Expr::Binary(left, operator, right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                operations::binary(operator, left, right, self.decimals)
+            }
+            Expr::Logical(left, operator, right) => {
+                let left = self.evaluate(left)?;
+                match operator.lexeme.as_ref() {
+                    "or" if left.is_truthy() => Ok(left),
+                    "and" if !left.is_truthy() => Ok(left),
+                    _ => self.evaluate(right),
+                }
+            }
+            Expr::Call(callee, paren, arguments) => {
+                let callee = self.evaluate(callee)?;
+                let mut values = Vec::with_capacity(arguments.len());
+                let mut keywords = Vec::new();
+                for argument in arguments {
+                    let value = self.evaluate(&argument.value)?;
+                    match argument.name {
+                        Some(ref name) => keywords.push((name, value)),
+                        None => values.push(value),
+                    }
+                }
+                self.call(callee, paren, values, keywords)
+            }
+            Expr::Function(declaration) => Ok(Value::Function(gc::allocate(Closure {
+                declaration: declaration.clone(),
+                closure: self.environment.clone(),
+            }))),
+            Expr::Get(object, name) => {
+                let object = self.evaluate(object)?;
+                operations::attribute(name, object)
+            }
+            Expr::Super(keyword, name) => self.superclass_method(keyword, name),
+            Expr::Index(object, bracket, index) => {
+                let object = self.evaluate(object)?;
+                let index = self.evaluate(index)?;
+                operations::index(bracket, object, index)
+            }
+            Expr::Array(_, elements) => Ok(Value::array(self.evaluate_all(elements)?)),
+            Expr::Tuple(_, elements) => Ok(Value::tuple(self.evaluate_all(elements)?)),
+            Expr::Map(_, entries) => {
+                let mut map = IndexMap::with_capacity(entries.len());
+                for (key_expr, value) in entries {
+                    let key = operations::key(key_expr.token(), &self.evaluate(key_expr)?)?;
+                    map.insert(key, self.evaluate(value)?);
+                }
+                Ok(Value::map(map))
+            }
+            Expr::If(if_body) => match self.execute_if(if_body)? {
+                Flow::Return(value) => Ok(value),
+                Flow::Next => Ok(Value::Null),
+            },
+            Expr::Match(match_body) => match self.execute_match(match_body)? {
+                Flow::Return(value) => Ok(value),
+                Flow::Next => Ok(Value::Null),
+            },
+        }
+    }
+
+    /// The method of the class that the current one inherits from, bound to the same `self`
+    fn superclass_method(&mut self, keyword: &Token, name: &Token) -> Result<Value> {
+        let superclass = self.environment.borrow().get(keyword).ok();
+        let superclass = operations::superclass(keyword, superclass)?;
+        let receiver = Token {
+            kind: TokenKind::Ident,
+            lexeme: "self".to_string(),
+            ..keyword.clone()
+        };
+        let receiver = self.environment.borrow().get(&receiver)?;
+        operations::super_method(name, superclass, receiver)
+    }
+
+    fn evaluate_all(&mut self, exprs: &[Expr]) -> Result<Vec<Value>> {
+        let mut values = Vec::with_capacity(exprs.len());
+        for expr in exprs {
+            values.push(self.evaluate(expr)?);
+        }
+        Ok(values)
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        paren: &Token,
+        arguments: Vec<Value>,
+        keywords: Vec<(&Token, Value)>,
+    ) -> Result<Value> {
+        match callee {
+            Value::Function(function) => {
+                self.call_closure(&function, paren, None, arguments, keywords)
+            }
+            Value::Class(class) => {
+                let instance = Value::Instance(gc::allocate(RefCell::new(Instance {
+                    class: class.clone(),
+                    fields: IndexMap::new(),
+                })));
+                match class.find_method("init") {
+                    Some(init) => {
+                        let method = Method {
+                            receiver: instance.clone(),
+                            function: init,
+                        };
+                        self.call(
+                            Value::Method(gc::allocate(method)),
+                            paren,
+                            arguments,
+                            keywords,
+                        )?;
+                    }
+                    None if !keywords.is_empty() => {
+                        return Err(Error::Runtime(
+                            keywords[0].0.span,
+                            format!(
+                                "the class {} has no parameter '{}'",
+                                class.name, keywords[0].0.lexeme
+                            ),
+                            keywords[0].0.lexeme.clone(),
+                        ))
+                    }
+                    None => check_arity(paren, 0, arguments.len())?,
+                }
+                operations::check_fields(paren, &instance)?;
+                Ok(instance)
+            }
+            Value::Method(method) => match method.function {
+                Value::Function(ref function) => {
+                    let receiver = method.receiver.clone();
+                    self.call_closure(function, paren, Some(receiver), arguments, keywords)
+                }
+                ref function => Err(operations::not_callable(paren, function)),
+            },
+            Value::Builtin(builtin) => {
+                if let Some((name, _)) = keywords.first() {
+                    return Err(Error::Runtime(
+                        name.span,
+                        format!("'{}' does not accept keyword arguments", builtin.name),
+                        name.lexeme.clone(),
+                    ));
+                }
+                if let Some(arity) = builtin.arity {
+                    check_arity(paren, arity, arguments.len())?;
+                }
+                (builtin.function)(self, paren, arguments)
+            }
+            value => Err(operations::not_callable(paren, &value)),
+        }
+    }
+
+    /// Call a function of the script, the receiver is the `self` of a method
+    fn call_closure(
+        &mut self,
+        function: &Closure,
+        paren: &Token,
+        receiver: Option<Value>,
+        arguments: Vec<Value>,
+        keywords: Vec<(&Token, Value)>,
+    ) -> Result<Value> {
+        let declaration = &function.declaration;
+        let environment = self.bind(function, paren, receiver, arguments, keywords)?;
+
+        if self.depth >= MAX_DEPTH {
+            return Err(Error::Runtime(
+                paren.span,
+                "maximum recursion depth exceeded".to_string(),
+                declaration.name.lexeme.clone(),
+            ));
+        }
+        self.depth += 1;
+        let result = self.execute_with(&declaration.body, environment);
+        self.depth -= 1;
+
+        match result {
+            Ok(Flow::Return(value)) => Ok(value),
+            Ok(Flow::Next) => Ok(Value::Null),
+            // A `break` can not stop a loop outside of the function
+            Err(Error::Break(span)) => Err(Error::Runtime(
+                span,
+                "'break' outside of a loop".to_string(),
+                "break".to_string(),
+            )),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Create the environment of a call, with the arguments in the parameters that they belong to
+    /// and the default values in the ones without arguments
+    fn bind(
+        &mut self,
+        function: &Closure,
+        paren: &Token,
+        receiver: Option<Value>,
+        arguments: Vec<Value>,
+        keywords: Vec<(&Token, Value)>,
+    ) -> Result<Rc<RefCell<Environment>>> {
+        let declaration = &function.declaration;
+        let params = &declaration.params;
+        let values = operations::bind(
+            &declaration.name.lexeme,
+            params
+                .iter()
+                .map(|param| (param.name.lexeme.as_str(), param.default.is_some())),
+            paren,
+            receiver,
+            arguments,
+            keywords,
+        )?;
+
+        // The default values are evaluated in the environment of the call, so they can use the
+        // parameters before them
+        let environment = Environment::shared(Some(function.closure.clone()));
+        for (param, value) in params.iter().zip(values) {
+            let value = match (value, &param.default) {
+                (Some(value), _) => value,
+                (None, Some(default)) => {
+                    let previous = mem::replace(&mut self.environment, environment.clone());
+                    let value = self.evaluate(default);
+                    self.environment = previous;
+                    value?
+                }
+                // `bind` gives a value to all of the parameters without a default one
+                (None, None) => unreachable!(),
+            };
+            environment.borrow_mut().define(&param.name.lexeme, value);
+        }
+        Ok(environment)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::operations::Output;
+    use crate::resolver::Resolver;
+    use ezcript_lexer::lexer::Lexer;
+    use ezcript_parser::parser::Parser;
+
+    fn parse(source: &str) -> Result<Vec<Stmt>> {
+        let tokens = Lexer::new(source.chars()).tokenize()?;
+        Parser::new(tokens).parse()
+    }
+
+    fn run(source: &str) -> Result<String> {
+        let statements = parse(source)?;
+        if let Some(err) = Resolver::new().resolve(&statements).into_iter().next() {
+            return Err(err);
+        }
+        let output = Output::default();
+        Interpreter::new()
+            .with_args(vec!["first".to_string()])
+            .with_output(Box::new(output.clone()))
+            .interpret(&statements)?;
+        Ok(output.text())
+    }
+
+    #[test]
+    fn test_functions_and_loops() {
+        let source =
+            "func fib(n) do\n    if n < 2 do return n\n    return fib(n - 1) + fib(n - 2)\n\
+                      set i = 0\n\
+                      while i < 10 do\n    i++\n    if i == 7 do break\n\
+                      print(fib(i), arg(0), arg(1))";
+
+        assert_eq!(run(source).unwrap(), "13 first null\n");
+    }
+
+    #[test]
+    fn test_if_expression() {
+        let source = "set x = 3\n\
+                      set kind = if x > 2 do\n    return \"big\"\nelse do\n    return \"small\"\n\
+                      print(kind, 7 // 2, 2 ** 3, \"a\" + \"b\", not x)";
+
+        assert_eq!(run(source).unwrap(), "big 3 8 ab false\n");
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        assert!(matches!(run("print(y)"), Err(Error::Undefined(_, _, None))));
+        assert!(matches!(
+            run("print(1 + \"a\")"),
+            Err(Error::Runtime(_, _, _))
+        ));
+        // The resolver finds the `break` outside of a loop before the script runs
+        assert!(matches!(run("break"), Err(Error::Parse(_, _, _))));
+        assert!(matches!(
+            run("func f() do break\nwhile true do f()"),
+            Err(Error::Parse(_, _, _))
+        ));
+    }
+
+    #[test]
+    fn test_collections() {
+        let source = "set list = [1, 2, (3, \"x\")]\n\
+                      list[0] = 10\n\
+                      list[-1] = list[2] + (4,)\n\
+                      set person = { name: \"Ana\", \"age\": 31, (1, 2): true }\n\
+                      person.age += 1\n\
+                      person[\"city\"] = \"Lima\"\n\
+                      set total = 0\n\
+                      for each key in person do total += 1\n\
+                      print(list, len(list), 2 in list, \"age\" in person, total)\n\
+                      print(person, person[(1, 2)], \"na\" in person.name, (1,), [] == [])";
+
+        assert_eq!(
+            run(source).unwrap(),
+            "[10, 2, (3, \"x\", 4)] 3 true true 4\n\
+             {\"name\": \"Ana\", \"age\": 32, (1, 2): true, \"city\": \"Lima\"} true true (1,) true\n"
+        );
+        assert!(run("print([1][1])").is_err());
+        assert_eq!(run("print([1, 2][(2 ** 64) ** 0])").unwrap(), "2\n");
+        assert!(run("set t = (1, 2)\nt[0] = 3").is_err());
+        assert!(run("print({ [1]: 2 })").is_err());
+        // The keys are the same when their exact values are equal
+        let keys = "set m = { 2: \"a\", 9007199254740993: \"b\" }\nm[2.0] = \"c\"\n\
+                    m[9007199254740992.0] = \"d\"\n\
+                    print(len(m), m[2], 9007199254740993 == 9007199254740992.0)";
+        assert_eq!(run(keys).unwrap(), "3 c false\n");
+    }
+
+    #[test]
+    fn test_first_class_functions() {
+        let source = "func counter(start = 0, step = 1) do\n    set count = start\n    \
+                      return func() do\n        count += step\n        return count\n\
+                      set next = counter(step = 5)\n\
+                      next()\n\
+                      func apply(f, x) do return f(x)\n\
+                      print(next(), apply(func(x) do return x * 2, 21), counter(10)())";
+
+        assert_eq!(run(source).unwrap(), "10 42 11\n");
+        assert!(run("func f(a) do return a\nf(1, a = 2)").is_err());
+        assert!(run("func f(a = 1) do return a\nf(b = 2)").is_err());
+        assert!(run("print(end = 1)").is_err());
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = "class Humano do\n    func init(self, nombre) do\n        self.nombre = nombre\n\
+                      \n    func saludar(self) do return \"Hola, \" + self.nombre\n\
+                      class Trabajador inherit Humano do\n    func init(self, nombre, puesto) do\n        \
+                      super.init(nombre)\n        self.puesto = puesto\n\
+                      set t = Trabajador(\"Ana\", \"panadera\")\n\
+                      print(t.saludar(), t.puesto, t)";
+
+        assert_eq!(
+            run(source).unwrap(),
+            "Hola, Ana panadera Trabajador {nombre: \"Ana\", puesto: \"panadera\"}\n"
+        );
+        let err = run("class A do func f(self) do return 1\nA().g").unwrap_err();
+        assert!(
+            matches!(err, Error::Runtime(_, msg, _) if msg == "the A object has no attribute 'g'")
+        );
+        assert!(run("class A do func f(self) do return 1\nA(1)").is_err());
+    }
+
+    #[test]
+    fn test_interfaces() {
+        let interface = "interface IHumano do\n    nombre: String\n    \
+                         edad: { type: Integer, required: false }\n\
+                         class Empleado inherit IHumano do\n    func init(self, nombre) do\n        \
+                         self.nombre = nombre\n";
+
+        assert_eq!(
+            run(&format!("{}print(Empleado(\"Pepe\"))", interface)).unwrap(),
+            "Empleado {nombre: \"Pepe\", edad: null}\n"
+        );
+        let err = run(&format!("{}Empleado(3)", interface)).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Runtime(_, msg, _) if msg == "the field 'nombre' of IHumano must be string, not number"
+        ));
+        assert!(run(
+            "interface I do a: string\nclass A inherit I do func f(self) do return 1\nA()"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_match() {
+        let source = "class P do\n    func init(self, x) do\n        self.x = x\n\
+                      func describe(value) do\n    return match value\n        \
+                      1 do return \"one\"\n        2..5 do return \"few\"\n        \
+                      [a, b] if a == b do return \"pair\"\n        (a, _) do return a\n        \
+                      P { x } do return x * 10\n        _ do return \"other\"\n\
+                      print(describe(1), describe(4.5), describe([2, 2]), describe([2, 3]), \
+                      describe((\"t\", 0)), describe(P(7)))";
+
+        assert_eq!(run(source).unwrap(), "one few pair other t 70\n");
+        assert!(run("set x = 1\nmatch 1\n    x { y } do y\n").is_err());
+    }
+
+    #[test]
+    fn test_types() {
+        let source =
+            "class A do func f(self) do return 1\nclass B inherit A do func g(self) do return 2\n\
+                      set b = B()\n\
+                      print(\"x\".type == string, 1.type, b.type == B, { type: 1 }.type)\n\
+                      print(b is A, A() is B, 2 is integer, 2.5 is integer, null is string)";
+
+        assert_eq!(
+            run(source).unwrap(),
+            "true <type number> true 1\ntrue false true false false\n"
+        );
+        assert!(run("1 is 2").is_err());
+    }
+
+    #[test]
+    fn test_scopes() {
+        let source = "set x = 1\nif true do\n    set x = 2\n    set y = x\n    print(x, y)\n\
+                      for each i in [1, 2] do\n    x += i\nprint(x)";
+
+        assert_eq!(run(source).unwrap(), "2 2\n4\n");
+        let err = run("if true do\n    set inside = 1\nprint(insid)").unwrap_err();
+        assert_eq!(err.message(), "undefined variable 'insid'");
+        let err = run("set counter = 1\nprint(countr)").unwrap_err();
+        assert_eq!(
+            err.message(),
+            "undefined variable 'countr', did you mean 'counter'?"
+        );
+        assert!(matches!(err, Error::Undefined(_, _, Some(similar)) if similar == "counter"));
+        let err = run("const k = 1\nk = 2").unwrap_err();
+        assert!(
+            matches!(err, Error::Runtime(_, msg, _) if msg == "can not change the constant 'k'")
+        );
+        assert!(run("const k = 1\nset k = 2").is_err());
+    }
+
+    #[test]
+    fn test_interpret_entry() {
+        let mut interpreter = Interpreter::new().with_output(Box::new(Output::default()));
+        let mut entry = |source: &str| interpreter.interpret_entry(&parse(source).unwrap());
+
+        assert!(matches!(entry("set x = 5"), Ok(None)));
+        assert!(matches!(entry("x * 2"), Ok(Some(Value::Number(n))) if n == Number::Integer(10)));
+        assert!(entry("x + y").is_err());
+        assert!(matches!(entry("x++\nx"), Ok(Some(Value::Number(n))) if n == Number::Integer(6)));
+    }
+}
@@ -0,0 +1,12 @@
+pub mod builtins;
+pub mod chunk;
+pub mod compiler;
+pub mod disassembler;
+pub mod environment;
+pub mod ezc;
+pub mod gc;
+pub mod interpreter;
+mod operations;
+pub mod resolver;
+pub mod value;
+pub mod vm;
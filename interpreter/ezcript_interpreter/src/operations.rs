@@ -0,0 +1,574 @@
+#[cfg(test)]
+use std::cell::RefCell;
+use std::collections::HashMap;
+#[cfg(test)]
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use super::gc;
+use super::value::{Class, Interface, Key, Method, Type, Value};
+use ezcript_lexer::number::{Arithmetic, Number};
+use ezcript_lexer::tokens::{Token, TokenKind};
+use ezcript_result::{Error, Result};
+
+/// How many calls can be nested before the script is stopped
+pub(crate) const MAX_DEPTH: usize = 1000;
+
+/// The result of an operator with two operands. In the decimal mode the integers that would give
+/// a float give a decimal, so `1 / 10` is `0.1` and `2 ** -1` is `0.5` exactly.
+pub(crate) fn binary(operator: &Token, left: Value, right: Value, decimals: bool) -> Result<Value> {
+    use TokenKind::*;
+
+    match (operator.kind, left, right) {
+        (EqEqual, l, r) => Ok(Value::Boolean(l == r)),
+        (BangEqual, l, r) => Ok(Value::Boolean(l != r)),
+        (Keyword, l, r) if operator.lexeme == "in" => contains(operator, &r, &l),
+        (Keyword, l, r) if operator.lexeme == "is" => match r.as_type() {
+            Some(value_type) => Ok(Value::Boolean(value_type.matches(&l))),
+            None => Err(type_error(operator, "a type", &r)),
+        },
+        (Plus, Value::Array(l), Value::Array(r)) => {
+            let mut values = l.borrow().clone();
+            values.extend(r.borrow().iter().cloned());
+            Ok(Value::array(values))
+        }
+        (Plus, Value::Tuple(l), Value::Tuple(r)) => {
+            Ok(Value::tuple(l.iter().chain(r.iter()).cloned().collect()))
+        }
+        (Plus, Value::String(l), Value::String(r)) => Ok(Value::String(l + &r)),
+        (Plus, Value::String(_), r) | (Plus, r, Value::String(_)) => {
+            Err(type_error(operator, "a string", &r))
+        }
+        (kind, Value::String(l), Value::String(r)) => match kind {
+            Less => Ok(Value::Boolean(l < r)),
+            LessEqual => Ok(Value::Boolean(l <= r)),
+            Greater => Ok(Value::Boolean(l > r)),
+            GreaterEqual => Ok(Value::Boolean(l >= r)),
+            _ => Err(type_error(operator, "a number", &Value::String(l))),
+        },
+        (kind, Value::Number(l), Value::Number(r)) => Ok(match kind {
+            Slash | SlashEqual if decimals && l.is_integer() && r.is_integer() => {
+                Value::Number(arithmetic(operator, l.as_decimal().div(&r))?)
+            }
+            DoubleStar if decimals && l.is_integer() && r.is_integer() && r.to_f64() < 0.0 => {
+                Value::Number(arithmetic(operator, l.as_decimal().pow(&r))?)
+            }
+            Plus | PlusEqual | DoublePlus => Value::Number(arithmetic(operator, l.add(&r))?),
+            Minus | MinEqual | DoubleMinus => Value::Number(arithmetic(operator, l.sub(&r))?),
+            Star | StarEqual => Value::Number(arithmetic(operator, l.mul(&r))?),
+            Slash | SlashEqual => Value::Number(arithmetic(operator, l.div(&r))?),
+            DoubleSlash => Value::Number(arithmetic(operator, l.floor_div(&r))?),
+            Percent | PercentEqual => Value::Number(arithmetic(operator, l.rem(&r))?),
+            DoubleStar => Value::Number(arithmetic(operator, l.pow(&r))?),
+            Less => Value::Boolean(l < r),
+            LessEqual => Value::Boolean(l <= r),
+            Greater => Value::Boolean(l > r),
+            GreaterEqual => Value::Boolean(l >= r),
+            _ => return Err(unknown_operator(operator)),
+        }),
+        (_, Value::Number(_), r) => Err(type_error(operator, "a number", &r)),
+        (_, l, _) => Err(type_error(operator, "a number", &l)),
+    }
+}
+
+/// The result of `-` or `not`
+pub(crate) fn unary(operator: &Token, right: Value) -> Result<Value> {
+    match operator.kind {
+        TokenKind::Minus => match right {
+            Value::Number(n) => Ok(Value::Number(-n)),
+            value => Err(type_error(operator, "a number", &value)),
+        },
+        _ => Ok(Value::Boolean(!right.is_truthy())),
+    }
+}
+
+/// The element of an array, tuple or string in a position, or the value of a key of an
+/// associative array
+pub(crate) fn index(bracket: &Token, object: Value, index: Value) -> Result<Value> {
+    match object {
+        Value::Array(array) => {
+            let array = array.borrow();
+            Ok(array[position(bracket, &index, array.len())?].clone())
+        }
+        Value::Tuple(tuple) => Ok(tuple[position(bracket, &index, tuple.len())?].clone()),
+        Value::String(s) => {
+            let position = position(bracket, &index, s.chars().count())?;
+            Ok(Value::String(s.chars().nth(position).unwrap().to_string()))
+        }
+        Value::Map(map) => match map.borrow().get(&key(bracket, &index)?) {
+            Some(value) => Ok(value.clone()),
+            None => Err(Error::Runtime(
+                bracket.span,
+                format!("the key {} is not in the object", index),
+                bracket.lexeme.clone(),
+            )),
+        },
+        object => Err(Error::Runtime(
+            bracket.span,
+            format!("a value of type {} has no elements", object.type_name()),
+            bracket.lexeme.clone(),
+        )),
+    }
+}
+
+/// Change the element of an array in a position, or the value of a key of an associative array
+pub(crate) fn set_index(bracket: &Token, object: Value, index: Value, value: Value) -> Result<()> {
+    match object {
+        Value::Array(array) => {
+            let mut array = array.borrow_mut();
+            let position = position(bracket, &index, array.len())?;
+            array[position] = value;
+            Ok(())
+        }
+        Value::Map(map) => {
+            map.borrow_mut().insert(key(bracket, &index)?, value);
+            Ok(())
+        }
+        object => Err(Error::Runtime(
+            bracket.span,
+            format!("the elements of a {} can not change", object.type_name()),
+            bracket.lexeme.clone(),
+        )),
+    }
+}
+
+/// The value of an attribute, a key of an associative array, a field or method of an object or a
+/// method of a class
+pub(crate) fn attribute(name: &Token, object: Value) -> Result<Value> {
+    match object {
+        Value::Map(map) => match map.borrow().get(&Key::String(name.lexeme.clone())) {
+            Some(value) => Ok(value.clone()),
+            None => other_attribute(name, Value::Map(map.clone())),
+        },
+        Value::Instance(instance) => {
+            if let Some(value) = instance.borrow().fields.get(&name.lexeme) {
+                return Ok(value.clone());
+            }
+            let method = instance.borrow().class.find_method(&name.lexeme);
+            match method {
+                Some(function) => Ok(Value::Method(gc::allocate(Method {
+                    receiver: Value::Instance(instance),
+                    function,
+                }))),
+                None => other_attribute(name, Value::Instance(instance)),
+            }
+        }
+        Value::Class(class) => match class.find_method(&name.lexeme) {
+            Some(function) => Ok(function),
+            None => other_attribute(name, Value::Class(class)),
+        },
+        object => other_attribute(name, object),
+    }
+}
+
+/// Change the value of a key of an associative array or of a field of an object
+pub(crate) fn set_attribute(name: &Token, object: Value, value: Value) -> Result<()> {
+    match object {
+        Value::Map(map) => {
+            map.borrow_mut()
+                .insert(Key::String(name.lexeme.clone()), value);
+            Ok(())
+        }
+        Value::Instance(instance) => {
+            instance
+                .borrow_mut()
+                .fields
+                .insert(name.lexeme.clone(), value);
+            Ok(())
+        }
+        object => Err(no_attribute(name, &object)),
+    }
+}
+
+/// The class that a class extends, if any, and the interfaces that it inherits
+pub(crate) type Inherited = (Option<Rc<Class>>, Vec<Rc<Interface>>);
+
+/// Split what a class inherits into the class that it extends and its interfaces, each value comes
+/// with the token of its name
+pub(crate) fn inherits(inherited: Vec<(&Token, Value)>) -> Result<Inherited> {
+    let mut superclass: Option<Rc<Class>> = None;
+    let mut interfaces = Vec::new();
+    for (name, value) in inherited {
+        match value {
+            Value::Class(_) if superclass.is_some() => {
+                return Err(Error::Runtime(
+                    name.span,
+                    "a class can only inherit from one class".to_string(),
+                    name.lexeme.clone(),
+                ))
+            }
+            Value::Class(other) => superclass = Some(other),
+            Value::Interface(interface) => interfaces.push(interface),
+            value => {
+                return Err(Error::Runtime(
+                    name.span,
+                    format!(
+                        "a class can only inherit from a class or an interface, not {}",
+                        value.type_name()
+                    ),
+                    name.lexeme.clone(),
+                ))
+            }
+        }
+    }
+    Ok((superclass, interfaces))
+}
+
+/// Create a class, the fields of its interfaces are set by the instances so a method can not take
+/// their place
+pub(crate) fn class(
+    name: &Token,
+    superclass: Option<Rc<Class>>,
+    interfaces: Vec<Rc<Interface>>,
+    methods: HashMap<String, Value>,
+) -> Result<Value> {
+    let class = Class {
+        name: name.lexeme.clone(),
+        superclass,
+        interfaces,
+        methods,
+    };
+    for interface in class.all_interfaces() {
+        for field in &interface.fields {
+            if class.find_method(&field.name).is_some() {
+                return Err(Error::Runtime(
+                    name.span,
+                    format!(
+                        "the field '{}' of {} can not be a method of {}",
+                        field.name, interface.name, class.name
+                    ),
+                    name.lexeme.clone(),
+                ));
+            }
+        }
+    }
+    Ok(Value::Class(gc::allocate(class)))
+}
+
+/// The type of a field of an interface, a type of the language or the class or interface in the
+/// variable with its name, if there is one
+pub(crate) fn field_type(name: &Token, value: Option<Value>) -> Result<Type> {
+    if let Some(builtin) = Type::builtin(&name.lexeme) {
+        return Ok(builtin);
+    }
+    match value.as_ref().and_then(Value::as_type) {
+        Some(Type::Builtin(_)) | None => Err(Error::Runtime(
+            name.span,
+            format!("unknown type '{}'", name.lexeme),
+            name.lexeme.clone(),
+        )),
+        Some(field_type) => Ok(field_type),
+    }
+}
+
+/// Check that a new instance set the fields of the interfaces of its class with the right types,
+/// the optional fields that it did not set are `null`
+pub(crate) fn check_fields(paren: &Token, instance: &Value) -> Result<()> {
+    let mut instance = match instance {
+        Value::Instance(instance) => instance.borrow_mut(),
+        _ => return Ok(()),
+    };
+    let class = instance.class.clone();
+    for interface in class.all_interfaces() {
+        for field in &interface.fields {
+            let msg = match instance.fields.get(&field.name) {
+                None if field.required => format!(
+                    "the {} object is missing the field '{}' of {}",
+                    class.name, field.name, interface.name
+                ),
+                None => {
+                    instance.fields.insert(field.name.clone(), Value::Null);
+                    continue;
+                }
+                Some(Value::Null) if !field.required => continue,
+                Some(value) if field.field_type.matches(value) => continue,
+                Some(value) => format!(
+                    "the field '{}' of {} must be {}, not {}",
+                    field.name,
+                    interface.name,
+                    field.field_type,
+                    value.type_name()
+                ),
+            };
+            return Err(Error::Runtime(paren.span, msg, paren.lexeme.clone()));
+        }
+    }
+    Ok(())
+}
+
+/// The class that `super` stands for, it only exists in the methods of a class that inherits
+pub(crate) fn superclass(keyword: &Token, value: Option<Value>) -> Result<Rc<Class>> {
+    match value {
+        Some(Value::Class(superclass)) => Ok(superclass),
+        _ => Err(Error::Runtime(
+            keyword.span,
+            "'super' can only be used in a class that inherits".to_string(),
+            keyword.lexeme.clone(),
+        )),
+    }
+}
+
+/// The method of the superclass bound to the `self` of the current method, for `super.name`
+pub(crate) fn super_method(name: &Token, superclass: Rc<Class>, receiver: Value) -> Result<Value> {
+    match superclass.find_method(&name.lexeme) {
+        Some(function) => Ok(Value::Method(gc::allocate(Method { receiver, function }))),
+        None => Err(no_attribute(name, &Value::Class(superclass))),
+    }
+}
+
+/// True if the value is an instance of the class of an instance pattern or of one that inherits
+/// from it, the token is the name of the class
+pub(crate) fn is_instance(name: &Token, class: Value, value: &Value) -> Result<bool> {
+    let class = match class {
+        Value::Class(class) => class,
+        other => {
+            return Err(Error::Runtime(
+                name.span,
+                format!("'{}' is a {}, not a class", name.lexeme, other.type_name()),
+                name.lexeme.clone(),
+            ))
+        }
+    };
+    Ok(match value {
+        Value::Instance(instance) => instance.borrow().class.is_subclass_of(&class),
+        _ => false,
+    })
+}
+
+/// The values that a `for each` goes through, the token is the one of the iterable for the errors
+pub(crate) fn iterate(token: &Token, iterable: Value) -> Result<Vec<Value>> {
+    match iterable {
+        Value::String(s) => Ok(s.chars().map(|c| Value::String(c.to_string())).collect()),
+        Value::Array(array) => Ok(array.borrow().clone()),
+        Value::Tuple(tuple) => Ok(tuple.as_ref().clone()),
+        Value::Map(map) => Ok(map.borrow().keys().map(Key::to_value).collect()),
+        value => Err(Error::Runtime(
+            token.span,
+            format!("cannot iterate over a value of type {}", value.type_name()),
+            token.lexeme.clone(),
+        )),
+    }
+}
+
+pub(crate) fn check_arity(paren: &Token, expected: usize, got: usize) -> Result<()> {
+    if expected == got {
+        return Ok(());
+    }
+    Err(Error::Runtime(
+        paren.span,
+        format!("expected {} arguments but got {}", expected, got),
+        paren.lexeme.clone(),
+    ))
+}
+
+/// Put the arguments in the parameters that they belong to, `None` for the parameters without
+/// arguments, that take their default value. The parameters are given by their names and if
+/// they have a default value, and the receiver of a method is the first one.
+pub(crate) fn bind<'a>(
+    name: &str,
+    params: impl Iterator<Item = (&'a str, bool)> + Clone,
+    paren: &Token,
+    receiver: Option<Value>,
+    arguments: Vec<Value>,
+    keywords: Vec<(&Token, Value)>,
+) -> Result<Vec<Option<Value>>> {
+    let count = params.clone().count();
+    // The `self` of a method is not counted in the messages about the arity
+    let bound = receiver.is_some() as usize;
+    if count < bound {
+        return Err(Error::Runtime(
+            paren.span,
+            "a method needs the parameter 'self'".to_string(),
+            name.to_string(),
+        ));
+    }
+    // The functions without default values keep the simple message about the arity
+    let defaults = params.clone().any(|(_, default)| default);
+    if arguments.len() + bound > count || (!defaults && keywords.is_empty()) {
+        check_arity(paren, count - bound, arguments.len())?;
+    }
+
+    let mut values: Vec<Option<Value>> = receiver.into_iter().chain(arguments).map(Some).collect();
+    values.resize(count, None);
+    for (name, value) in keywords {
+        let position = params.clone().position(|(param, _)| param == name.lexeme);
+        match position {
+            Some(position) if values[position].is_none() => values[position] = Some(value),
+            Some(_) => {
+                return Err(Error::Runtime(
+                    name.span,
+                    format!("the argument '{}' was given twice", name.lexeme),
+                    name.lexeme.clone(),
+                ))
+            }
+            None => {
+                return Err(Error::Runtime(
+                    name.span,
+                    format!("the function has no parameter '{}'", name.lexeme),
+                    name.lexeme.clone(),
+                ))
+            }
+        }
+    }
+
+    if let Some(((param, _), _)) = params
+        .zip(&values)
+        .find(|((_, default), value)| value.is_none() && !default)
+    {
+        return Err(Error::Runtime(
+            paren.span,
+            format!("missing the argument '{}'", param),
+            paren.lexeme.clone(),
+        ));
+    }
+    Ok(values)
+}
+
+pub(crate) fn not_callable(paren: &Token, value: &Value) -> Error {
+    Error::Runtime(
+        paren.span,
+        format!("a value of type {} can not be called", value.type_name()),
+        paren.lexeme.clone(),
+    )
+}
+
+pub(crate) fn type_error(operator: &Token, expected: &str, got: &Value) -> Error {
+    Error::Runtime(
+        operator.span,
+        format!(
+            "the operand of '{}' must be {}, not {}",
+            operator.lexeme,
+            expected,
+            got.type_name()
+        ),
+        operator.lexeme.clone(),
+    )
+}
+
+/// True if the value is an element of the array or tuple, a key of the associative array or a
+/// part of the string
+pub(crate) fn contains(operator: &Token, container: &Value, value: &Value) -> Result<Value> {
+    let found = match (container, value) {
+        (Value::Array(array), _) => array.borrow().contains(value),
+        (Value::Tuple(tuple), _) => tuple.contains(value),
+        (Value::Map(map), _) => {
+            Key::from_value(value).is_some_and(|key| map.borrow().contains_key(&key))
+        }
+        (Value::String(s), Value::String(part)) => s.contains(part.as_str()),
+        (Value::String(_), value) => return Err(type_error(operator, "a string", value)),
+        (container, _) => {
+            return Err(type_error(
+                operator,
+                "an array, an object, a tuple or a string",
+                container,
+            ))
+        }
+    };
+    Ok(Value::Boolean(found))
+}
+
+/// The position of an index in a list of `len` elements, the negative indexes count from the end
+pub(crate) fn position(bracket: &Token, index: &Value, len: usize) -> Result<usize> {
+    let n = match *index {
+        // The indexes that do not fit in 64 bits are out of range anyway
+        Value::Number(ref n) if n.is_whole() => n.to_i64().unwrap_or(i64::MAX),
+        ref index => {
+            return Err(Error::Runtime(
+                bracket.span,
+                format!(
+                    "the index must be a whole number, not {}",
+                    index.type_name()
+                ),
+                bracket.lexeme.clone(),
+            ))
+        }
+    };
+    let position = if n < 0 {
+        n.saturating_add(len as i64)
+    } else {
+        n
+    };
+    if position < 0 || position >= len as i64 {
+        return Err(Error::Runtime(
+            bracket.span,
+            format!("the index {} is out of range for {} elements", index, len),
+            bracket.lexeme.clone(),
+        ));
+    }
+    Ok(position as usize)
+}
+
+/// The key of an associative array for a value, only the values that can not change are keys
+pub(crate) fn key(token: &Token, value: &Value) -> Result<Key> {
+    Key::from_value(value).ok_or_else(|| {
+        Error::Runtime(
+            token.span,
+            format!("a value of type {} can not be a key", value.type_name()),
+            token.lexeme.clone(),
+        )
+    })
+}
+
+/// The number that an operator gives, or an error where the operator is if it can not be
+/// computed, like a division by zero
+fn arithmetic(operator: &Token, result: Arithmetic) -> Result<Number> {
+    result.map_err(|message| {
+        Error::Runtime(operator.span, message.to_string(), operator.lexeme.clone())
+    })
+}
+
+pub(crate) fn unknown_operator(operator: &Token) -> Error {
+    Error::Runtime(
+        operator.span,
+        "unknown operator".to_string(),
+        operator.lexeme.clone(),
+    )
+}
+
+/// The attributes that every value has, `type` gives the type of the value
+pub(crate) fn other_attribute(name: &Token, object: Value) -> Result<Value> {
+    match name.lexeme.as_str() {
+        "type" => Ok(object.type_of()),
+        _ => Err(no_attribute(name, &object)),
+    }
+}
+
+pub(crate) fn no_attribute(name: &Token, object: &Value) -> Error {
+    let msg = match object {
+        Value::Instance(instance) => format!(
+            "the {} object has no attribute '{}'",
+            instance.borrow().class.name,
+            name.lexeme
+        ),
+        Value::Class(class) => format!("the class {} has no method '{}'", class.name, name.lexeme),
+        object => format!(
+            "a value of type {} has no attribute '{}'",
+            object.type_name(),
+            name.lexeme
+        ),
+    };
+    Error::Runtime(name.span, msg, name.lexeme.clone())
+}
+
+/// A writer that keeps what `print` writes, so the tests can look at it
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct Output(Rc<RefCell<Vec<u8>>>);
+
+#[cfg(test)]
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl Output {
+    pub(crate) fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
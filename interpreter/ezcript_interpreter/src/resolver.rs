@@ -0,0 +1,514 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use super::builtins;
+use super::value::BUILTIN_TYPES;
+use ezcript_lexer::tokens::{Token, TokenKind};
+use ezcript_parser::ast::{Expr, Function, If, Match, Pattern, Slot, Stmt};
+use ezcript_result::{Error, Warning};
+
+/// What a name of a scope was declared as, only the variables and parameters are reported when
+/// they are never used
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Variable,
+    Parameter,
+    /// A function, class or interface
+    Declaration,
+}
+
+/// A name declared in a scope
+#[derive(Debug)]
+struct Local {
+    name: Token,
+    kind: Kind,
+    /// False while its value is being evaluated, it can not be read then
+    ready: bool,
+    used: bool,
+}
+
+/// The names of a block, in the same order in which the interpreter creates them
+#[derive(Debug, Default)]
+struct Scope {
+    slots: HashMap<String, usize>,
+    locals: Vec<Local>,
+}
+
+/// A pass over the syntax tree before it is executed. Every local variable is bound to the slot
+/// where the interpreter keeps it, and the mistakes that do not need to run the code are found:
+/// reading a variable in its own initializer, a `return` outside of a function, a `break` outside
+/// of a loop and the variables and parameters that are never used.
+///
+/// The variables of the top level are not resolved, they are looked up by name, so the entries of
+/// the prompt can use the ones of the entries before them.
+#[derive(Debug)]
+pub struct Resolver {
+    scopes: Vec<Scope>,
+    /// The names of the top level that already have a value, with the builtins
+    globals: HashSet<String>,
+    /// The variable of the top level whose value is being resolved, if it is new
+    initializing: Option<String>,
+    /// How many functions and `if` or `match` values contain the code, a `return` needs one
+    returns: usize,
+    /// How many loops contain the code inside of the current function
+    loops: usize,
+    /// The names that were used without being declared before, a function can use a variable
+    /// that is declared after it
+    unresolved: HashSet<String>,
+    errors: Vec<Error>,
+    warnings: Vec<Warning>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        let builtins = builtins::builtins().into_iter().map(|builtin| builtin.name);
+        Resolver {
+            scopes: Vec::new(),
+            globals: builtins
+                .chain(BUILTIN_TYPES.iter().copied())
+                .map(String::from)
+                .collect(),
+            initializing: None,
+            returns: 0,
+            loops: 0,
+            unresolved: HashSet::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Add the variables that the top level already has, like the ones of the entries of the
+    /// prompt before this one
+    pub fn with_globals(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.globals.extend(names);
+        self
+    }
+
+    /// Resolve the variables of the whole program, all the errors that are found are given back
+    pub fn resolve(&mut self, statements: &[Stmt]) -> Vec<Error> {
+        self.resolve_block(statements);
+        self.warnings
+            .sort_by_key(|warning| (warning.span.line, warning.span.column));
+        mem::take(&mut self.errors)
+    }
+
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    fn resolve_block(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.resolve_stmt(statement);
+        }
+    }
+
+    /// Resolve a block in a scope of its own, like the interpreter executes it
+    fn resolve_scoped(&mut self, statements: &[Stmt]) {
+        self.begin_scope();
+        self.resolve_block(statements);
+        self.end_scope();
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression(expr) => self.resolve_expr(expr),
+            Stmt::Set(name, _, value) => {
+                let initializing = self.begin_global(name);
+                self.declare(name, Kind::Variable);
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+                self.define(name);
+                self.initializing = initializing;
+            }
+            Stmt::Const(name, _, value) => {
+                let initializing = self.begin_global(name);
+                self.declare(name, Kind::Variable);
+                self.resolve_expr(value);
+                self.define(name);
+                self.initializing = initializing;
+            }
+            Stmt::Assign(target, operator, value) => {
+                self.resolve_expr(value);
+                match target {
+                    // Saving a value is not a use of the variable, `+=` reads it
+                    Expr::Variable(name, slot) => {
+                        slot.set(self.find(name, operator.kind != TokenKind::Equal));
+                    }
+                    target => self.resolve_expr(target),
+                }
+            }
+            Stmt::Increment(target, _) => self.resolve_expr(target),
+            Stmt::If(if_body) => self.resolve_if(if_body),
+            Stmt::Match(match_body) => self.resolve_match(match_body),
+            Stmt::While(condition, body) | Stmt::DoWhile(body, condition) => {
+                self.resolve_expr(condition);
+                self.loops += 1;
+                self.resolve_scoped(body);
+                self.loops -= 1;
+            }
+            Stmt::ForEach(name, iterable, body) => {
+                self.resolve_expr(iterable);
+                self.loops += 1;
+                self.begin_scope();
+                self.declare(name, Kind::Variable);
+                self.define(name);
+                self.resolve_block(body);
+                self.end_scope();
+                self.loops -= 1;
+            }
+            Stmt::Func(function) => {
+                self.declare(&function.name, Kind::Declaration);
+                self.define(&function.name);
+                self.resolve_function(function);
+            }
+            Stmt::Class(class) => {
+                self.declare(&class.name, Kind::Declaration);
+                self.define(&class.name);
+                // The scope of `super`, the interpreter creates it for every class
+                self.begin_scope();
+                for method in &class.methods {
+                    self.resolve_function(method);
+                }
+                self.end_scope();
+            }
+            Stmt::Interface(interface) => {
+                self.declare(&interface.name, Kind::Declaration);
+                self.define(&interface.name);
+            }
+            Stmt::Return(keyword, value) => {
+                if self.returns == 0 {
+                    self.error(keyword, "'return' outside of a function");
+                }
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            Stmt::Break(keyword) => {
+                if self.loops == 0 {
+                    self.error(keyword, "'break' outside of a loop");
+                }
+            }
+        }
+    }
+
+    fn resolve_if(&mut self, if_body: &If) {
+        for (condition, body) in &if_body.branches {
+            self.resolve_expr(condition);
+            self.resolve_scoped(body);
+        }
+        if let Some(ref body) = if_body.otherwise {
+            self.resolve_scoped(body);
+        }
+    }
+
+    /// The variables of a pattern are in the scope of its arm, with the guard and the body
+    fn resolve_match(&mut self, match_body: &Match) {
+        self.resolve_expr(&match_body.subject);
+        for arm in &match_body.arms {
+            self.begin_scope();
+            self.declare_bindings(&arm.pattern);
+            if let Some(ref guard) = arm.guard {
+                self.resolve_expr(guard);
+            }
+            self.resolve_block(&arm.body);
+            self.end_scope();
+        }
+    }
+
+    /// Declare the variables of a pattern in the order in which the interpreter saves them
+    fn declare_bindings(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Binding(name) => {
+                self.declare(name, Kind::Variable);
+                self.define(name);
+            }
+            Pattern::Array(_, patterns) | Pattern::Tuple(_, patterns) => {
+                for pattern in patterns {
+                    self.declare_bindings(pattern);
+                }
+            }
+            Pattern::Instance(_, fields) => {
+                for (_, pattern) in fields {
+                    self.declare_bindings(pattern);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Literal(_) | Pattern::Range(_, _, _) => (),
+        }
+    }
+
+    /// The parameters and the body of a function share a scope, a `break` of the body can not
+    /// stop a loop outside of it
+    fn resolve_function(&mut self, function: &Function) {
+        // The function runs after the variable that it is saved in has its value
+        let initializing = self.initializing.take();
+        let loops = mem::replace(&mut self.loops, 0);
+        self.returns += 1;
+        self.begin_scope();
+        for param in &function.params {
+            // The default values can use the parameters before them
+            if let Some(ref default) = param.default {
+                self.resolve_expr(default);
+            }
+            self.declare(&param.name, Kind::Parameter);
+            self.define(&param.name);
+        }
+        self.resolve_block(&function.body);
+        self.end_scope();
+        self.returns -= 1;
+        self.loops = loops;
+        self.initializing = initializing;
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) | Expr::Super(_, _) => (),
+            Expr::Variable(name, slot) => slot.set(self.find(name, true)),
+            Expr::Grouping(expr) | Expr::Unary(_, expr) | Expr::Get(expr, _) => {
+                self.resolve_expr(expr)
+            }
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Index(left, _, right) => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            Expr::Call(callee, _, arguments) => {
+                self.resolve_expr(callee);
+                for argument in arguments {
+                    self.resolve_expr(&argument.value);
+                }
+            }
+            Expr::Array(_, items) | Expr::Tuple(_, items) => {
+                for item in items {
+                    self.resolve_expr(item);
+                }
+            }
+            Expr::Map(_, entries) => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            Expr::Function(function) => self.resolve_function(function),
+            // A `return` inside of them gives their value
+            Expr::If(if_body) => {
+                self.returns += 1;
+                self.resolve_if(if_body);
+                self.returns -= 1;
+            }
+            Expr::Match(match_body) => {
+                self.returns += 1;
+                self.resolve_match(match_body);
+                self.returns -= 1;
+            }
+        }
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    /// Close the innermost scope, its variables and parameters that were never used are warned
+    /// about
+    fn end_scope(&mut self) {
+        let scope = match self.scopes.pop() {
+            Some(scope) => scope,
+            None => return,
+        };
+        for local in scope.locals {
+            let what = match local.kind {
+                Kind::Variable => "variable",
+                Kind::Parameter => "parameter",
+                Kind::Declaration => continue,
+            };
+            let name = &local.name.lexeme;
+            if local.used || name == "self" || name.starts_with('_') {
+                continue;
+            }
+            if self.unresolved.contains(name) {
+                continue;
+            }
+            let msg = format!("the {} '{}' is never used", what, name);
+            self.warnings.push(Warning::new(local.name.span, &msg));
+        }
+    }
+
+    /// Start the value of a variable, a new one of the top level can not be read in it. The
+    /// variable whose value was being resolved before is given back.
+    fn begin_global(&mut self, name: &Token) -> Option<String> {
+        let new = self.scopes.is_empty() && !self.globals.contains(&name.lexeme);
+        match new {
+            true => self.initializing.replace(name.lexeme.clone()),
+            false => self.initializing.clone(),
+        }
+    }
+
+    /// Add the name to the innermost scope, it can not be read until it is defined. A name that
+    /// is declared again keeps its slot, like in the interpreter.
+    fn declare(&mut self, name: &Token, kind: Kind) {
+        let scope = match self.scopes.last_mut() {
+            Some(scope) => scope,
+            None => {
+                self.globals.insert(name.lexeme.clone());
+                return;
+            }
+        };
+        match scope.slots.get(&name.lexeme) {
+            // The value of the variable before is still there while the new one is evaluated
+            Some(&slot) => scope.locals[slot].kind = kind,
+            None => {
+                scope.slots.insert(name.lexeme.clone(), scope.locals.len());
+                scope.locals.push(Local {
+                    name: name.clone(),
+                    kind,
+                    ready: false,
+                    used: false,
+                });
+            }
+        }
+    }
+
+    fn define(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            if let Some(&slot) = scope.slots.get(&name.lexeme) {
+                scope.locals[slot].ready = true;
+            }
+        }
+    }
+
+    /// The slot of the variable in the innermost scope that has it, `None` if it is a global or
+    /// it is not declared yet
+    fn find(&mut self, name: &Token, read: bool) -> Option<Slot> {
+        let found = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .enumerate()
+            .find_map(|(depth, scope)| {
+                let index = *scope.slots.get(&name.lexeme)?;
+                Some((depth, index, &mut scope.locals[index]))
+            });
+        let (depth, index, local) = match found {
+            Some(found) => found,
+            None => {
+                if read && self.initializing.as_ref() == Some(&name.lexeme) {
+                    let msg = format!("can not read '{}' in its own initializer", name.lexeme);
+                    self.error(name, &msg);
+                }
+                self.unresolved.insert(name.lexeme.clone());
+                return None;
+            }
+        };
+
+        let unready = read && !local.ready;
+        local.used |= read;
+        if unready {
+            let msg = format!("can not read '{}' in its own initializer", name.lexeme);
+            self.error(name, &msg);
+        }
+        Some(Slot { depth, index })
+    }
+
+    fn error(&mut self, token: &Token, msg: &str) {
+        self.errors.push(Error::Parse(
+            token.span,
+            msg.to_string(),
+            token.lexeme.clone(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::lexer::Lexer;
+    use ezcript_parser::parser::Parser;
+
+    fn resolve(source: &str) -> (Vec<Stmt>, Vec<String>, Vec<String>) {
+        let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut resolver = Resolver::new();
+        let errors = resolver.resolve(&statements);
+        let errors = errors.iter().map(|err| err.to_string()).collect();
+        let warnings = resolver.warnings().iter().map(|w| w.to_string()).collect();
+        (statements, errors, warnings)
+    }
+
+    #[test]
+    fn test_slots() {
+        let (statements, errors, warnings) = resolve(
+            "set g = 1\nfunc f(a, b) do\n    set c = a\n    return func() do return b + c + g",
+        );
+        assert!(errors.is_empty() && warnings.is_empty());
+
+        let body = match &statements[1] {
+            Stmt::Func(function) => &function.body,
+            _ => unreachable!(),
+        };
+        let closure = match &body[1] {
+            Stmt::Return(_, Some(Expr::Function(closure))) => closure,
+            _ => unreachable!(),
+        };
+        let slot = |expr: &Expr| match expr {
+            Expr::Variable(_, slot) => slot.get(),
+            _ => unreachable!(),
+        };
+        match &closure.body[0] {
+            Stmt::Return(_, Some(Expr::Binary(left, _, g))) => {
+                let (b, c) = match left.as_ref() {
+                    Expr::Binary(b, _, c) => (b, c),
+                    _ => unreachable!(),
+                };
+                assert_eq!(slot(b), Some(Slot { depth: 1, index: 1 }));
+                assert_eq!(slot(c), Some(Slot { depth: 1, index: 2 }));
+                assert_eq!(slot(g), None);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_errors_and_warnings() {
+        let (_, errors, warnings) = resolve(
+            "return 1\nbreak\nfunc f(x, unused, _ignored) do\n    set y = y\n    \
+             while x do break\n    return x\nset v = if true do return 1",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Parse Error [line 1] 'return' outside of a function: near return",
+                "Parse Error [line 2] 'break' outside of a loop: near break",
+                "Parse Error [line 4] can not read 'y' in its own initializer: near y",
+            ]
+        );
+        assert_eq!(
+            warnings,
+            vec!["Warning [line 3] the parameter 'unused' is never used"]
+        );
+
+        // The top level is checked too, but not its variables that already have a value
+        let (_, errors, _) = resolve(
+            "set a = a\nconst c = [1, c]\nset b = 1\nset b = b + 1\nset print = print\n\
+             set f = func(n) do return f(n - 1)\nset v = if true do return v",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Parse Error [line 1] can not read 'a' in its own initializer: near a",
+                "Parse Error [line 2] can not read 'c' in its own initializer: near c",
+                "Parse Error [line 7] can not read 'v' in its own initializer: near v",
+            ]
+        );
+        let tokens = Lexer::new("set x = x + 1".chars()).tokenize().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        let mut resolver = Resolver::new().with_globals(vec!["x".to_string()]);
+        assert!(resolver.resolve(&statements).is_empty());
+    }
+}
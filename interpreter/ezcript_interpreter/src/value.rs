@@ -0,0 +1,524 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+
+use super::builtins::Host;
+use super::environment::Environment;
+use super::gc;
+use super::vm;
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::{Literal, Token};
+use ezcript_parser::ast::Function;
+use ezcript_result::Result;
+
+/// A value that the interpreter works with at runtime
+#[derive(Debug, Clone)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    Number(Number),
+    String(String),
+    /// A function declared in the script
+    Function(Rc<Closure>),
+    /// A function compiled to bytecode, that the VM runs
+    Compiled(Rc<vm::Closure>),
+    /// A function that comes with the interpreter, like `print`
+    Builtin(Rc<Builtin>),
+    /// A list of values that can change, like `[1, 2, 3]`
+    Array(Rc<RefCell<Vec<Value>>>),
+    /// An associative array, like `{ name: "Ana" }`, it keeps the order of its keys
+    Map(Rc<RefCell<IndexMap<Key, Value>>>),
+    /// A list of values that can not change, like `(1, 2)`
+    Tuple(Rc<Vec<Value>>),
+    /// A class, calling it creates an instance
+    Class(Rc<Class>),
+    /// An object created by a class
+    Instance(Rc<RefCell<Instance>>),
+    /// A method together with the instance that it was taken from
+    Method(Rc<Method>),
+    /// The fields that the classes that inherit it must set
+    Interface(Rc<Interface>),
+    /// A type of the language, like `string`, the value of `value.type`
+    Type(Type),
+}
+
+impl Value {
+    /// `null`, `false`, `0` and `""` are false, everything else is true
+    pub fn is_truthy(&self) -> bool {
+        match *self {
+            Value::Null => false,
+            Value::Boolean(b) => b,
+            Value::Number(ref n) => !n.is_zero(),
+            Value::String(ref s) => !s.is_empty(),
+            Value::Function(_) | Value::Compiled(_) | Value::Builtin(_) | Value::Method(_) => true,
+            Value::Class(_) | Value::Instance(_) | Value::Interface(_) | Value::Type(_) => true,
+            Value::Array(ref array) => !array.borrow().is_empty(),
+            Value::Map(ref map) => !map.borrow().is_empty(),
+            Value::Tuple(ref tuple) => !tuple.is_empty(),
+        }
+    }
+
+    pub fn array(values: Vec<Value>) -> Self {
+        Value::Array(gc::allocate(RefCell::new(values)))
+    }
+
+    pub fn map(entries: IndexMap<Key, Value>) -> Self {
+        Value::Map(gc::allocate(RefCell::new(entries)))
+    }
+
+    pub fn tuple(values: Vec<Value>) -> Self {
+        Value::Tuple(gc::allocate(values))
+    }
+
+    /// The type of the value, its class if it is an instance
+    pub fn type_of(&self) -> Value {
+        match *self {
+            Value::Instance(ref instance) => Value::Class(instance.borrow().class.clone()),
+            _ => Value::Type(Type::Builtin(self.type_name())),
+        }
+    }
+
+    /// The type that the value stands for, if it is one, like the right side of `is`
+    pub fn as_type(&self) -> Option<Type> {
+        match *self {
+            Value::Type(ref value_type) => Some(value_type.clone()),
+            Value::Class(ref class) => Some(Type::Class(class.clone())),
+            Value::Interface(ref interface) => Some(Type::Interface(interface.clone())),
+            _ => None,
+        }
+    }
+
+    /// The name of a function of the script, declared or compiled, `None` if it is anonymous
+    pub fn function_name(&self) -> Option<&str> {
+        match *self {
+            Value::Function(ref closure) => closure.declaration.name(),
+            Value::Compiled(ref closure) => closure.prototype.name.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The names that can follow a `.` after the value, for the completion of the prompt
+    pub fn members(&self) -> Vec<String> {
+        match *self {
+            Value::Map(ref map) => map
+                .borrow()
+                .keys()
+                .filter_map(|key| match key {
+                    Key::String(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+            Value::Class(ref class) => class.method_names(),
+            Value::Interface(ref interface) => interface
+                .fields
+                .iter()
+                .map(|field| field.name.clone())
+                .collect(),
+            Value::Instance(ref instance) => {
+                let instance = instance.borrow();
+                let mut names: Vec<String> = instance.fields.keys().cloned().collect();
+                names.extend(instance.class.method_names());
+                names
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The name of the type of the value, as the user sees it
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Null => "null",
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Function(_) | Value::Compiled(_) | Value::Builtin(_) | Value::Method(_) => {
+                "function"
+            }
+            Value::Class(_) => "class",
+            Value::Instance(_) => "instance",
+            Value::Interface(_) => "interface",
+            Value::Type(_) => "type",
+            Value::Array(_) => "array",
+            Value::Map(_) => "object",
+            Value::Tuple(_) => "tuple",
+        }
+    }
+
+    /// Write the value as it is written in the code, the strings inside of other values have
+    /// quotes. The containers that are being written are in `seen`, to cut the cycles.
+    fn write(&self, f: &mut fmt::Formatter, quoted: bool, seen: &mut Vec<usize>) -> fmt::Result {
+        let address = match *self {
+            Value::Array(ref array) => Rc::as_ptr(array) as *const () as usize,
+            Value::Map(ref map) => Rc::as_ptr(map) as *const () as usize,
+            Value::Tuple(ref tuple) => Rc::as_ptr(tuple) as *const () as usize,
+            Value::Instance(ref instance) => Rc::as_ptr(instance) as *const () as usize,
+            Value::String(ref s) if quoted => return write!(f, "{:?}", s),
+            _ => return write!(f, "{}", self),
+        };
+        if seen.contains(&address) {
+            return match *self {
+                Value::Array(_) => write!(f, "[...]"),
+                Value::Map(_) => write!(f, "{{...}}"),
+                Value::Instance(ref instance) => {
+                    write!(f, "{} {{...}}", instance.borrow().class.name)
+                }
+                _ => write!(f, "(...)"),
+            };
+        }
+        seen.push(address);
+
+        let result = match *self {
+            Value::Array(ref array) => write_list(f, "[", "]", array.borrow().iter(), seen),
+            Value::Tuple(ref tuple) if tuple.len() == 1 => {
+                write!(f, "(")?;
+                tuple[0].write(f, true, seen)?;
+                write!(f, ",)")
+            }
+            Value::Tuple(ref tuple) => write_list(f, "(", ")", tuple.iter(), seen),
+            Value::Map(ref map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    key.to_value().write(f, true, seen)?;
+                    write!(f, ": ")?;
+                    value.write(f, true, seen)?;
+                }
+                write!(f, "}}")
+            }
+            Value::Instance(ref instance) => {
+                let instance = instance.borrow();
+                write!(f, "{} {{", instance.class.name)?;
+                for (i, (name, value)) in instance.fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    value.write(f, true, seen)?;
+                }
+                write!(f, "}}")
+            }
+            _ => Ok(()),
+        };
+        seen.pop();
+        result
+    }
+}
+
+fn write_list<'a>(
+    f: &mut fmt::Formatter,
+    open: &str,
+    close: &str,
+    values: impl Iterator<Item = &'a Value>,
+    seen: &mut Vec<usize>,
+) -> fmt::Result {
+    write!(f, "{}", open)?;
+    for (i, value) in values.enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        value.write(f, true, seen)?;
+    }
+    write!(f, "{}", close)
+}
+
+/// A value that can be a key of an associative array: the values that can not change
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub enum Key {
+    Null,
+    Boolean(bool),
+    /// The numbers with the same value are the same key, like `1` and `1.0`
+    Number(Number),
+    String(String),
+    Tuple(Vec<Key>),
+}
+
+// A key is never `NaN`, so every key is equal to itself
+impl Eq for Key {}
+
+impl Key {
+    /// The key of a value, `None` if the value can change or is not a number (`NaN`)
+    pub fn from_value(value: &Value) -> Option<Key> {
+        match *value {
+            Value::Null => Some(Key::Null),
+            Value::Boolean(b) => Some(Key::Boolean(b)),
+            Value::Number(ref n) if n.is_nan() => None,
+            Value::Number(ref n) => Some(Key::Number(n.clone())),
+            Value::String(ref s) => Some(Key::String(s.clone())),
+            Value::Tuple(ref tuple) => tuple
+                .iter()
+                .map(Key::from_value)
+                .collect::<Option<_>>()
+                .map(Key::Tuple),
+            _ => None,
+        }
+    }
+
+    pub fn to_value(&self) -> Value {
+        match *self {
+            Key::Null => Value::Null,
+            Key::Boolean(b) => Value::Boolean(b),
+            Key::Number(ref n) => Value::Number(n.clone()),
+            Key::String(ref s) => Value::String(s.clone()),
+            Key::Tuple(ref keys) => Value::tuple(keys.iter().map(Key::to_value).collect()),
+        }
+    }
+}
+
+impl From<&Literal> for Value {
+    fn from(literal: &Literal) -> Self {
+        match *literal {
+            Literal::Null => Value::Null,
+            Literal::Boolean(b) => Value::Boolean(b),
+            Literal::Number(ref n) => Value::Number(n.clone()),
+            Literal::String(ref s) => Value::String(s.clone()),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Compiled(a), Value::Compiled(b)) => Rc::ptr_eq(a, b),
+            (Value::Builtin(a), Value::Builtin(b)) => Rc::ptr_eq(a, b),
+            (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b) || *a.borrow() == *b.borrow(),
+            (Value::Tuple(a), Value::Tuple(b)) => a == b,
+            (Value::Class(a), Value::Class(b)) => Rc::ptr_eq(a, b),
+            (Value::Instance(a), Value::Instance(b)) => Rc::ptr_eq(a, b),
+            (Value::Interface(a), Value::Interface(b)) => Rc::ptr_eq(a, b),
+            (Value::Type(a), Value::Type(b)) => a == b,
+            (Value::Method(a), Value::Method(b)) => {
+                a.receiver == b.receiver && a.function == b.function
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "null"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Number(ref n) => write!(f, "{}", n),
+            Value::String(ref s) => write!(f, "{}", s),
+            Value::Function(_) | Value::Compiled(_) => match self.function_name() {
+                Some(name) => write!(f, "<func {}>", name),
+                None => write!(f, "<func>"),
+            },
+            Value::Builtin(ref builtin) => write!(f, "<builtin {}>", builtin.name),
+            Value::Class(ref class) => write!(f, "<class {}>", class.name),
+            Value::Interface(ref interface) => write!(f, "<interface {}>", interface.name),
+            Value::Type(ref value_type) => write!(f, "<type {}>", value_type),
+            Value::Method(ref method) => match method.function.function_name() {
+                Some(name) => write!(f, "<method {}>", name),
+                None => write!(f, "<method>"),
+            },
+            Value::Array(_) | Value::Map(_) | Value::Tuple(_) | Value::Instance(_) => {
+                self.write(f, false, &mut Vec::new())
+            }
+        }
+    }
+}
+
+/// A function declared in the script together with the environment where it was declared
+pub struct Closure {
+    pub declaration: Rc<Function>,
+    pub closure: Rc<RefCell<Environment>>,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The environment is not printed, it can contain the closure itself
+        write!(f, "Closure({})", self.declaration.name.lexeme)
+    }
+}
+
+/// A class declared in the script
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    pub superclass: Option<Rc<Class>>,
+    pub interfaces: Vec<Rc<Interface>>,
+    /// The functions of the methods, declared in the script or compiled for the VM
+    pub methods: HashMap<String, Value>,
+}
+
+impl Class {
+    /// Look for the method in the class and then in the ones that it inherits
+    pub fn find_method(&self, name: &str) -> Option<Value> {
+        match self.methods.get(name) {
+            Some(method) => Some(method.clone()),
+            None => self.superclass.as_ref()?.find_method(name),
+        }
+    }
+
+    /// The names of the methods of the class and of the ones that it inherits, sorted
+    pub fn method_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.methods.keys().cloned().collect();
+        if let Some(ref superclass) = self.superclass {
+            names.extend(superclass.method_names());
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// True if the class is the other one or inherits from it
+    pub fn is_subclass_of(&self, other: &Class) -> bool {
+        std::ptr::eq(self, other)
+            || self
+                .superclass
+                .as_ref()
+                .is_some_and(|superclass| superclass.is_subclass_of(other))
+    }
+
+    /// The interfaces of the class and of the ones that it inherits
+    pub fn all_interfaces(&self) -> Vec<Rc<Interface>> {
+        let mut interfaces = match self.superclass {
+            Some(ref superclass) => superclass.all_interfaces(),
+            None => Vec::new(),
+        };
+        interfaces.extend(self.interfaces.iter().cloned());
+        interfaces
+    }
+}
+
+/// An interface declared in the script
+#[derive(Debug)]
+pub struct Interface {
+    pub name: String,
+    pub fields: Vec<Field>,
+}
+
+/// A field of an interface, the optional ones are `null` when the instance does not set them
+#[derive(Debug)]
+pub struct Field {
+    pub name: String,
+    pub field_type: Type,
+    pub required: bool,
+}
+
+/// The types that the values can have, in lowercase
+pub const BUILTIN_TYPES: [&str; 15] = [
+    "any",
+    "null",
+    "boolean",
+    "number",
+    "integer",
+    "float",
+    "string",
+    "function",
+    "array",
+    "object",
+    "tuple",
+    "instance",
+    "class",
+    "interface",
+    "type",
+];
+
+/// A type that a value can be checked against, like the one of a field of an interface
+#[derive(Debug, Clone)]
+pub enum Type {
+    /// A type of the language, like `string` or `integer`
+    Builtin(&'static str),
+    /// The instances of the class or of the ones that inherit from it
+    Class(Rc<Class>),
+    /// The instances of the classes that inherit the interface
+    Interface(Rc<Interface>),
+}
+
+impl Type {
+    /// The type of the language with the name, in any case, like `String` or `integer`
+    pub fn builtin(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        BUILTIN_TYPES
+            .iter()
+            .find(|builtin| **builtin == name)
+            .map(|builtin| Type::Builtin(builtin))
+    }
+
+    /// True if the value has the type
+    pub fn matches(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Type::Builtin("any"), _) => true,
+            (Type::Builtin("integer"), Value::Number(n)) => n.is_integer(),
+            (Type::Builtin("float"), Value::Number(n)) => !n.is_integer(),
+            (Type::Builtin(name), value) => *name == value.type_name(),
+            (Type::Class(class), Value::Instance(instance)) => {
+                instance.borrow().class.is_subclass_of(class)
+            }
+            (Type::Interface(interface), Value::Instance(instance)) => instance
+                .borrow()
+                .class
+                .all_interfaces()
+                .iter()
+                .any(|other| Rc::ptr_eq(other, interface)),
+            _ => false,
+        }
+    }
+}
+
+impl PartialEq for Type {
+    fn eq(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Builtin(a), Type::Builtin(b)) => a == b,
+            (Type::Class(a), Type::Class(b)) => Rc::ptr_eq(a, b),
+            (Type::Interface(a), Type::Interface(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Builtin(name) => write!(f, "{}", name),
+            Type::Class(class) => write!(f, "{}", class.name),
+            Type::Interface(interface) => write!(f, "{}", interface.name),
+        }
+    }
+}
+
+/// An object created by a class, with the attributes that its methods set with `self`
+#[derive(Debug)]
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: IndexMap<String, Value>,
+}
+
+/// A method taken from an instance, the instance is the first argument when it is called
+#[derive(Debug)]
+pub struct Method {
+    pub receiver: Value,
+    pub function: Value,
+}
+
+/// The signature of the functions that come with the interpreter, the token is the closing
+/// parenthesis of the call, for the errors
+pub type BuiltinFn = fn(&mut dyn Host, &Token, Vec<Value>) -> Result<Value>;
+
+/// A function that comes with the interpreter
+pub struct Builtin {
+    pub name: &'static str,
+    /// How many arguments it needs, `None` if it accepts any number of them
+    pub arity: Option<usize>,
+    pub function: BuiltinFn,
+}
+
+impl fmt::Debug for Builtin {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Builtin({})", self.name)
+    }
+}
@@ -0,0 +1,907 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+
+use super::builtins::{self, Host};
+use super::chunk::{Constant, Op, Prototype};
+use super::environment::Environment;
+use super::gc;
+use super::operations::{self, check_arity, MAX_DEPTH};
+use super::value::{Field, Instance, Interface, Key, Type, Value, BUILTIN_TYPES};
+use ezcript_lexer::number::Number;
+use ezcript_lexer::tokens::Token;
+use ezcript_result::{Error, Result, Span};
+
+/// A variable captured by a closure, it points to a local slot while the slot exists
+#[derive(Debug)]
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+/// A compiled function together with the variables that it captured
+pub struct Closure {
+    pub prototype: Rc<Prototype>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl fmt::Debug for Closure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The upvalues are not printed, they can contain the closure itself
+        let name = self.prototype.name.as_deref().unwrap_or("func");
+        write!(f, "Closure({})", name)
+    }
+}
+
+/// A call being executed
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Where the local slots of the call start
+    locals: usize,
+    /// How many values the stack had when the call started
+    stack: usize,
+    /// Which parameters received an argument
+    given: Vec<bool>,
+    /// The new instance if the call is the `init` of a class, with the parenthesis of the call
+    instance: Option<(Value, Token)>,
+}
+
+/// A stack machine that executes the bytecode made by the `Compiler`
+pub struct Vm {
+    globals: Environment,
+    stack: Vec<Value>,
+    /// The local slots of all the calls, each one starts where the one before it ends
+    locals: Vec<Value>,
+    frames: Vec<Frame>,
+    /// The upvalues that point to local slots, in the order of their slots
+    open: Vec<Rc<RefCell<Upvalue>>>,
+    /// The arguments that the script receives from the command line
+    args: Vec<String>,
+    /// Where `print` writes
+    out: Box<dyn Write>,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Vm::new()
+    }
+}
+
+impl Host for Vm {
+    fn out(&mut self) -> &mut dyn Write {
+        &mut self.out
+    }
+
+    fn args(&self) -> &[String] {
+        &self.args
+    }
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        let mut globals = Environment::new(None);
+        for builtin in builtins::builtins() {
+            globals.define(builtin.name, Value::Builtin(Rc::new(builtin)));
+        }
+        for name in BUILTIN_TYPES.iter() {
+            globals.define(name, Value::Type(Type::Builtin(name)));
+        }
+
+        Vm {
+            globals,
+            stack: Vec::new(),
+            locals: Vec::new(),
+            frames: Vec::new(),
+            open: Vec::new(),
+            args: Vec::new(),
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    /// Set the arguments that the script can read with `arg`
+    pub fn with_args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Set where `print` writes, the standard output by default
+    pub fn with_output(mut self, out: Box<dyn Write>) -> Self {
+        self.out = out;
+        self
+    }
+
+    /// Execute a compiled program
+    pub fn interpret(&mut self, script: Rc<Prototype>) -> Result<()> {
+        let closure = Rc::new(Closure {
+            prototype: script,
+            upvalues: Vec::new(),
+        });
+        self.push_frame(closure, Vec::new());
+        let result = self.run();
+        if result.is_err() {
+            self.stack.clear();
+            self.locals.clear();
+            self.frames.clear();
+            self.open.clear();
+        }
+        result
+    }
+
+    fn push_frame(&mut self, closure: Rc<Closure>, arguments: Vec<Option<Value>>) {
+        let locals = self.locals.len();
+        let given = arguments.iter().map(Option::is_some).collect();
+        self.locals.extend(
+            arguments
+                .into_iter()
+                .map(|value| value.unwrap_or(Value::Null)),
+        );
+        self.locals
+            .resize(locals + closure.prototype.slots as usize, Value::Null);
+        self.frames.push(Frame {
+            closure,
+            ip: 0,
+            locals,
+            stack: self.stack.len(),
+            given,
+            instance: None,
+        });
+    }
+
+    fn pop(&mut self) -> Result<Value> {
+        self.take(1)?;
+        Ok(self.stack.pop().unwrap())
+    }
+
+    fn peek(&self) -> Result<&Value> {
+        self.take(1)?;
+        Ok(self.stack.last().unwrap())
+    }
+
+    /// Pop that many values, in the order that they were pushed
+    fn pop_many(&mut self, len: usize) -> Result<Vec<Value>> {
+        self.take(len)?;
+        Ok(self.stack.split_off(self.stack.len() - len))
+    }
+
+    /// Check that the current call has that many values in the stack, the compiler never makes
+    /// code that takes more
+    fn take(&self, len: usize) -> Result<()> {
+        match self.stack.len().saturating_sub(self.frame().stack) < len {
+            true => Err(self.damaged("it takes more values than the stack has")),
+            false => Ok(()),
+        }
+    }
+
+    /// The error of code that the compiler never makes, only a damaged compiled file can have it
+    fn damaged(&self, reason: &str) -> Error {
+        let frame = self.frame();
+        let lines = &frame.closure.prototype.chunk.lines;
+        let line = lines.get(frame.ip.saturating_sub(1)).copied().unwrap_or(0);
+        Error::Runtime(
+            Span::new(line as u64, 0, 0, 0),
+            format!("the compiled code is damaged, {}", reason),
+            String::new(),
+        )
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().unwrap()
+    }
+
+    /// The position of a local slot of the current call
+    fn slot(&self, slot: u16) -> usize {
+        self.frame().locals + slot as usize
+    }
+
+    fn run(&mut self) -> Result<()> {
+        loop {
+            let prototype = self.frame().closure.prototype.clone();
+            let chunk = &prototype.chunk;
+            let op = chunk.code[self.frame().ip];
+            self.frames.last_mut().unwrap().ip += 1;
+
+            match op {
+                Op::Constant(index) => {
+                    let value = match chunk.constants[index as usize] {
+                        Constant::Number(ref n) => Value::Number(n.clone()),
+                        Constant::String(ref s) => Value::String(s.clone()),
+                        Constant::Function(_) => unreachable!("functions are made by closures"),
+                    };
+                    self.stack.push(value);
+                }
+                Op::Null => self.stack.push(Value::Null),
+                Op::True => self.stack.push(Value::Boolean(true)),
+                Op::False => self.stack.push(Value::Boolean(false)),
+                Op::Pop => {
+                    self.pop()?;
+                }
+                Op::GetLocal(slot) => {
+                    let value = self.locals[self.slot(slot)].clone();
+                    self.stack.push(value);
+                }
+                Op::SetLocal(slot) => {
+                    let position = self.slot(slot);
+                    self.locals[position] = self.pop()?;
+                }
+                Op::GetUpvalue(index) => {
+                    let value = match *self.frame().closure.upvalues[index as usize].borrow() {
+                        Upvalue::Open(position) => self.locals[position].clone(),
+                        Upvalue::Closed(ref value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                Op::SetUpvalue(index) => {
+                    let value = self.pop()?;
+                    let upvalue = self.frame().closure.upvalues[index as usize].clone();
+                    let mut upvalue = upvalue.borrow_mut();
+                    match *upvalue {
+                        Upvalue::Open(position) => self.locals[position] = value,
+                        Upvalue::Closed(ref mut closed) => *closed = value,
+                    }
+                }
+                Op::GetGlobal(name) => {
+                    let value = self.globals.get(&chunk.tokens[name as usize])?;
+                    self.stack.push(value);
+                }
+                Op::SetGlobal(name) => {
+                    let value = self.pop()?;
+                    self.globals.assign(&chunk.tokens[name as usize], value)?;
+                }
+                Op::DefineGlobal(name) | Op::DefineConst(name) => {
+                    let name = &chunk.tokens[name as usize];
+                    if self.globals.is_constant(&name.lexeme) {
+                        return Err(Error::Runtime(
+                            name.span,
+                            format!("the constant '{}' can not be declared again", name.lexeme),
+                            name.lexeme.clone(),
+                        ));
+                    }
+                    let value = self.pop()?;
+                    match op {
+                        Op::DefineConst(_) => self.globals.define_const(&name.lexeme, value),
+                        _ => self.globals.define(&name.lexeme, value),
+                    }
+                }
+                Op::Close(slot) => {
+                    let position = self.slot(slot);
+                    self.close(position);
+                }
+                Op::Unary(operator) => {
+                    let right = self.pop()?;
+                    let value = operations::unary(&chunk.tokens[operator as usize], right)?;
+                    self.stack.push(value);
+                }
+                Op::Binary(operator) => {
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    let operator = &chunk.tokens[operator as usize];
+                    let value = operations::binary(operator, left, right, prototype.decimals)?;
+                    self.stack.push(value);
+                }
+                Op::Jump(target) => self.jump(target),
+                Op::JumpIfFalse(target) => {
+                    if !self.pop()?.is_truthy() {
+                        self.jump(target);
+                    }
+                }
+                Op::JumpIfFalseOrPop(target) => {
+                    if self.peek()?.is_truthy() {
+                        self.pop()?;
+                    } else {
+                        self.jump(target);
+                    }
+                }
+                Op::JumpIfTrueOrPop(target) => {
+                    if self.peek()?.is_truthy() {
+                        self.jump(target);
+                    } else {
+                        self.pop()?;
+                    }
+                }
+                Op::Call(call) => {
+                    let site = &chunk.calls[call as usize];
+                    let paren = &chunk.tokens[site.paren as usize];
+                    let values = self.pop_many(site.names.len())?;
+                    let callee = self.pop()?;
+                    let mut arguments = Vec::with_capacity(values.len());
+                    let mut keywords = Vec::new();
+                    for (value, name) in values.into_iter().zip(&site.names) {
+                        match name {
+                            Some(name) => keywords.push((&chunk.tokens[*name as usize], value)),
+                            None => arguments.push(value),
+                        }
+                    }
+                    self.call(callee, paren, arguments, keywords)?;
+                }
+                Op::Closure(function) => {
+                    let prototype = match chunk.constants[function as usize] {
+                        Constant::Function(ref prototype) => prototype.clone(),
+                        _ => unreachable!("closures are made of functions"),
+                    };
+                    let mut upvalues = Vec::with_capacity(prototype.captures.len());
+                    for capture in &prototype.captures {
+                        upvalues.push(if capture.local {
+                            let position = self.slot(capture.index);
+                            self.capture(position)
+                        } else {
+                            self.frame().closure.upvalues[capture.index as usize].clone()
+                        });
+                    }
+                    let closure = Closure {
+                        prototype,
+                        upvalues,
+                    };
+                    self.stack.push(Value::Compiled(gc::allocate(closure)));
+                }
+                Op::Return => {
+                    let mut value = self.pop()?;
+                    let frame = self.frames.pop().unwrap();
+                    self.close(frame.locals);
+                    self.locals.truncate(frame.locals);
+                    self.stack.truncate(frame.stack);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    // Calling a class gives the instance, whatever its `init` returns
+                    if let Some((instance, paren)) = frame.instance {
+                        operations::check_fields(&paren, &instance)?;
+                        value = instance;
+                    }
+                    self.stack.push(value);
+                }
+                Op::Array(len) => {
+                    let values = self.pop_many(len as usize)?;
+                    self.stack.push(Value::array(values));
+                }
+                Op::Tuple(len) => {
+                    let values = self.pop_many(len as usize)?;
+                    self.stack.push(Value::tuple(values));
+                }
+                Op::Map(len) => {
+                    let values = self.pop_many(2 * len as usize)?;
+                    let mut map = IndexMap::with_capacity(len as usize);
+                    let mut values = values.into_iter();
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        // The keys were checked by `Key`
+                        let key = Key::from_value(&key)
+                            .ok_or_else(|| self.damaged("it has a key that was not checked"))?;
+                        map.insert(key, value);
+                    }
+                    self.stack.push(Value::map(map));
+                }
+                Op::Key(token) => {
+                    operations::key(&chunk.tokens[token as usize], self.peek()?)?;
+                }
+                Op::Index(bracket) => {
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    let value = operations::index(&chunk.tokens[bracket as usize], object, index)?;
+                    self.stack.push(value);
+                }
+                Op::SetIndex(bracket) => {
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    let value = self.pop()?;
+                    operations::set_index(&chunk.tokens[bracket as usize], object, index, value)?;
+                }
+                Op::GetAttribute(name) => {
+                    let object = self.pop()?;
+                    let value = operations::attribute(&chunk.tokens[name as usize], object)?;
+                    self.stack.push(value);
+                }
+                Op::SetAttribute(name) => {
+                    let object = self.pop()?;
+                    let value = self.pop()?;
+                    operations::set_attribute(&chunk.tokens[name as usize], object, value)?;
+                }
+                Op::Iterate(token) => {
+                    let iterable = self.pop()?;
+                    let values = operations::iterate(&chunk.tokens[token as usize], iterable)?;
+                    self.stack.push(Value::array(values));
+                }
+                Op::ForEach(slot, exit) => {
+                    let items = self.slot(slot);
+                    let (array, position) = match (&self.locals[items], &self.locals[items + 1]) {
+                        (Value::Array(array), Value::Number(Number::Integer(n))) if *n >= 0 => {
+                            (array.clone(), *n as usize)
+                        }
+                        _ => return Err(self.damaged("a `for each` has no array in its slots")),
+                    };
+                    let next = array.borrow().get(position).cloned();
+                    match next {
+                        Some(value) => {
+                            self.locals[items + 1] =
+                                Value::Number(Number::Integer(position as i64 + 1));
+                            self.stack.push(value);
+                        }
+                        None => self.jump(exit),
+                    }
+                }
+                Op::IfGiven(param, target) => {
+                    if self.frame().given[param as usize] {
+                        self.jump(target);
+                    }
+                }
+                Op::Mark(slot) => {
+                    let position = self.slot(slot);
+                    self.locals[position] = Value::Number(Number::Integer(self.stack.len() as i64));
+                }
+                Op::Unwind(slot) => {
+                    // The values of the calls before this one stay
+                    if let Value::Number(Number::Integer(len)) = self.locals[self.slot(slot)] {
+                        self.stack
+                            .truncate((len.max(0) as usize).max(self.frame().stack));
+                    }
+                }
+                Op::MatchArray(len) => {
+                    let matched = match self.pop()? {
+                        Value::Array(array) => array.borrow().len() == len as usize,
+                        _ => false,
+                    };
+                    self.stack.push(Value::Boolean(matched));
+                }
+                Op::MatchTuple(len) => {
+                    let matched = match self.pop()? {
+                        Value::Tuple(tuple) => tuple.len() == len as usize,
+                        _ => false,
+                    };
+                    self.stack.push(Value::Boolean(matched));
+                }
+                Op::MatchRange(low, high) => {
+                    let number = |index: u16| match chunk.constants[index as usize] {
+                        Constant::Number(ref n) => n.clone(),
+                        _ => Number::Float(f64::NAN),
+                    };
+                    let matched = match self.pop()? {
+                        Value::Number(n) => number(low) <= n && n <= number(high),
+                        _ => false,
+                    };
+                    self.stack.push(Value::Boolean(matched));
+                }
+                Op::Element(position) => {
+                    // The patterns check the elements first
+                    let value = match self.pop()? {
+                        Value::Array(array) => array.borrow().get(position as usize).cloned(),
+                        Value::Tuple(tuple) => tuple.get(position as usize).cloned(),
+                        _ => None,
+                    };
+                    let value =
+                        value.ok_or_else(|| self.damaged("it takes an element that is missing"))?;
+                    self.stack.push(value);
+                }
+                Op::Superclass(site) => {
+                    let site = &chunk.classes[site as usize];
+                    self.take(site.inherits.len())?;
+                    let values = &self.stack[self.stack.len() - site.inherits.len()..];
+                    let inherited = site
+                        .inherits
+                        .iter()
+                        .map(|name| &chunk.tokens[*name as usize])
+                        .zip(values.iter().cloned())
+                        .collect();
+                    let value = match operations::inherits(inherited)?.0 {
+                        Some(superclass) => Value::Class(superclass),
+                        None => Value::Null,
+                    };
+                    self.stack.push(value);
+                }
+                Op::Class(site) => {
+                    let site = &chunk.classes[site as usize];
+                    let functions = self.pop_many(site.methods as usize)?;
+                    let values = self.pop_many(site.inherits.len())?;
+                    let inherited = site
+                        .inherits
+                        .iter()
+                        .map(|name| &chunk.tokens[*name as usize])
+                        .zip(values)
+                        .collect();
+                    let (superclass, interfaces) = operations::inherits(inherited)?;
+                    let methods: HashMap<String, Value> = functions
+                        .into_iter()
+                        .map(|method| (method.function_name().unwrap_or("").to_string(), method))
+                        .collect();
+                    let name = &chunk.tokens[site.name as usize];
+                    let value = operations::class(name, superclass, interfaces, methods)?;
+                    self.stack.push(value);
+                }
+                Op::Interface(site) => {
+                    let site = &chunk.interfaces[site as usize];
+                    let values = self.pop_many(site.fields.len())?;
+                    let mut fields = Vec::with_capacity(site.fields.len());
+                    for (field, value) in site.fields.iter().zip(values) {
+                        let type_name = &chunk.tokens[field.field_type as usize];
+                        fields.push(Field {
+                            name: chunk.tokens[field.name as usize].lexeme.clone(),
+                            field_type: operations::field_type(type_name, Some(value))?,
+                            required: field.required,
+                        });
+                    }
+                    let interface = Interface {
+                        name: chunk.tokens[site.name as usize].lexeme.clone(),
+                        fields,
+                    };
+                    self.stack.push(Value::Interface(gc::allocate(interface)));
+                }
+                Op::TryGlobal(name) => {
+                    let value = self.globals.get(&chunk.tokens[name as usize]);
+                    self.stack.push(value.unwrap_or(Value::Null));
+                }
+                Op::Super(keyword, name) => {
+                    let receiver = self.pop()?;
+                    let superclass = self.pop()?;
+                    let superclass =
+                        operations::superclass(&chunk.tokens[keyword as usize], Some(superclass))?;
+                    let name = &chunk.tokens[name as usize];
+                    let value = operations::super_method(name, superclass, receiver)?;
+                    self.stack.push(value);
+                }
+                Op::MatchInstance(name) => {
+                    let class = self.pop()?;
+                    let value = self.pop()?;
+                    let matched =
+                        operations::is_instance(&chunk.tokens[name as usize], class, &value)?;
+                    self.stack.push(Value::Boolean(matched));
+                }
+                Op::HasField(name) => {
+                    let matched = match self.pop()? {
+                        Value::Instance(instance) => instance
+                            .borrow()
+                            .fields
+                            .contains_key(&chunk.tokens[name as usize].lexeme),
+                        _ => false,
+                    };
+                    self.stack.push(Value::Boolean(matched));
+                }
+            }
+        }
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.frames.last_mut().unwrap().ip = target as usize;
+    }
+
+    /// The upvalue that points to the local slot, the closures that capture the same slot share it
+    fn capture(&mut self, position: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open
+            .iter()
+            .rev()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(p) if p == position));
+        if let Some(upvalue) = existing {
+            return upvalue.clone();
+        }
+        let upvalue = gc::allocate(RefCell::new(Upvalue::Open(position)));
+        let index = self
+            .open
+            .iter()
+            .position(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(p) if p > position))
+            .unwrap_or(self.open.len());
+        self.open.insert(index, upvalue.clone());
+        upvalue
+    }
+
+    /// Close the upvalues of the local slots from the position up, they keep the value that the
+    /// slot has now
+    fn close(&mut self, position: usize) {
+        while let Some(upvalue) = self.open.last() {
+            let slot = match *upvalue.borrow() {
+                Upvalue::Open(slot) if slot >= position => slot,
+                _ => break,
+            };
+            let upvalue = self.open.pop().unwrap();
+            *upvalue.borrow_mut() = Upvalue::Closed(self.locals[slot].clone());
+        }
+    }
+
+    fn call(
+        &mut self,
+        callee: Value,
+        paren: &Token,
+        arguments: Vec<Value>,
+        keywords: Vec<(&Token, Value)>,
+    ) -> Result<()> {
+        match callee {
+            Value::Compiled(closure) => {
+                self.call_closure(closure, paren, None, arguments, keywords)?
+            }
+            Value::Class(class) => {
+                let instance = Value::Instance(gc::allocate(RefCell::new(Instance {
+                    class: class.clone(),
+                    fields: IndexMap::new(),
+                })));
+                match class.find_method("init") {
+                    // The instance is checked and pushed when the `init` returns
+                    Some(Value::Compiled(init)) => {
+                        let receiver = Some(instance.clone());
+                        self.call_closure(init, paren, receiver, arguments, keywords)?;
+                        self.frames.last_mut().unwrap().instance = Some((instance, paren.clone()));
+                        return Ok(());
+                    }
+                    Some(init) => return Err(operations::not_callable(paren, &init)),
+                    None if !keywords.is_empty() => {
+                        return Err(Error::Runtime(
+                            keywords[0].0.span,
+                            format!(
+                                "the class {} has no parameter '{}'",
+                                class.name, keywords[0].0.lexeme
+                            ),
+                            keywords[0].0.lexeme.clone(),
+                        ))
+                    }
+                    None => check_arity(paren, 0, arguments.len())?,
+                }
+                operations::check_fields(paren, &instance)?;
+                self.stack.push(instance);
+            }
+            Value::Method(method) => match method.function {
+                Value::Compiled(ref closure) => {
+                    let receiver = Some(method.receiver.clone());
+                    self.call_closure(closure.clone(), paren, receiver, arguments, keywords)?
+                }
+                ref function => return Err(operations::not_callable(paren, function)),
+            },
+            Value::Builtin(builtin) => {
+                if let Some((name, _)) = keywords.first() {
+                    return Err(Error::Runtime(
+                        name.span,
+                        format!("'{}' does not accept keyword arguments", builtin.name),
+                        name.lexeme.clone(),
+                    ));
+                }
+                if let Some(arity) = builtin.arity {
+                    check_arity(paren, arity, arguments.len())?;
+                }
+                let value = (builtin.function)(self, paren, arguments)?;
+                self.stack.push(value);
+            }
+            value => return Err(operations::not_callable(paren, &value)),
+        }
+        Ok(())
+    }
+
+    /// Start a call of a compiled function, the receiver is the `self` of a method
+    fn call_closure(
+        &mut self,
+        closure: Rc<Closure>,
+        paren: &Token,
+        receiver: Option<Value>,
+        arguments: Vec<Value>,
+        keywords: Vec<(&Token, Value)>,
+    ) -> Result<()> {
+        let prototype = &closure.prototype;
+        let values = operations::bind(
+            prototype.name.as_deref().unwrap_or("func"),
+            prototype
+                .params
+                .iter()
+                .map(|param| (param.name.as_str(), param.default)),
+            paren,
+            receiver,
+            arguments,
+            keywords,
+        )?;
+        if self.frames.len() > MAX_DEPTH {
+            let name = closure.prototype.name.as_deref().unwrap_or("func");
+            return Err(Error::Runtime(
+                paren.span,
+                "maximum recursion depth exceeded".to_string(),
+                name.to_string(),
+            ));
+        }
+        self.push_frame(closure, values);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::compiler::Compiler;
+    use crate::ezc;
+    use crate::interpreter::Interpreter;
+    use crate::operations::Output;
+    use crate::resolver::Resolver;
+    use ezcript_lexer::lexer::Lexer;
+    use ezcript_parser::ast::Stmt;
+    use ezcript_parser::parser::Parser;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert!(Resolver::new().resolve(&statements).is_empty());
+        statements
+    }
+
+    fn run(source: &str) -> Result<String> {
+        let script = Compiler::new().compile(&parse(source))?;
+        let output = Output::default();
+        Vm::new()
+            .with_args(vec!["first".to_string()])
+            .with_output(Box::new(output.clone()))
+            .interpret(script)?;
+        Ok(output.text())
+    }
+
+    /// The output of the tree-walking interpreter, or its error
+    fn walk(source: &str) -> Result<String> {
+        let output = Output::default();
+        Interpreter::new()
+            .with_args(vec!["first".to_string()])
+            .with_output(Box::new(output.clone()))
+            .interpret(&parse(source))?;
+        Ok(output.text())
+    }
+
+    #[test]
+    fn test_same_as_interpreter() {
+        let programs = [
+            include_str!("../../../examples/identifiers_and_booleans.ez"),
+            include_str!("../../../examples/numbers.ez"),
+            include_str!("../../../examples/strings.ez"),
+            "func counter(start = 0, step = 1) do\n    set count = start\n    \
+             return func() do\n        count += step\n        return count\n\
+             set next = counter(step = 5)\nnext()\n\
+             set fs = []\nfor each n in [1, 2] do fs = fs + [func() do return n]\n\
+             print(next(), counter(10)(), fs[0](), fs[1]())",
+            "func describe(value) do\n    return match value\n        \
+             1 do return \"one\"\n        2..5 do return \"few\"\n        \
+             [a, b] if a == b do return \"pair\"\n        (a, _) do return a\n        \
+             _ do return \"other\"\n\
+             print(describe(1), describe(4.5), describe([2, 2]), describe((\"t\", 0)))",
+            "set y = 0\nwhile true do\n    set z = if y > 3 do\n        break\n    \
+             else do\n        return y\n    y += z + 1\n\
+             set m = { a: 1 }\nm.b = [1, 2]\nm.b[0] += 5\n\
+             print(y, m, 2 in m.b, m.type, 1 is integer, arg(0), print)",
+            "set f = 1\nset n = 0\nwhile n < 25 do\n    n++\n    f *= n\n\
+             print(f, f - f, 10 / 4, 4 / 2, 7 // 2, 1 == 1.0, 1.0 is integer, { 1: \"a\" }[1.0])",
+            "func outer() do\n    func is_even(n) do\n        if n == 0 do return true\n        \
+             return is_odd(n - 1)\n    func is_odd(n) do\n        if n == 0 do return false\n        \
+             return is_even(n - 1)\n    return is_even(10)\nprint(outer())",
+            "interface INamed do\n    name: String\n    age: { type: Integer, required: false }\n\
+             class Human inherit INamed do\n    func init(self, name) do\n        \
+             self.name = name\n    func greet(self, greeting = \"Hi\") do\n        \
+             return greeting + \", \" + self.name\n\
+             class Worker inherit Human do\n    func init(self, name, job) do\n        \
+             super.init(name)\n        self.job = job\n    func greet(self) do\n        \
+             return super.greet(greeting = \"Hello\") + \" the \" + self.job\n\
+             set w = Worker(\"Ana\", \"baker\")\nset greet = w.greet\n\
+             print(w, greet(), Human.greet(w), w is Human, w is INamed, Human(\"Bo\").greet())\n\
+             func describe(value) do\n    return match value\n        \
+             Worker { job: \"baker\", name } do return name + \" bakes\"\n        \
+             Human { age: 3 } do return \"three\"\n        Human { name } do return name\n        \
+             _ do return \"other\"\n\
+             print(describe(w), describe(Human(\"Bo\")), describe(1))",
+            "func make() do\n    class Counter do\n        func init(self) do self.n = 0\n        \
+             func next(self) do\n            self.n += 1\n            return Counter\n    \
+             return Counter()\nset c = make()\nc.next()\nprint(c.n, c.next() == c.type)",
+        ];
+
+        for program in programs.iter() {
+            assert_eq!(run(program).unwrap(), walk(program).unwrap(), "{}", program);
+        }
+    }
+
+    #[test]
+    fn test_gc_stress() {
+        let program = "func make(n) do\n    set node = { n: n }\n    node.self = [node]\n    \
+                       set count = n\n    func next() do\n        count += 1\n        \
+                       return count\n    return (node, next)\n\
+                       set total = 0\nfor each i in [1, 2, 3] do\n    \
+                       set made = make(i)\n    made[1]()\n    \
+                       total += made[0].self[0].n + made[1]()\n\
+                       print(total, make(7)[0].self)";
+        let expected = (run(program).unwrap(), walk(program).unwrap());
+        gc::set_stress(true);
+        let stressed = (run(program).unwrap(), walk(program).unwrap());
+        gc::set_stress(false);
+        assert_eq!(stressed, expected);
+        assert_eq!(expected.0, expected.1);
+    }
+
+    #[test]
+    fn test_decimals() {
+        let program = "print(1 / 10 + 2 / 10, 1 / 3, 2 ** -1, 2 ** 3, 0.1 * 3)\n\
+                       set x = 1\nx /= 4\nfunc half(n) do return n / 2\nprint(x, half(1))";
+        let tokens = Lexer::new(program.chars())
+            .with_decimals(true)
+            .tokenize()
+            .unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        assert!(Resolver::new().resolve(&statements).is_empty());
+
+        let script = Compiler::new()
+            .with_decimals(true)
+            .compile(&statements)
+            .unwrap();
+        let script = ezc::decode(&ezc::encode(&script)).unwrap();
+        let output = Output::default();
+        Vm::new()
+            .with_output(Box::new(output.clone()))
+            .interpret(script)
+            .unwrap();
+        assert_eq!(
+            output.text(),
+            "0.3 0.3333333333333333333333333333 0.5 8 0.3\n0.25 0.5\n"
+        );
+
+        let walked = Output::default();
+        Interpreter::new()
+            .with_decimals(true)
+            .with_output(Box::new(walked.clone()))
+            .interpret(&statements)
+            .unwrap();
+        assert_eq!(walked.text(), output.text());
+        assert_eq!(
+            run("print(1 / 10 + 2 / 10)").unwrap(),
+            "0.30000000000000004\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let programs = [
+            "print(y)",
+            "print([1][2])",
+            "func f(a) do return a\nf()",
+            "print(1 % 0)",
+            "print(2 ** 10000 / 3)",
+            "class A do func f(self) do return 1\nA().g",
+            "class A do func f(self) do return super.f()\nA().f()",
+            "interface I do a: string\nclass A inherit I do func f(self) do return 1\nA()",
+            "interface I do a: Missing",
+            "interface I do a: string\nclass A inherit I, I do func init(self, a) do self.a = a\nA(1)",
+            "class A do func f(self) do return 1\nclass B inherit A, A do func g(self) do return 2",
+            "set x = 1\nmatch 1\n    x { y } do y\n",
+        ];
+        for program in programs.iter() {
+            assert_eq!(
+                run(program).unwrap_err().to_string(),
+                walk(program).unwrap_err().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_damaged_code() {
+        // Code that the compiler never makes, as if it came from a damaged `.ezc` file
+        let programs = vec![
+            vec![Op::Pop, Op::Return],
+            vec![Op::Return],
+            vec![Op::Array(3), Op::Return],
+            vec![Op::ForEach(0, 2), Op::Pop, Op::Null, Op::Return],
+            vec![Op::Array(0), Op::Element(1), Op::Return],
+            vec![Op::Array(0), Op::Null, Op::Map(1), Op::Return],
+        ];
+        for code in programs {
+            let lines = vec![1; code.len()];
+            let script = Prototype {
+                name: None,
+                params: Vec::new(),
+                slots: 2,
+                captures: Vec::new(),
+                decimals: false,
+                chunk: Chunk {
+                    code,
+                    lines,
+                    ..Chunk::default()
+                },
+            };
+            let script = ezc::decode(&ezc::encode(&script)).unwrap();
+            let err = Vm::new()
+                .with_output(Box::new(Output::default()))
+                .interpret(script)
+                .unwrap_err();
+            assert!(
+                err.message().starts_with("the compiled code is damaged"),
+                "{}",
+                err
+            );
+        }
+    }
+}
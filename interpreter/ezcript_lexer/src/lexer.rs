@@ -2,8 +2,9 @@ use std::collections::{HashSet, VecDeque};
 use std::ops::Index;
 use std::str::Chars;
 
+use super::number::Number;
 use super::tokens::{Literal, Token, TokenKind};
-use ezcript_result::{Error, Result};
+use ezcript_result::{Error, Result, Span};
 
 #[derive(Debug)]
 pub struct Lexer<'a> {
@@ -11,7 +12,24 @@ pub struct Lexer<'a> {
     tokens: VecDeque<char>,
     lexeme: String,
     line: u64,
+    column: u64,
+    offset: usize,
+    start: Span,
     eof: bool,
+    /// Columns where the open indented blocks start
+    indents: Vec<u64>,
+    /// How many brackets are open, the indentation inside of them is ignored
+    depth: usize,
+    /// True while only whitespace has been found in the current line
+    line_blank: bool,
+    /// If the line was blank before the current lexeme
+    start_blank: bool,
+    /// Tokens that are ready to be returned before scanning more characters
+    pending: VecDeque<Token>,
+    /// Return the comments as `Comment` tokens instead of skipping them
+    comments: bool,
+    /// Read the numbers with a point as exact decimals instead of floats
+    decimals: bool,
 }
 
 impl<'a> Lexer<'a> {
@@ -21,11 +39,108 @@ impl<'a> Lexer<'a> {
             tokens: VecDeque::with_capacity(2),
             lexeme: String::from(""),
             line: 1,
+            column: 1,
+            offset: 0,
+            start: Span::default(),
             eof: false,
+            indents: vec![1],
+            depth: 0,
+            line_blank: true,
+            start_blank: true,
+            pending: VecDeque::new(),
+            comments: false,
+            decimals: false,
         }
     }
 
+    /// Make the lexer return the comments as `Comment` tokens, for tools like the formatter
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
+
+    /// Make the numbers with a point exact decimals, so `0.1 + 0.2` is `0.3`
+    pub fn with_decimals(mut self, decimals: bool) -> Self {
+        self.decimals = decimals;
+        self
+    }
+
     pub fn next_token(&mut self) -> Option<Result<Token>> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(Ok(token));
+        }
+
+        match self.scan_token()? {
+            Ok(token) => Some(self.layout(token)),
+            Err(err) => Some(Err(err)),
+        }
+    }
+
+    /// All the tokens of the source until `Eof`, or the first error
+    pub fn tokenize(mut self) -> Result<Vec<Token>> {
+        let mut tokens = Vec::new();
+        while let Some(token) = self.next_token() {
+            tokens.push(token?);
+        }
+        Ok(tokens)
+    }
+
+    /// Put the `Indent` and `Dedent` tokens that a change of indentation needs before the first
+    /// token of a line, the blocks that are still open are closed at the end of the file
+    fn layout(&mut self, token: Token) -> Result<Token> {
+        if token.kind == TokenKind::Comment {
+            return Ok(token);
+        }
+
+        let first_of_line = self.start_blank;
+        let mut result = Ok(());
+
+        match token.kind {
+            TokenKind::Eof => {
+                while self.indents.len() > 1 {
+                    self.indents.pop();
+                    self.pending
+                        .push_back(layout_token(TokenKind::Dedent, &token));
+                }
+            }
+            _ if self.depth > 0 || !first_of_line => (),
+            _ => {
+                let column = token.span.column;
+                if column > self.indents[self.indents.len() - 1] {
+                    self.indents.push(column);
+                    self.pending
+                        .push_back(layout_token(TokenKind::Indent, &token));
+                }
+                while column < self.indents[self.indents.len() - 1] {
+                    self.indents.pop();
+                    self.pending
+                        .push_back(layout_token(TokenKind::Dedent, &token));
+                }
+                if column != self.indents[self.indents.len() - 1] {
+                    self.indents.push(column);
+                    result = Err(Error::Lexical(
+                        token.span,
+                        "unindent does not match any outer indentation level".to_string(),
+                        token.lexeme.clone(),
+                    ));
+                }
+            }
+        }
+
+        match token.kind {
+            TokenKind::LParen | TokenKind::LBracket | TokenKind::LBrace => self.depth += 1,
+            TokenKind::RParen | TokenKind::RBracket | TokenKind::RBrace => {
+                self.depth = self.depth.saturating_sub(1)
+            }
+            _ => (),
+        }
+
+        self.pending.push_back(token);
+        result?;
+        Ok(self.pending.pop_front().unwrap())
+    }
+
+    fn scan_token(&mut self) -> Option<Result<Token>> {
         if self.eof {
             return None;
         }
@@ -36,6 +151,7 @@ impl<'a> Lexer<'a> {
             match self.advance().unwrap() {
                 '\0' => {
                     self.eof = true;
+                    self.lexeme.clear();
                     return self.static_token(TokenKind::Eof);
                 }
                 '(' => return self.static_token(TokenKind::LParen),
@@ -47,7 +163,7 @@ impl<'a> Lexer<'a> {
                 ':' => return self.static_token(TokenKind::Colon),
                 ',' => return self.static_token(TokenKind::Comma),
                 '%' => return self.static_token(TokenKind::Percent),
-                '.' => return self.static_token(TokenKind::Dot),
+                '.' => return self.match_static_token('.', TokenKind::DoubleDot, TokenKind::Dot),
                 '-' => match self.peek(1) {
                     '-' => {
                         return self.match_static_token(
@@ -111,17 +227,23 @@ impl<'a> Lexer<'a> {
                 }
                 '"' => return self.string(),
                 '\'' => return self.string(),
-                '#' => match self.peek(1) {
-                    '*' => self.block_comment(),
-                    _ => self.line_comment(),
-                },
+                '#' => {
+                    match self.peek(1) {
+                        '*' => self.block_comment(),
+                        _ => self.line_comment(),
+                    }
+                    if self.comments {
+                        return self.static_token(TokenKind::Comment);
+                    }
+                    self.lexeme.clear();
+                }
                 c if c.is_whitespace() => {
                     self.lexeme.clear();
                     if c == '\n' {
                         self.line += 1;
                     }
                 }
-                c if c.is_digit(10) => return self.number(),
+                c if c.is_ascii_digit() => return self.number(),
                 c if is_alphanumeric(c) => return self.identifier(),
                 _ => return self.err("unexpected character"),
             }
@@ -136,11 +258,19 @@ impl<'a> Lexer<'a> {
         Some(Ok(Token {
             kind,
             literal,
-            line: self.line,
+            span: self.span(),
             lexeme: self.lexeme.clone(),
         }))
     }
 
+    /// The place of the current lexeme in the source code
+    fn span(&self) -> Span {
+        Span {
+            end: self.offset,
+            ..self.start
+        }
+    }
+
     fn advance(&mut self) -> Option<char> {
         if self.eof {
             return None;
@@ -154,9 +284,21 @@ impl<'a> Lexer<'a> {
             self.eof = true;
             Some('\0')
         })
-        .and_then(|c| {
+        .inspect(|&c| {
+            if self.lexeme.is_empty() {
+                self.start = Span::new(self.line, self.column, self.offset, self.offset);
+                self.start_blank = self.line_blank;
+            }
             self.lexeme.push(c);
-            Some(c)
+            if c == '\n' {
+                self.line_blank = true;
+            } else if !c.is_whitespace() {
+                self.line_blank = false;
+            }
+            if c != '\0' {
+                self.offset += 1;
+                self.column = if c == '\n' { 1 } else { self.column + 1 };
+            }
         })
     }
 
@@ -178,7 +320,6 @@ impl<'a> Lexer<'a> {
 
     fn line_comment(&mut self) {
         self.advance_until(&['\n']);
-        self.lexeme.clear();
     }
 
     fn block_comment(&mut self) {
@@ -190,8 +331,7 @@ impl<'a> Lexer<'a> {
             match (last, next) {
                 (_, '\n') => self.line += 1,
                 ('*', '#') => {
-                    self.advance(); // *
-                    self.advance(); // /
+                    self.advance(); // #
                     break;
                 }
                 (_, '\0') => break,
@@ -199,13 +339,11 @@ impl<'a> Lexer<'a> {
             }
             self.advance();
         }
-
-        self.lexeme.clear();
     }
 
     fn err(&self, msg: &str) -> Option<Result<Token>> {
         Some(Err(Error::Lexical(
-            self.line,
+            self.span(),
             msg.to_string(),
             self.lexeme.clone(),
         )))
@@ -268,30 +406,25 @@ impl<'a> Lexer<'a> {
 
         self.advance();
 
-        let literal: String = self
-            .lexeme
-            .clone()
-            .chars()
-            .skip(1)
-            .take(self.lexeme.len() - 2)
-            .collect();
+        // The quotes are one byte long, so the slice always falls between chars
+        let literal = self.lexeme[1..self.lexeme.len() - 1].to_string();
 
         self.literal_token(TokenKind::String, Some(Literal::String(literal)))
     }
 
     fn number(&mut self) -> Option<Result<Token>> {
-        while self.peek(1).is_digit(10) {
+        while self.peek(1).is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek(1) == '.' && self.peek(2).is_digit(10) {
+        if self.peek(1) == '.' && self.peek(2).is_ascii_digit() {
             self.advance();
-            while self.peek(1).is_digit(10) {
+            while self.peek(1).is_ascii_digit() {
                 self.advance();
             }
         }
 
-        if let Ok(literal) = self.lexeme.clone().parse::<f64>() {
+        if let Some(literal) = Number::parse(&self.lexeme, self.decimals) {
             return self.literal_token(TokenKind::Number, Some(Literal::Number(literal)));
         }
 
@@ -299,7 +432,7 @@ impl<'a> Lexer<'a> {
     }
 
     fn identifier(&mut self) -> Option<Result<Token>> {
-        while is_alphanumeric(self.peek(1)) || self.peek(1).is_digit(10) {
+        while is_alphanumeric(self.peek(1)) || self.peek(1).is_ascii_digit() {
             self.advance();
         }
         let lexeme: &str = self.lexeme.as_ref();
@@ -309,9 +442,9 @@ impl<'a> Lexer<'a> {
             TokenKind::Null => self.literal_token(kind, Some(Literal::Null)),
             TokenKind::Boolean => {
                 if lexeme == "true" {
-                    return self.literal_token(kind, Some(Literal::Boolean(true)));
+                    self.literal_token(kind, Some(Literal::Boolean(true)))
                 } else {
-                    return self.literal_token(kind, Some(Literal::Boolean(false)));
+                    self.literal_token(kind, Some(Literal::Boolean(false)))
                 }
             }
             _ => self.static_token(kind),
@@ -319,6 +452,65 @@ impl<'a> Lexer<'a> {
     }
 }
 
+/// An empty token that marks the start or the end of an indented block before `next`
+fn layout_token(kind: TokenKind, next: &Token) -> Token {
+    Token {
+        kind,
+        lexeme: String::new(),
+        literal: None,
+        span: Span {
+            end: next.span.start,
+            ..next.span
+        },
+    }
+}
+
 fn is_alphanumeric(c: char) -> bool {
     c.is_digit(36) || c == '_' || c == '$'
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_spans() {
+        let mut lexer = Lexer::new("set x\n  = 'é'".chars());
+        let spans: Vec<Span> = (0..5)
+            .map(|_| lexer.next_token().unwrap().unwrap())
+            .filter(|t| t.kind != TokenKind::Indent)
+            .map(|t| t.span)
+            .collect();
+
+        assert_eq!(spans[0], Span::new(1, 1, 0, 3));
+        assert_eq!(spans[1], Span::new(1, 5, 4, 5));
+        assert_eq!(spans[2], Span::new(2, 3, 8, 9));
+        assert_eq!(spans[3], Span::new(2, 5, 10, 13));
+    }
+
+    #[test]
+    fn test_indentation() {
+        let source = "while x do\n    print(x)\n    if y do\n        f(1,\n  2)\nz";
+        let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+        let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind).collect();
+
+        use TokenKind::*;
+        assert_eq!(
+            kinds,
+            vec![
+                Keyword, Ident, Keyword, Indent, Ident, LParen, Ident, RParen, Keyword, Ident,
+                Keyword, Indent, Ident, LParen, Number, Comma, Number, RParen, Dedent, Dedent,
+                Ident, Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn test_non_ascii_string() {
+        let mut lexer = Lexer::new("'Sintáxis'".chars());
+        let token = lexer.next_token().unwrap().unwrap();
+
+        assert_eq!(token.literal, Some(Literal::String("Sintáxis".to_string())));
+        assert!(Lexer::new("'Sintáxis".chars()).tokenize().is_err());
+    }
+}
@@ -2,4 +2,5 @@
 extern crate lazy_static;
 
 pub mod lexer;
+pub mod number;
 pub mod tokens;
@@ -0,0 +1,794 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Neg;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+/// How many significant digits a division of decimals keeps when the result is not exact
+const PRECISION: usize = 28;
+/// The integers up to this size are exact as floats
+const EXACT_FLOAT: u64 = 1 << 53;
+
+const DIVISION_BY_ZERO: &str = "can not divide by zero";
+const TOO_BIG: &str = "the result is too big";
+const TOO_BIG_FLOAT: &str = "the result is too big to be a float";
+
+/// The result of an operator that can fail, the error is its message
+pub type Arithmetic = std::result::Result<Number, &'static str>;
+
+/// A number of the language. The integers are written without a point and grow to any size, the
+/// numbers with a point are floats, or exact decimals in the decimal mode.
+#[derive(Debug, Clone)]
+pub enum Number {
+    Integer(i64),
+    /// An integer that does not fit in 64 bits
+    Big(BigInt),
+    Float(f64),
+    Decimal(Decimal),
+}
+
+impl Number {
+    /// Read a number as it is written in the code, the ones with a point are decimals if
+    /// `decimals` is true
+    pub fn parse(text: &str, decimals: bool) -> Option<Number> {
+        if text.contains('.') {
+            return match decimals {
+                true => Decimal::parse(text).map(Number::Decimal),
+                false => text.parse().ok().map(Number::Float),
+            };
+        }
+        match text.parse::<i64>() {
+            Ok(n) => Some(Number::Integer(n)),
+            Err(_) => text.parse::<BigInt>().ok().map(Number::Big),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Number::Integer(_) | Number::Big(_))
+    }
+
+    /// True if the number has no fraction, like `3` or `3.0`
+    pub fn is_whole(&self) -> bool {
+        match self {
+            Number::Integer(_) | Number::Big(_) => true,
+            Number::Float(n) => n.fract() == 0.0,
+            Number::Decimal(n) => n.scale == 0,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(n) => *n == 0,
+            Number::Big(n) => n.is_zero(),
+            Number::Float(n) => *n == 0.0,
+            Number::Decimal(n) => n.digits.is_zero(),
+        }
+    }
+
+    /// True if both numbers are of the same kind and have the same value, unlike `==` this keeps
+    /// `1` apart from `1.0` and `-0.0` apart from `0.0`
+    pub fn is_identical(&self, other: &Number) -> bool {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => a == b,
+            (Number::Big(a), Number::Big(b)) => a == b,
+            (Number::Float(a), Number::Float(b)) => a.to_bits() == b.to_bits(),
+            (Number::Decimal(a), Number::Decimal(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    pub fn is_nan(&self) -> bool {
+        matches!(self, Number::Float(n) if n.is_nan())
+    }
+
+    /// False only for the infinities and `NaN`
+    pub fn is_finite(&self) -> bool {
+        !matches!(self, Number::Float(n) if !n.is_finite())
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(n) => *n as f64,
+            Number::Big(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Float(n) => *n,
+            Number::Decimal(n) => n.to_f64(),
+        }
+    }
+
+    /// The value as an integer of 64 bits, if it has no fraction and fits in one
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            Number::Integer(n) => Some(*n),
+            Number::Float(n) if n.fract() == 0.0 => n.to_i64(),
+            Number::Decimal(n) if n.scale == 0 => n.digits.to_i64(),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer of any size, if it has no fraction
+    fn to_bigint(&self) -> Option<BigInt> {
+        match self {
+            Number::Integer(n) => Some(BigInt::from(*n)),
+            Number::Big(n) => Some(n.clone()),
+            Number::Float(n) if n.fract() == 0.0 => format!("{}", n).parse().ok(),
+            Number::Decimal(n) if n.scale == 0 => Some(n.digits.clone()),
+            _ => None,
+        }
+    }
+
+    /// The exact decimal of the value, the floats are taken as they are written
+    fn to_decimal(&self) -> Option<Decimal> {
+        match self {
+            Number::Integer(n) => Some(Decimal::new(BigInt::from(*n), 0)),
+            Number::Big(n) => Some(Decimal::new(n.clone(), 0)),
+            Number::Float(n) if n.is_finite() => Decimal::parse(&format!("{}", n)),
+            Number::Float(_) => None,
+            Number::Decimal(n) => Some(n.clone()),
+        }
+    }
+
+    /// The integer as a decimal with no fraction, for the decimal mode where `1 / 10` is `0.1`.
+    /// The floats and decimals stay as they are.
+    pub fn as_decimal(&self) -> Number {
+        match self {
+            Number::Integer(_) | Number::Big(_) => self
+                .to_decimal()
+                .map_or_else(|| self.clone(), Number::Decimal),
+            _ => self.clone(),
+        }
+    }
+
+    /// The exact value of the number, the floats have one too because they are a fraction with a
+    /// power of two below it. `None` for `NaN` and the infinities.
+    fn exact(&self) -> Option<Decimal> {
+        let n = match self {
+            Number::Float(n) if n.is_finite() => *n,
+            Number::Float(_) => return None,
+            _ => return self.to_decimal(),
+        };
+        let bits = n.to_bits();
+        let (mantissa, exponent) = match ((bits >> 52) & 0x7ff) as i64 {
+            0 => (bits & 0xf_ffff_ffff_ffff, -1074),
+            exponent => (bits & 0xf_ffff_ffff_ffff | 1 << 52, exponent - 1075),
+        };
+        let mut digits = BigInt::from(mantissa);
+        if n.is_sign_negative() {
+            digits = -digits;
+        }
+        // A fraction over 2 to the power of k is the same as the fraction times 5 to the power
+        // of k over 10 to the power of k
+        Some(match exponent {
+            exponent if exponent >= 0 => Decimal::new(digits << exponent as usize, 0),
+            exponent => {
+                let scale = -exponent as u32;
+                Decimal::new(
+                    digits * num_traits::pow(BigInt::from(5), scale as usize),
+                    scale,
+                )
+            }
+        })
+    }
+
+    pub fn add(&self, other: &Number) -> Arithmetic {
+        self.combine(
+            other,
+            i64::checked_add,
+            |a, b| a + b,
+            |a, b| a + b,
+            Decimal::add,
+        )
+    }
+
+    pub fn sub(&self, other: &Number) -> Arithmetic {
+        self.combine(
+            other,
+            i64::checked_sub,
+            |a, b| a - b,
+            |a, b| a - b,
+            Decimal::sub,
+        )
+    }
+
+    pub fn mul(&self, other: &Number) -> Arithmetic {
+        self.combine(
+            other,
+            i64::checked_mul,
+            |a, b| a * b,
+            |a, b| a * b,
+            Decimal::mul,
+        )
+    }
+
+    /// The division with `/`, a float if both numbers are integers. The big integers are divided
+    /// exactly before the result is rounded to a float.
+    pub fn div(&self, other: &Number) -> Arithmetic {
+        if other.is_zero() {
+            return Err(DIVISION_BY_ZERO);
+        }
+        if let Some((a, b)) = self.decimals(other) {
+            return Ok(Number::Decimal(a.div(&b)));
+        }
+        // The integers that are exact as floats can be divided as floats
+        let fits = |n: &Number| match n {
+            Number::Integer(n) => n.unsigned_abs() <= EXACT_FLOAT,
+            Number::Big(_) => false,
+            _ => true,
+        };
+        let result = if fits(self) && fits(other) {
+            self.to_f64() / other.to_f64()
+        } else if self.is_integer() && other.is_integer() {
+            ratio_to_f64(&self.to_bigint().unwrap(), &other.to_bigint().unwrap())
+        } else {
+            match (self.exact(), other.exact()) {
+                (Some(a), Some(b)) => a.div(&b).to_f64(),
+                _ => self.to_f64() / other.to_f64(),
+            }
+        };
+        self.float(other, result)
+    }
+
+    /// The division rounded down, with `//`: `7 // 2` is `3` and `-7 // 2` is `-4`
+    pub fn floor_div(&self, other: &Number) -> Arithmetic {
+        if other.is_zero() {
+            return Err(DIVISION_BY_ZERO);
+        }
+        self.combine(
+            other,
+            |a, b| a.checked_div(b).map(|_| Integer::div_floor(&a, &b)),
+            |a, b| a.div_floor(&b),
+            |a, b| (a / b).floor(),
+            Decimal::floor_div,
+        )
+    }
+
+    /// The remainder of the division, with `%`. It has the sign of the divisor, so `-7 % 3` is
+    /// `2`, as `-7 // 3` is `-3`.
+    pub fn rem(&self, other: &Number) -> Arithmetic {
+        if other.is_zero() {
+            return Err(DIVISION_BY_ZERO);
+        }
+        self.combine(
+            other,
+            |a, b| a.checked_rem(b).map(|_| a.mod_floor(&b)),
+            |a, b| a.mod_floor(&b),
+            |a, b| match a % b {
+                r if r != 0.0 && (r < 0.0) != (b < 0.0) => r + b,
+                r => r,
+            },
+            Decimal::rem,
+        )
+    }
+
+    /// The number to the power of the other one, with `**`. The powers of integers and decimals
+    /// are exact when the exponent is an integer, but an integer to a negative power is a float,
+    /// like `2 ** -1` that is `0.5`.
+    pub fn pow(&self, other: &Number) -> Arithmetic {
+        let zero = Number::Integer(0);
+        if self.is_zero() && *other < zero {
+            return Err("zero can not be raised to a negative power");
+        }
+        if *self < zero && !other.is_whole() {
+            return Err("a negative number can not be raised to a fractional power");
+        }
+
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) if *b >= 0 => {
+                let exponent = u32::try_from(*b).map_err(|_| TOO_BIG)?;
+                Ok(match a.checked_pow(exponent) {
+                    Some(n) => Number::Integer(n),
+                    None => Number::from(BigInt::from(*a).pow(exponent)),
+                })
+            }
+            (Number::Big(a), Number::Integer(b)) if *b >= 0 => {
+                let exponent = u32::try_from(*b).map_err(|_| TOO_BIG)?;
+                Ok(Number::from(a.pow(exponent)))
+            }
+            (Number::Decimal(a), Number::Integer(b)) => a.pow(*b).map(Number::Decimal),
+            _ => {
+                let result = self.float(other, self.powf(other.to_f64()))?;
+                Ok(match (self, other) {
+                    (Number::Decimal(_), _) | (_, Number::Decimal(_)) => {
+                        result.to_decimal().map_or(result, Number::Decimal)
+                    }
+                    _ => result,
+                })
+            }
+        }
+    }
+
+    /// Both numbers as decimals, if one of them is a decimal and the other one can be
+    fn decimals(&self, other: &Number) -> Option<(Decimal, Decimal)> {
+        match (self, other) {
+            (Number::Decimal(_), _) | (_, Number::Decimal(_)) => {
+                Some((self.to_decimal()?, other.to_decimal()?))
+            }
+            _ => None,
+        }
+    }
+
+    /// The float power, a big integer that is too big to be a float is split in a float and a
+    /// power of two, so its power can still be a float
+    fn powf(&self, exponent: f64) -> f64 {
+        match self {
+            Number::Big(n) if n.is_positive() && !self.to_f64().is_finite() => {
+                let shift = n.bits() - 64;
+                let mantissa = (n >> shift).to_f64().unwrap_or(f64::NAN);
+                ((mantissa.log2() + shift as f64) * exponent).exp2()
+            }
+            _ => self.to_f64().powf(exponent),
+        }
+    }
+
+    /// The float result of an operator, an error if it is infinite or `NaN` when the numbers were
+    /// not
+    fn float(&self, other: &Number, result: f64) -> Arithmetic {
+        match result.is_finite() || !self.is_finite() || !other.is_finite() {
+            true => Ok(Number::Float(result)),
+            false => Err(TOO_BIG_FLOAT),
+        }
+    }
+
+    /// Apply an operator: to integers if both are integers, to decimals if one of them is a
+    /// decimal and to floats if not. The integers that do not fit in 64 bits become big, and a
+    /// big integer and a float are combined with their exact values before the result is
+    /// rounded to a float.
+    fn combine(
+        &self,
+        other: &Number,
+        small: fn(i64, i64) -> Option<i64>,
+        big: fn(BigInt, BigInt) -> BigInt,
+        float: fn(f64, f64) -> f64,
+        decimal: fn(&Decimal, &Decimal) -> Decimal,
+    ) -> Arithmetic {
+        if let (Number::Integer(a), Number::Integer(b)) = (self, other) {
+            if let Some(n) = small(*a, *b) {
+                return Ok(Number::Integer(n));
+            }
+        }
+        if self.is_integer() && other.is_integer() {
+            let (a, b) = (self.to_bigint().unwrap(), other.to_bigint().unwrap());
+            return Ok(Number::from(big(a, b)));
+        }
+        if let Some((a, b)) = self.decimals(other) {
+            return Ok(Number::Decimal(decimal(&a, &b)));
+        }
+        let result = match (self, other) {
+            (Number::Big(_), _) | (_, Number::Big(_)) => match (self.exact(), other.exact()) {
+                (Some(a), Some(b)) => decimal(&a, &b).to_f64(),
+                _ => float(self.to_f64(), other.to_f64()),
+            },
+            _ => float(self.to_f64(), other.to_f64()),
+        };
+        self.float(other, result)
+    }
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Number::Integer(n)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(n: f64) -> Self {
+        Number::Float(n)
+    }
+}
+
+impl From<BigInt> for Number {
+    /// A big integer, or a small one if it fits in 64 bits
+    fn from(n: BigInt) -> Self {
+        match n.to_i64() {
+            Some(n) => Number::Integer(n),
+            None => Number::Big(n),
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+
+    fn neg(self) -> Number {
+        match self {
+            Number::Integer(n) => match n.checked_neg() {
+                Some(n) => Number::Integer(n),
+                None => Number::from(-BigInt::from(n)),
+            },
+            Number::Big(n) => Number::from(-n),
+            Number::Float(n) => Number::Float(-n),
+            Number::Decimal(n) => Number::Decimal(Decimal::new(-n.digits, n.scale)),
+        }
+    }
+}
+
+impl PartialEq for Number {
+    /// The numbers are compared by their exact value, `1 == 1.0` but the float
+    /// `9007199254740992.0` is not the integer `9007199254740993`. `NaN` is not equal to anything,
+    /// so the numbers are not `Eq`.
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Integer(a), Number::Integer(b)) => a.partial_cmp(b),
+            (Number::Float(a), Number::Float(b)) => a.partial_cmp(b),
+            (Number::Integer(a), Number::Float(b)) if a.unsigned_abs() <= EXACT_FLOAT => {
+                (*a as f64).partial_cmp(b)
+            }
+            (Number::Float(a), Number::Integer(b)) if b.unsigned_abs() <= EXACT_FLOAT => {
+                a.partial_cmp(&(*b as f64))
+            }
+            (Number::Float(a), _) if a.is_infinite() => Some(a.partial_cmp(&0.0)?),
+            (_, Number::Float(b)) if b.is_infinite() => Some(0.0.partial_cmp(b)?),
+            _ => Some(self.exact()?.cmp(&other.exact()?)),
+        }
+    }
+}
+
+impl Hash for Number {
+    /// The numbers that are equal have the same hash, like `2`, `2.0` and the decimal `2.0`,
+    /// because it comes from their exact value
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Number::Integer(n) => n.hash(state),
+            Number::Float(n) if !n.is_finite() => n.to_bits().hash(state),
+            _ => {
+                if let Some(exact) = self.exact() {
+                    match (exact.scale, exact.digits.to_i64()) {
+                        (0, Some(n)) => n.hash(state),
+                        (scale, _) => {
+                            exact.digits.hash(state);
+                            scale.hash(state);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Number {
+    /// The floats and decimals always have a point, so they look different from the integers
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Number::Integer(n) => write!(f, "{}", n),
+            Number::Big(n) => write!(f, "{}", n),
+            Number::Float(n) if n.is_finite() && n.fract() == 0.0 => write!(f, "{:.1}", n),
+            Number::Float(n) => write!(f, "{}", n),
+            Number::Decimal(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+/// An exact number with a fraction, the digits divided by 10 to the power of the scale. The
+/// digits never end in zeros after the point, so the same number is always written the same way.
+#[derive(Debug, Clone)]
+pub struct Decimal {
+    digits: BigInt,
+    scale: u32,
+}
+
+impl Decimal {
+    fn new(mut digits: BigInt, mut scale: u32) -> Self {
+        let ten = BigInt::from(10);
+        while scale > 0 && (&digits % &ten).is_zero() {
+            digits /= &ten;
+            scale -= 1;
+        }
+        Decimal { digits, scale }
+    }
+
+    /// Read a decimal written like `-12.50`
+    pub fn parse(text: &str) -> Option<Decimal> {
+        let (whole, fraction) = match text.find('.') {
+            Some(point) => (&text[..point], &text[point + 1..]),
+            None => (text, ""),
+        };
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let digits: BigInt = format!("{}{}", whole, fraction).parse().ok()?;
+        Some(Decimal::new(digits, fraction.len() as u32))
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    /// The digits of both decimals with the same scale
+    fn align(&self, other: &Decimal) -> (BigInt, BigInt, u32) {
+        let scale = self.scale.max(other.scale);
+        (
+            &self.digits * power_of_ten(scale - self.scale),
+            &other.digits * power_of_ten(scale - other.scale),
+            scale,
+        )
+    }
+
+    fn add(&self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.align(other);
+        Decimal::new(a + b, scale)
+    }
+
+    fn sub(&self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.align(other);
+        Decimal::new(a - b, scale)
+    }
+
+    fn mul(&self, other: &Decimal) -> Decimal {
+        Decimal::new(&self.digits * &other.digits, self.scale + other.scale)
+    }
+
+    /// The division rounded down, an integer
+    fn floor_div(&self, other: &Decimal) -> Decimal {
+        let (a, b, _) = self.align(other);
+        Decimal::new(a.div_floor(&b), 0)
+    }
+
+    /// The remainder of the division rounded down, with the sign of the divisor
+    fn rem(&self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = self.align(other);
+        Decimal::new(a.mod_floor(&b), scale)
+    }
+
+    /// The decimal to the power of an integer, the negative powers are divisions so they can be
+    /// rounded. The decimal can not be zero if the power is negative.
+    fn pow(&self, exponent: i64) -> std::result::Result<Decimal, &'static str> {
+        let positive = u32::try_from(exponent.unsigned_abs()).map_err(|_| TOO_BIG)?;
+        let scale = self.scale.checked_mul(positive).ok_or(TOO_BIG)?;
+        let power = Decimal::new(self.digits.pow(positive), scale);
+        Ok(match exponent < 0 {
+            true => Decimal::new(BigInt::from(1), 0).div(&power),
+            false => power,
+        })
+    }
+
+    /// The division, exact or rounded to `PRECISION` significant digits with the last one even
+    /// when it is halfway. The other decimal can not be zero.
+    fn div(&self, other: &Decimal) -> Decimal {
+        let length = |n: &BigInt| n.abs().to_string().len();
+        let extra = (PRECISION + length(&other.digits)).saturating_sub(length(&self.digits)) as u32;
+        let scale = self.scale + extra;
+        let numerator = &self.digits * power_of_ten(extra + other.scale);
+        let (mut quotient, remainder) = numerator.div_rem(&other.digits);
+
+        let twice = remainder.abs() * 2;
+        let divisor = other.digits.abs();
+        if twice > divisor || (twice == divisor && quotient.is_odd()) {
+            match numerator.is_negative() == other.digits.is_negative() {
+                true => quotient += 1,
+                false => quotient -= 1,
+            }
+        }
+        Decimal::new(quotient, scale)
+    }
+}
+
+impl PartialEq for Decimal {
+    fn eq(&self, other: &Decimal) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Decimal {}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Decimal) -> Ordering {
+        let (a, b, _) = self.align(other);
+        a.cmp(&b)
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let digits = self.digits.abs().to_string();
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", digits, width = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        let sign = if self.digits.is_negative() { "-" } else { "" };
+        match fraction {
+            "" => write!(f, "{}{}.0", sign, whole),
+            _ => write!(f, "{}{}.{}", sign, whole, fraction),
+        }
+    }
+}
+
+/// The float nearest to `a / b`, found with integers so it is right when they are too big to be
+/// floats. The quotient keeps 64 bits and the remainder is a last bit, so it rounds as the exact
+/// fraction would.
+fn ratio_to_f64(a: &BigInt, b: &BigInt) -> f64 {
+    let negative = a.is_negative() != b.is_negative();
+    let (a, b) = (a.abs(), b.abs());
+    let shift = 64 - (a.bits() as i64 - b.bits() as i64);
+    let (a, b) = match shift >= 0 {
+        true => (a << shift as u64, b),
+        false => (a, b << -shift as u64),
+    };
+    let (quotient, remainder) = a.div_rem(&b);
+    let mut quotient = quotient.to_u128().unwrap_or(u128::MAX);
+    if !remainder.is_zero() {
+        quotient |= 1;
+    }
+    // Multiply by the power of two in steps, so the steps are not infinite when the result is not
+    let (mut n, mut exponent) = (quotient as f64, -shift);
+    while exponent.abs() > 1000 {
+        let step = 1000 * exponent.signum();
+        n *= 2f64.powi(step as i32);
+        exponent -= step;
+    }
+    n *= 2f64.powi(exponent as i32);
+    match negative {
+        true => -n,
+        false => n,
+    }
+}
+
+fn power_of_ten(exponent: u32) -> BigInt {
+    num_traits::pow(BigInt::from(10), exponent as usize)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(text: &str) -> Number {
+        Number::parse(text, true).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(
+            number("0.1").add(&number("0.2")).unwrap().to_string(),
+            "0.3"
+        );
+        assert_eq!(Number::parse("0.1", false).unwrap().to_string(), "0.1");
+        assert_eq!(number("1.50").mul(&number("2")).unwrap().to_string(), "3.0");
+        assert_eq!(
+            number("2").div(&number("3.0")).unwrap().to_string(),
+            "0.6666666666666666666666666667"
+        );
+        assert_eq!(
+            number("-1").div(&number("8.0")).unwrap().to_string(),
+            "-0.125"
+        );
+        assert_eq!(number("10").div(&number("4")).unwrap().to_string(), "2.5");
+
+        let big = number("9223372036854775807").add(&number("1")).unwrap();
+        assert_eq!(big.to_string(), "9223372036854775808");
+        let small = big.sub(&number("1")).unwrap();
+        assert_eq!(small.to_string(), "9223372036854775807");
+        assert!(matches!(small, Number::Integer(_)));
+        assert_eq!(
+            (-number("9223372036854775808")).to_string(),
+            "-9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn test_division() {
+        let operate = |operator: fn(&Number, &Number) -> Arithmetic, a, b| {
+            operator(&number(a), &number(b)).map(|n| n.to_string())
+        };
+        assert_eq!(operate(Number::floor_div, "-7", "2"), Ok("-4".to_string()));
+        assert_eq!(operate(Number::floor_div, "7", "-2"), Ok("-4".to_string()));
+        assert_eq!(
+            operate(Number::floor_div, "7.5", "2"),
+            Ok("3.0".to_string())
+        );
+        assert_eq!(operate(Number::rem, "-7", "3"), Ok("2".to_string()));
+        assert_eq!(operate(Number::rem, "7", "-3"), Ok("-2".to_string()));
+        assert_eq!(operate(Number::rem, "-7.5", "2"), Ok("0.5".to_string()));
+        assert_eq!(operate(Number::pow, "2", "-1"), Ok("0.5".to_string()));
+        assert_eq!(operate(Number::pow, "4", "0.5"), Ok("2.0".to_string()));
+        assert_eq!(
+            operate(Number::pow, "2", "100"),
+            Ok("1267650600228229401496703205376".to_string())
+        );
+        assert_eq!(operate(Number::div, "1", "0"), Err(DIVISION_BY_ZERO));
+        assert_eq!(operate(Number::rem, "1", "0.0"), Err(DIVISION_BY_ZERO));
+        assert!(operate(Number::pow, "0", "-1").is_err());
+        assert!(operate(Number::pow, "-8", "0.5").is_err());
+
+        assert_eq!(operate(Number::pow, "0.1", "3"), Ok("0.001".to_string()));
+        assert_eq!(operate(Number::pow, "2.0", "-2"), Ok("0.25".to_string()));
+        let exact = number("1").as_decimal();
+        assert_eq!(exact.div(&number("10")).unwrap().to_string(), "0.1");
+        assert!(matches!(exact.div(&number("3")), Ok(Number::Decimal(_))));
+
+        let float = |text| Number::parse(text, false).unwrap();
+        assert!(matches!(float("7.5").floor_div(&float("2")), Ok(Number::Float(n)) if n == 3.0));
+        assert!(matches!(float("-7.5").rem(&float("2")), Ok(Number::Float(n)) if n == 0.5));
+    }
+
+    #[test]
+    fn test_big_operands() {
+        let huge = number("2").pow(&number("10000")).unwrap();
+        let float = |text| Number::parse(text, false).unwrap();
+        let as_f64 = |result: Arithmetic| result.map(|n| n.to_f64());
+
+        assert_eq!(as_f64(huge.div(&huge)), Ok(1.0));
+        assert_eq!(as_f64(huge.div(&number("3"))), Err(TOO_BIG_FLOAT));
+        let power = number("2").pow(&number("64")).unwrap();
+        assert_eq!(
+            as_f64(power.div(&number("3"))),
+            Ok(18446744073709551616.0 / 3.0)
+        );
+        assert_eq!(as_f64(power.div(&float("0.5"))), Ok(36893488147419103232.0));
+
+        assert_eq!(as_f64((-huge.clone()).rem(&float("1.5"))), Ok(0.5));
+        assert_eq!(as_f64(huge.floor_div(&float("1.5"))), Err(TOO_BIG_FLOAT));
+        assert!(matches!(huge.floor_div(&huge), Ok(Number::Integer(1))));
+        assert_eq!(as_f64(huge.rem(&float("0.25"))), Ok(0.0));
+
+        let root = number("2")
+            .pow(&number("2000"))
+            .unwrap()
+            .pow(&float("0.25"));
+        let expected = 2f64.powi(500);
+        assert!((root.unwrap().to_f64() - expected).abs() < expected * 1e-12);
+        assert_eq!(as_f64(huge.pow(&float("0.5"))), Err(TOO_BIG_FLOAT));
+        assert_eq!(as_f64(huge.pow(&number("0.5"))), Err(TOO_BIG_FLOAT));
+        assert!(matches!(power.pow(&number("0")), Ok(Number::Integer(1))));
+        assert_eq!(as_f64(huge.pow(&number("-1"))), Ok(0.0));
+
+        assert_eq!(as_f64(huge.add(&float("0.5"))), Err(TOO_BIG_FLOAT));
+        assert_eq!(
+            as_f64(Number::Float(1e308).mul(&float("10.0"))),
+            Err(TOO_BIG_FLOAT)
+        );
+        assert_eq!(as_f64(Number::Float(1e308).add(&float("1.0"))), Ok(1e308));
+    }
+
+    #[test]
+    fn test_compare() {
+        assert_eq!(number("1"), Number::Float(1.0));
+        assert_eq!(number("2.50"), number("2.5"));
+        assert!(number("0.1") < number("0.11"));
+        assert!(number("99999999999999999999") > Number::Float(1.0));
+        assert_ne!(Number::Float(f64::NAN), Number::Float(f64::NAN));
+
+        // The floats are compared by their exact value, so `==` stays transitive
+        let float = Number::Float(9007199254740992.0);
+        assert_ne!(number("9007199254740993"), float);
+        assert!(number("9007199254740993") > float);
+        assert_eq!(number("9007199254740992"), float);
+        assert_ne!(Number::Float(0.1), number("0.1"));
+        assert_eq!(Number::Float(0.5), number("0.5"));
+        assert!(Number::Float(f64::INFINITY) > number("99999999999999999999"));
+        assert!(Number::Float(1e23) < number("99999999999999991611393"));
+        assert_eq!(Number::Float(1e23), number("99999999999999991611392"));
+    }
+
+    #[test]
+    fn test_hash() {
+        let hash = |n: &Number| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            n.hash(&mut hasher);
+            hasher.finish()
+        };
+        let equal = [
+            (number("2"), Number::Float(2.0)),
+            (number("2.0"), Number::Float(2.0)),
+            (number("-0.5"), Number::Float(-0.5)),
+            (number("0"), Number::Float(-0.0)),
+            (number("99999999999999991611392"), Number::Float(1e23)),
+        ];
+        for (a, b) in equal.iter() {
+            assert_eq!(a, b);
+            assert_eq!(hash(a), hash(b), "{} {}", a, b);
+        }
+    }
+}
@@ -3,6 +3,9 @@ use std::collections::HashMap;
 use std::fmt;
 use std::hash::{Hash, Hasher};
 
+use super::number::Number;
+use ezcript_result::Span;
+
 /// All the tokens that the language accept
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind {
@@ -50,6 +53,10 @@ pub enum TokenKind {
     SlashEqual,
     PercentEqual,
     RArrow,
+    /// `..`, between the ends of a range
+    DoubleDot,
+    /// Only made when the lexer keeps the comments
+    Comment,
     Illegal,
     Eof,
 }
@@ -60,6 +67,13 @@ impl TokenKind {
     pub fn reserved(keyword: &str) -> Option<&Self> {
         RESERVED.get(keyword)
     }
+
+    /// All the reserved words, sorted
+    pub fn reserved_words() -> Vec<&'static str> {
+        let mut words: Vec<&'static str> = RESERVED.keys().cloned().collect();
+        words.sort_unstable();
+        words
+    }
 }
 
 /// The Token structure, for create and manage the tokens from the Lexer
@@ -68,7 +82,7 @@ pub struct Token {
     pub kind: TokenKind,
     pub lexeme: String,
     pub literal: Option<Literal>,
-    pub line: u64,
+    pub span: Span,
 }
 
 impl Token {
@@ -90,7 +104,7 @@ impl Default for Token {
             kind: TokenKind::Eof,
             lexeme: "".to_string(),
             literal: None,
-            line: 1,
+            span: Span::default(),
         }
     }
 }
@@ -100,7 +114,7 @@ impl fmt::Display for Token {
         write!(
             f,
             "Type: {:?}, Lexeme: {}, Literal: {:?} : [line {}]",
-            self.kind, self.lexeme, self.literal, self.line
+            self.kind, self.lexeme, self.literal, self.span.line
         )
     }
 }
@@ -110,7 +124,7 @@ impl fmt::Display for Token {
 pub enum Literal {
     Null,
     Boolean(bool),
-    Number(f64),
+    Number(Number),
     String(String),
 }
 
@@ -121,7 +135,7 @@ impl Hash for Literal {
         match *self {
             Literal::Null => "".hash(state),
             Literal::Boolean(b) => b.hash(state),
-            Literal::Number(f) => f.to_bits().hash(state),
+            Literal::Number(ref n) => n.hash(state),
             Literal::String(ref s) => s.hash(state),
         }
     }
@@ -129,23 +143,12 @@ impl Hash for Literal {
 
 impl PartialEq for Literal {
     fn eq(&self, other: &Literal) -> bool {
-        match *self {
-            Literal::Null => match *other {
-                Literal::Null => true,
-                _ => false,
-            },
-            Literal::Boolean(ref a) => match *other {
-                Literal::Boolean(ref b) => a.eq(b),
-                _ => false,
-            },
-            Literal::Number(ref a) => match *other {
-                Literal::Number(ref b) => a.eq(b),
-                _ => false,
-            },
-            Literal::String(ref a) => match *other {
-                Literal::String(ref b) => a.eq(b),
-                _ => false,
-            },
+        match (self, other) {
+            (Literal::Null, Literal::Null) => true,
+            (Literal::Boolean(a), Literal::Boolean(b)) => a.eq(b),
+            (Literal::Number(a), Literal::Number(b)) => a.eq(b),
+            (Literal::String(a), Literal::String(b)) => a.eq(b),
+            _ => false,
         }
     }
 }
@@ -153,10 +156,10 @@ impl PartialEq for Literal {
 impl PartialOrd<Self> for Literal {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         match (self, other) {
-            (&Literal::Null, &Literal::Null) => Some(Ordering::Equal),
-            (&Literal::String(ref l), &Literal::String(ref r)) => l.partial_cmp(r),
-            (&Literal::Number(ref l), &Literal::Number(ref r)) => l.partial_cmp(r),
-            (&Literal::Boolean(ref l), &Literal::Boolean(ref r)) => l.partial_cmp(r),
+            (Literal::Null, Literal::Null) => Some(Ordering::Equal),
+            (Literal::String(l), Literal::String(r)) => l.partial_cmp(r),
+            (Literal::Number(l), Literal::Number(r)) => l.partial_cmp(r),
+            (Literal::Boolean(l), Literal::Boolean(r)) => l.partial_cmp(r),
             _ => None,
         }
     }
@@ -167,7 +170,7 @@ impl fmt::Display for Literal {
         match *self {
             Literal::Null => write!(f, "null"),
             Literal::Boolean(b) => write!(f, "{}", b),
-            Literal::Number(n) => write!(f, "{}", n),
+            Literal::Number(ref n) => write!(f, "{}", n),
             Literal::String(ref s) => write!(f, "{}", s),
         }
     }
@@ -197,6 +200,8 @@ lazy_static! {
         ("match", TokenKind::Keyword),
         ("class", TokenKind::Keyword),
         ("inherit", TokenKind::Keyword),
+        ("interface", TokenKind::Keyword),
+        ("super", TokenKind::Keyword),
         ("null", TokenKind::Null),
     ]
     .iter()
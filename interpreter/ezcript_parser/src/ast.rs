@@ -0,0 +1,235 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use ezcript_lexer::tokens::{Token, TokenKind};
+
+/// An expression, a piece of code that gives back a value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// A token with a `Literal`, like `1`, `"hello"`, `true` or `null`
+    Literal(Token),
+    /// The name of a variable, with the slot where the resolver found it. The globals have none.
+    Variable(Token, Cell<Option<Slot>>),
+    /// An expression between parentheses
+    Grouping(Box<Expr>),
+    /// An operator before an expression, like `-x` or `not x`
+    Unary(Token, Box<Expr>),
+    /// An operator between two expressions, like `1 + 2`
+    Binary(Box<Expr>, Token, Box<Expr>),
+    /// `and` or `or` between two expressions, the right one is only evaluated if needed
+    Logical(Box<Expr>, Token, Box<Expr>),
+    /// A call, the token is the closing parenthesis
+    Call(Box<Expr>, Token, Vec<Argument>),
+    /// The access to an attribute, like `value.type`
+    Get(Box<Expr>, Token),
+    /// An element of a value, like `list[0]`, the token is the opening bracket
+    Index(Box<Expr>, Token, Box<Expr>),
+    /// An array, like `[1, 2, 3]`, the token is the opening bracket
+    Array(Token, Vec<Expr>),
+    /// An associative array, like `{ name: "Ana", 5: true }`, the token is the opening brace
+    Map(Token, Vec<(Expr, Expr)>),
+    /// A tuple, like `(1, "a")` or `(1,)`, the token is the opening parenthesis
+    Tuple(Token, Vec<Expr>),
+    /// A method of the class that the current one inherits, like `super.init`, the first token
+    /// is the keyword
+    Super(Token, Token),
+    /// An anonymous function, like `func(x) do return x * 2`
+    Function(Rc<Function>),
+    /// An `if` used as a value, a `return` inside of it gives the value of the whole `if`
+    If(Box<If>),
+    /// A `match` used as a value, like an `if`
+    Match(Box<Match>),
+}
+
+impl Expr {
+    /// A token of the expression to point the errors at
+    pub fn token(&self) -> &Token {
+        match self {
+            Expr::Literal(token) | Expr::Variable(token, _) => token,
+            Expr::Grouping(expr) => expr.token(),
+            Expr::Unary(operator, _)
+            | Expr::Binary(_, operator, _)
+            | Expr::Logical(_, operator, _) => operator,
+            Expr::Call(_, paren, _) => paren,
+            Expr::Get(_, name) => name,
+            Expr::Super(keyword, _) => keyword,
+            Expr::Index(_, bracket, _) => bracket,
+            Expr::Array(token, _) | Expr::Map(token, _) | Expr::Tuple(token, _) => token,
+            Expr::Function(function) => &function.name,
+            Expr::If(if_body) => &if_body.keyword,
+            Expr::Match(match_body) => &match_body.keyword,
+        }
+    }
+}
+
+/// Where the declaration of a local variable is: how many scopes there are between the use and
+/// the declaration, and the position of the variable in its scope
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+impl Slot {
+    /// The same slot seen from the scope that contains the current one
+    pub fn up(self) -> Slot {
+        Slot {
+            depth: self.depth - 1,
+            ..self
+        }
+    }
+}
+
+/// A statement, a piece of code that does something
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    /// An expression whose value is discarded
+    Expression(Expr),
+    /// `set name: type = value`, the type and the value are optional
+    Set(Token, Option<TypeExpr>, Option<Expr>),
+    /// `const name: type = value`, the type is optional
+    Const(Token, Option<TypeExpr>, Expr),
+    /// `target = value`, the token is the operator, so it can be `+=`, `-=`, etc
+    Assign(Expr, Token, Expr),
+    /// `target++` or `target--`
+    Increment(Expr, Token),
+    If(If),
+    Match(Match),
+    /// `while condition do ...`
+    While(Expr, Vec<Stmt>),
+    /// `do ... while condition`
+    DoWhile(Vec<Stmt>, Expr),
+    /// `for each name in iterable do ...`
+    ForEach(Token, Expr, Vec<Stmt>),
+    Func(Rc<Function>),
+    Class(Rc<Class>),
+    Interface(Rc<Interface>),
+    /// `return value`, the token is the keyword
+    Return(Token, Option<Expr>),
+    /// `break`, the token is the keyword
+    Break(Token),
+}
+
+/// `if condition do ... elseif condition do ... else do ...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct If {
+    /// The `if` keyword
+    pub keyword: Token,
+    /// The condition and the body of the `if` and of every `elseif`
+    pub branches: Vec<(Expr, Vec<Stmt>)>,
+    /// The body of the `else`
+    pub otherwise: Option<Vec<Stmt>>,
+}
+
+/// `match subject do` and the arms in the indented lines after it, only the first arm whose
+/// pattern matches is executed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    /// The `match` keyword
+    pub keyword: Token,
+    pub subject: Expr,
+    pub arms: Vec<Arm>,
+}
+
+/// `pattern if guard do ...`, the guard is optional
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Vec<Stmt>,
+}
+
+/// What an arm of a `match` compares the value with
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    /// `_`, matches every value
+    Wildcard(Token),
+    /// A number, string, boolean or `null`, matches the values equal to it
+    Literal(Token),
+    /// `low..high`, matches the numbers between both ends, both included. The second token is
+    /// the `..`
+    Range(Token, Token, Token),
+    /// A name, matches every value and saves it in a variable with that name
+    Binding(Token),
+    /// `[a, b]`, matches the arrays with the same length whose elements match
+    Array(Token, Vec<Pattern>),
+    /// `(a, b)`, matches the tuples with the same length whose elements match
+    Tuple(Token, Vec<Pattern>),
+    /// `Name { field, other: pattern }`, matches the instances of the class whose fields match,
+    /// a field alone saves its value in a variable with its name
+    Instance(Token, Vec<(Token, Pattern)>),
+}
+
+impl Pattern {
+    /// True if the pattern matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        matches!(self, Pattern::Wildcard(_) | Pattern::Binding(_))
+    }
+}
+
+/// An argument of a call, `name = value` when it has a name
+#[derive(Debug, Clone, PartialEq)]
+pub struct Argument {
+    pub name: Option<Token>,
+    pub value: Expr,
+}
+
+/// `func name(params) -> type do ...`, the name is missing in the anonymous functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The name, or the `func` keyword if the function is anonymous
+    pub name: Token,
+    pub params: Vec<Param>,
+    pub return_type: Option<TypeExpr>,
+    pub body: Vec<Stmt>,
+}
+
+impl Function {
+    /// The name of the function, `None` if it is anonymous
+    pub fn name(&self) -> Option<&str> {
+        match self.name.kind {
+            TokenKind::Ident => Some(&self.name.lexeme),
+            _ => None,
+        }
+    }
+}
+
+/// `class Name inherit Other, IOther do ...`, the body only has methods
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: Token,
+    /// The names of the class and of the interfaces that it inherits
+    pub inherits: Vec<Token>,
+    pub methods: Vec<Rc<Function>>,
+}
+
+/// `interface Name do ...`, the body has the fields that the classes that inherit it must set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    pub name: Token,
+    pub fields: Vec<Field>,
+}
+
+/// A field of an interface, like `nombre: String` or `edad: { type: Integer, required: false }`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: Token,
+    pub type_expr: TypeExpr,
+    pub required: bool,
+}
+
+/// A parameter of a function, like `matched: number` or `times = 2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Token,
+    pub annotation: Option<TypeExpr>,
+    /// The value that the parameter takes when the call does not give it one
+    pub default: Option<Expr>,
+}
+
+/// A type annotation, like the `Integer` in `set num: Integer = 34`
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeExpr {
+    /// The name of a type
+    Name(Token),
+}
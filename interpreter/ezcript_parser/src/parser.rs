@@ -0,0 +1,1217 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::ast::{
+    Argument, Arm, Class, Expr, Field, Function, If, Interface, Match, Param, Pattern, Stmt,
+    TypeExpr,
+};
+use ezcript_lexer::tokens::{Literal, Token, TokenKind};
+use ezcript_result::{Error, Result, Span, Warning};
+
+/// A recursive descent parser that turns the tokens of the `Lexer` into statements.
+///
+/// The statements end at the end of a line, except inside of parentheses, brackets and braces,
+/// and the blocks are made with the `Indent` and `Dedent` tokens that the lexer creates.
+#[derive(Debug)]
+pub struct Parser {
+    tokens: Vec<Token>,
+    current: usize,
+    /// How many parentheses, brackets and braces are open
+    nesting: usize,
+    /// The classes declared until now and the names that they inherit, to know the cases that a
+    /// `match` has to cover
+    classes: HashMap<String, Vec<String>>,
+    warnings: Vec<Warning>,
+}
+
+impl Parser {
+    pub fn new(mut tokens: Vec<Token>) -> Self {
+        if tokens.last().is_none_or(|t| t.kind != TokenKind::Eof) {
+            let mut eof = Token::default();
+            if let Some(last) = tokens.last() {
+                eof.span.line = end_line(last);
+                eof.span.start = last.span.end;
+                eof.span.end = last.span.end;
+            }
+            tokens.push(eof);
+        }
+
+        Parser {
+            tokens,
+            current: 0,
+            nesting: 0,
+            classes: HashMap::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// The problems found while parsing that do not stop the program
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Parse the whole program
+    pub fn parse(&mut self) -> Result<Vec<Stmt>> {
+        let mut statements = Vec::new();
+
+        while !self.is_at_end() {
+            if self.check(TokenKind::Indent) {
+                return Err(self.error(self.peek(), "unexpected indentation"));
+            }
+            statements.push(self.statement()?);
+            self.end_statement()?;
+        }
+
+        Ok(statements)
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.match_keyword("set") {
+            return self.set_statement();
+        }
+        if self.match_keyword("const") {
+            return self.const_statement();
+        }
+        if self.check_keyword("if") {
+            return Ok(Stmt::If(self.if_body()?));
+        }
+        if self.check_keyword("match") {
+            return Ok(Stmt::Match(self.match_body()?));
+        }
+        if self.match_keyword("while") {
+            let condition = self.expression()?;
+            let body = self.block()?;
+            return Ok(Stmt::While(condition, body));
+        }
+        if self.match_keyword("do") {
+            return self.do_while_statement();
+        }
+        if self.match_keyword("for") {
+            return self.for_each_statement();
+        }
+        if self.check_keyword("func") && self.peek_next().kind == TokenKind::Ident {
+            self.advance();
+            let name = self.advance().clone();
+            return Ok(Stmt::Func(Rc::new(self.function(name)?)));
+        }
+        if self.match_keyword("class") {
+            return self.class_statement();
+        }
+        if self.match_keyword("interface") {
+            return self.interface_statement();
+        }
+        if self.match_keyword("return") {
+            let keyword = self.previous().clone();
+            let value = if self.ends_statement() {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            return Ok(Stmt::Return(keyword, value));
+        }
+        if self.match_keyword("break") {
+            return Ok(Stmt::Break(self.previous().clone()));
+        }
+
+        self.expression_statement()
+    }
+
+    fn set_statement(&mut self) -> Result<Stmt> {
+        let name = self.consume(TokenKind::Ident, "expected a variable name after 'set'")?;
+        let annotation = self.annotation()?;
+        let value = if self.match_kinds(&[TokenKind::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        Ok(Stmt::Set(name, annotation, value))
+    }
+
+    fn const_statement(&mut self) -> Result<Stmt> {
+        let name = self.consume(TokenKind::Ident, "expected a constant name after 'const'")?;
+        let annotation = self.annotation()?;
+        self.consume(TokenKind::Equal, "expected '=' after the constant name")?;
+        let value = self.expression()?;
+
+        Ok(Stmt::Const(name, annotation, value))
+    }
+
+    fn do_while_statement(&mut self) -> Result<Stmt> {
+        let body = self.body()?;
+        self.consume_keyword("while", "expected 'while' after the body of 'do'")?;
+        let condition = self.expression()?;
+
+        Ok(Stmt::DoWhile(body, condition))
+    }
+
+    fn for_each_statement(&mut self) -> Result<Stmt> {
+        self.consume_keyword("each", "expected 'each' after 'for'")?;
+        let name = self.consume(TokenKind::Ident, "expected a variable name after 'each'")?;
+        self.consume_keyword("in", "expected 'in' after the variable name")?;
+        let iterable = self.expression()?;
+        let body = self.block()?;
+
+        Ok(Stmt::ForEach(name, iterable, body))
+    }
+
+    fn class_statement(&mut self) -> Result<Stmt> {
+        let name = self.consume(TokenKind::Ident, "expected a class name after 'class'")?;
+        let mut inherits = Vec::new();
+        if self.match_keyword("inherit") {
+            loop {
+                inherits
+                    .push(self.consume(TokenKind::Ident, "expected a class or interface name")?);
+                if !self.match_kinds(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        let mut methods = Vec::new();
+        for statement in self.block()? {
+            match statement {
+                Stmt::Func(method) => methods.push(method),
+                _ => {
+                    return Err(self.error(&name, "a class can only have methods in its body"));
+                }
+            }
+        }
+
+        self.classes.insert(
+            name.lexeme.clone(),
+            inherits.iter().map(|name| name.lexeme.clone()).collect(),
+        );
+        Ok(Stmt::Class(Rc::new(Class {
+            name,
+            inherits,
+            methods,
+        })))
+    }
+
+    fn interface_statement(&mut self) -> Result<Stmt> {
+        let name = self.consume(
+            TokenKind::Ident,
+            "expected an interface name after 'interface'",
+        )?;
+        self.consume_keyword("do", "expected 'do' before the block")?;
+
+        let mut fields = Vec::new();
+        if self.match_kinds(&[TokenKind::Indent]) {
+            while !self.check(TokenKind::Dedent) && !self.is_at_end() {
+                fields.push(self.field()?);
+                self.end_statement()?;
+            }
+            self.consume(TokenKind::Dedent, "expected the end of the block")?;
+        } else if self.new_line() || self.is_at_end() {
+            return Err(self.error(self.peek(), "expected an indented block"));
+        } else {
+            fields.push(self.field()?);
+        }
+
+        Ok(Stmt::Interface(Rc::new(Interface { name, fields })))
+    }
+
+    /// A field of an interface, its type can be in an object that says if it is required
+    fn field(&mut self) -> Result<Field> {
+        let name = self.consume(TokenKind::Ident, "expected a field name")?;
+        self.consume(TokenKind::Colon, "expected ':' after the field name")?;
+        if !self.match_kinds(&[TokenKind::LBrace]) {
+            let type_expr = self.type_expr()?;
+            return Ok(Field {
+                name,
+                type_expr,
+                required: true,
+            });
+        }
+
+        self.nesting += 1;
+        let mut type_expr = None;
+        let mut required = true;
+        while !self.check(TokenKind::RBrace) && !self.is_at_end() {
+            let key = self.consume(TokenKind::Ident, "expected 'type' or 'required'")?;
+            self.consume(TokenKind::Colon, "expected ':' after the key")?;
+            match key.lexeme.as_ref() {
+                "type" => type_expr = Some(self.type_expr()?),
+                "required" => {
+                    let value = self.consume(TokenKind::Boolean, "expected true or false")?;
+                    required = value.lexeme == "true";
+                }
+                _ => return Err(self.error(&key, "expected 'type' or 'required'")),
+            }
+            if !self.match_kinds(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+        self.nesting -= 1;
+        let brace = self.consume(TokenKind::RBrace, "expected '}' after the field")?;
+
+        match type_expr {
+            Some(type_expr) => Ok(Field {
+                name,
+                type_expr,
+                required,
+            }),
+            None => Err(self.error(&brace, "the field needs a 'type'")),
+        }
+    }
+
+    /// The parameters, return type and body of a function, after its name
+    fn function(&mut self, name: Token) -> Result<Function> {
+        let after_name = if name.kind == TokenKind::Ident {
+            "expected '(' after the function name"
+        } else {
+            "expected '(' after 'func'"
+        };
+        self.consume(TokenKind::LParen, after_name)?;
+        self.nesting += 1;
+
+        let mut params: Vec<Param> = Vec::new();
+        if !self.check(TokenKind::RParen) {
+            loop {
+                let name = self.consume(TokenKind::Ident, "expected a parameter name")?;
+                let annotation = self.annotation()?;
+                let default = if self.match_kinds(&[TokenKind::Equal]) {
+                    Some(self.expression()?)
+                } else {
+                    None
+                };
+                if default.is_none() && params.iter().any(|param| param.default.is_some()) {
+                    return Err(self.error(
+                        &name,
+                        "a parameter without a default value can not follow one with it",
+                    ));
+                }
+                params.push(Param {
+                    name,
+                    annotation,
+                    default,
+                });
+                if !self.match_kinds(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
+        self.nesting -= 1;
+        self.consume(TokenKind::RParen, "expected ')' after the parameters")?;
+        let return_type = if self.match_kinds(&[TokenKind::RArrow]) {
+            Some(self.type_expr()?)
+        } else {
+            None
+        };
+        let body = self.block()?;
+
+        Ok(Function {
+            name,
+            params,
+            return_type,
+            body,
+        })
+    }
+
+    /// The optional `: type` after the name of a variable or parameter
+    fn annotation(&mut self) -> Result<Option<TypeExpr>> {
+        if self.match_kinds(&[TokenKind::Colon]) {
+            Ok(Some(self.type_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn type_expr(&mut self) -> Result<TypeExpr> {
+        if self.match_kinds(&[TokenKind::Ident, TokenKind::Null]) {
+            return Ok(TypeExpr::Name(self.previous().clone()));
+        }
+        Err(self.error(self.peek(), "expected a type"))
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt> {
+        let expr = self.expression()?;
+
+        if self.same_line()
+            && self.match_kinds(&[
+                TokenKind::Equal,
+                TokenKind::PlusEqual,
+                TokenKind::MinEqual,
+                TokenKind::StarEqual,
+                TokenKind::SlashEqual,
+                TokenKind::PercentEqual,
+            ])
+        {
+            let operator = self.previous().clone();
+            let value = self.expression()?;
+            self.check_target(&expr, &operator)?;
+            return Ok(Stmt::Assign(expr, operator, value));
+        }
+
+        if self.same_line() && self.match_kinds(&[TokenKind::DoublePlus, TokenKind::DoubleMinus]) {
+            let operator = self.previous().clone();
+            self.check_target(&expr, &operator)?;
+            return Ok(Stmt::Increment(expr, operator));
+        }
+
+        Ok(Stmt::Expression(expr))
+    }
+
+    /// Only variables, attributes and elements can be assigned
+    fn check_target(&self, target: &Expr, operator: &Token) -> Result<()> {
+        match target {
+            Expr::Variable(_, _) | Expr::Get(_, _) | Expr::Index(_, _, _) => Ok(()),
+            _ => Err(self.error(operator, "invalid assignment target")),
+        }
+    }
+
+    /// `if condition do ... elseif condition do ... else do ...`
+    fn if_body(&mut self) -> Result<If> {
+        let keyword = self.consume_keyword("if", "expected 'if'")?;
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+
+        let condition = self.expression()?;
+        branches.push((condition, self.block()?));
+
+        loop {
+            if self.match_keyword("elseif") {
+                let condition = self.expression()?;
+                branches.push((condition, self.block()?));
+            } else if self.match_keyword("else") {
+                otherwise = Some(self.block()?);
+                break;
+            } else {
+                break;
+            }
+        }
+
+        Ok(If {
+            keyword,
+            branches,
+            otherwise,
+        })
+    }
+
+    fn match_body(&mut self) -> Result<Match> {
+        let keyword = self.consume_keyword("match", "expected 'match'")?;
+        let subject = self.expression()?;
+        // The `do` is optional, the arms are always in an indented block
+        self.match_keyword("do");
+        self.consume(
+            TokenKind::Indent,
+            "expected the arms of the match in an indented block",
+        )?;
+
+        let mut arms = Vec::new();
+        while !self.check(TokenKind::Dedent) && !self.is_at_end() {
+            let pattern = self.pattern()?;
+            let guard = if self.match_keyword("if") {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+            let body = self.block()?;
+            arms.push(Arm {
+                pattern,
+                guard,
+                body,
+            });
+            // The arms in a single line can be separated with commas
+            if !self.ends_statement() {
+                self.match_kinds(&[TokenKind::Comma]);
+            }
+            self.end_statement()?;
+        }
+        self.consume(TokenKind::Dedent, "expected the end of the match")?;
+
+        self.check_exhaustive(&keyword, &arms);
+        Ok(Match {
+            keyword,
+            subject,
+            arms,
+        })
+    }
+
+    fn pattern(&mut self) -> Result<Pattern> {
+        if self.check(TokenKind::Ident) && self.peek().lexeme == "_" {
+            return Ok(Pattern::Wildcard(self.advance().clone()));
+        }
+
+        if self.match_kinds(&[TokenKind::Ident]) {
+            let name = self.previous().clone();
+            if !self.match_kinds(&[TokenKind::LBrace]) {
+                return Ok(Pattern::Binding(name));
+            }
+            let fields = self.elements(TokenKind::RBrace, Parser::field_pattern)?;
+            self.consume(TokenKind::RBrace, "expected '}' after the fields")?;
+            return Ok(Pattern::Instance(name, fields));
+        }
+
+        if self.match_kinds(&[TokenKind::LBracket]) {
+            let bracket = self.previous().clone();
+            let elements = self.elements(TokenKind::RBracket, Parser::pattern)?;
+            self.consume(TokenKind::RBracket, "expected ']' after the patterns")?;
+            return Ok(Pattern::Array(bracket, elements));
+        }
+
+        if self.match_kinds(&[TokenKind::LParen]) {
+            let paren = self.previous().clone();
+            let elements = self.elements(TokenKind::RParen, Parser::pattern)?;
+            let comma = self.previous().kind == TokenKind::Comma;
+            self.consume(TokenKind::RParen, "expected ')' after the patterns")?;
+            // `(pattern)` is only a grouping, like in the expressions
+            if elements.len() == 1 && !comma {
+                return Ok(elements.into_iter().next().unwrap());
+            }
+            return Ok(Pattern::Tuple(paren, elements));
+        }
+
+        let low = self.literal_pattern()?;
+        if low.kind == TokenKind::Number && self.match_kinds(&[TokenKind::DoubleDot]) {
+            let dots = self.previous().clone();
+            let high = self.literal_pattern()?;
+            if high.kind != TokenKind::Number {
+                return Err(self.error(&high, "expected a number at the end of the range"));
+            }
+            return Ok(Pattern::Range(low, dots, high));
+        }
+        Ok(Pattern::Literal(low))
+    }
+
+    /// A literal in a pattern, the numbers can have a `-` before them
+    fn literal_pattern(&mut self) -> Result<Token> {
+        if self.match_kinds(&[TokenKind::Minus]) {
+            let minus = self.previous().clone();
+            let number = self.consume(TokenKind::Number, "expected a number after '-'")?;
+            let literal = match number.literal {
+                Some(Literal::Number(n)) => Some(Literal::Number(-n)),
+                literal => literal,
+            };
+            return Ok(Token {
+                lexeme: format!("-{}", number.lexeme),
+                literal,
+                span: Span {
+                    end: number.span.end,
+                    ..minus.span
+                },
+                ..number
+            });
+        }
+
+        if self.match_kinds(&[
+            TokenKind::Number,
+            TokenKind::String,
+            TokenKind::Boolean,
+            TokenKind::Null,
+        ]) {
+            return Ok(self.previous().clone());
+        }
+        Err(self.error(self.peek(), "expected a pattern"))
+    }
+
+    /// `field` or `field: pattern` in the pattern of an instance
+    fn field_pattern(&mut self) -> Result<(Token, Pattern)> {
+        let name = self.consume(TokenKind::Ident, "expected a field name")?;
+        if self.match_kinds(&[TokenKind::Colon]) {
+            let pattern = self.pattern()?;
+            return Ok((name, pattern));
+        }
+        Ok((name.clone(), Pattern::Binding(name)))
+    }
+
+    /// Warn about a `match` on booleans or on the classes that inherit the same one that does not
+    /// have an arm for all of them, or an arm that matches everything
+    fn check_exhaustive(&mut self, keyword: &Token, arms: &[Arm]) {
+        // The arms with a guard can fail even if their pattern matches
+        let covered: Vec<&Pattern> = arms
+            .iter()
+            .filter(|arm| arm.guard.is_none())
+            .map(|arm| &arm.pattern)
+            .collect();
+        if covered.iter().any(|pattern| pattern.is_irrefutable()) {
+            return;
+        }
+
+        let booleans = arms.iter().all(|arm| {
+            matches!(arm.pattern, Pattern::Literal(ref token) if token.kind == TokenKind::Boolean)
+        });
+        let missing: Vec<String> = if booleans {
+            ["true", "false"]
+                .iter()
+                .filter(|value| {
+                    !covered.iter().any(
+                        |pattern| matches!(pattern, Pattern::Literal(token) if token.lexeme == **value),
+                    )
+                })
+                .map(|value| value.to_string())
+                .collect()
+        } else {
+            self.missing_classes(arms, &covered)
+        };
+
+        if !missing.is_empty() {
+            let message = format!("this match does not cover {}", missing.join(", "));
+            self.warnings.push(Warning::new(keyword.span, &message));
+        }
+    }
+
+    /// The classes that inherit the same class as the ones of the arms and that no arm covers,
+    /// when all the arms match instances
+    fn missing_classes(&self, arms: &[Arm], covered: &[&Pattern]) -> Vec<String> {
+        let mut names = Vec::with_capacity(arms.len());
+        for arm in arms {
+            match arm.pattern {
+                Pattern::Instance(ref name, _) => names.push(name.lexeme.as_str()),
+                _ => return Vec::new(),
+            }
+        }
+        let parent = match names.first().and_then(|name| self.classes.get(*name)) {
+            Some(parents) => parents
+                .iter()
+                .find(|parent| names.iter().all(|name| self.inherits(name, parent))),
+            None => None,
+        };
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return Vec::new(),
+        };
+
+        // An instance pattern covers its class when its fields match anything
+        let covers = |class: &str| {
+            covered.iter().any(|pattern| match pattern {
+                Pattern::Instance(name, fields) => {
+                    fields.iter().all(|(_, field)| field.is_irrefutable())
+                        && (name.lexeme == class || name.lexeme == *parent)
+                }
+                _ => false,
+            })
+        };
+        let mut missing: Vec<String> = self
+            .classes
+            .iter()
+            .filter(|(_, parents)| parents.contains(parent))
+            .map(|(name, _)| name.clone())
+            .filter(|name| !covers(name))
+            .collect();
+        missing.sort();
+        missing
+    }
+
+    /// True if the class is declared with `parent` in its `inherit`
+    fn inherits(&self, class: &str, parent: &str) -> bool {
+        self.classes
+            .get(class)
+            .is_some_and(|parents| parents.iter().any(|name| name == parent))
+    }
+
+    /// `do` followed by the body of a block
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.consume_keyword("do", "expected 'do' before the block")?;
+        self.body()
+    }
+
+    /// An indented list of statements, or a single statement in the same line
+    fn body(&mut self) -> Result<Vec<Stmt>> {
+        if self.match_kinds(&[TokenKind::Indent]) {
+            let mut statements = Vec::new();
+            while !self.check(TokenKind::Dedent) && !self.is_at_end() {
+                statements.push(self.statement()?);
+                self.end_statement()?;
+            }
+            self.consume(TokenKind::Dedent, "expected the end of the block")?;
+            return Ok(statements);
+        }
+
+        if self.new_line() || self.is_at_end() {
+            return Err(self.error(self.peek(), "expected an indented block"));
+        }
+
+        Ok(vec![self.statement()?])
+    }
+
+    fn expression(&mut self) -> Result<Expr> {
+        self.or()
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+
+        while self.same_line() && self.match_keyword("or") {
+            let operator = self.previous().clone();
+            let right = self.and()?;
+            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+
+        while self.same_line() && self.match_keyword("and") {
+            let operator = self.previous().clone();
+            let right = self.not()?;
+            expr = Expr::Logical(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.match_kinds(&[TokenKind::Not]) {
+            let operator = self.previous().clone();
+            let right = self.not()?;
+            return Ok(Expr::Unary(operator, Box::new(right)));
+        }
+
+        self.equality()
+    }
+
+    fn equality(&mut self) -> Result<Expr> {
+        self.binary(
+            &[TokenKind::EqEqual, TokenKind::BangEqual],
+            Parser::comparison,
+        )
+    }
+
+    /// The comparisons, `in`, that tells if a value is inside of another one, and `is`, that tells
+    /// if a value has a type
+    fn comparison(&mut self) -> Result<Expr> {
+        let mut expr = self.term()?;
+
+        while self.same_line()
+            && (self.match_kinds(&[
+                TokenKind::Less,
+                TokenKind::LessEqual,
+                TokenKind::Greater,
+                TokenKind::GreaterEqual,
+            ]) || self.match_keyword("in")
+                || self.match_keyword("is"))
+        {
+            let operator = self.previous().clone();
+            let right = self.term()?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr> {
+        self.binary(&[TokenKind::Plus, TokenKind::Minus], Parser::factor)
+    }
+
+    fn factor(&mut self) -> Result<Expr> {
+        self.binary(
+            &[
+                TokenKind::Star,
+                TokenKind::Slash,
+                TokenKind::DoubleSlash,
+                TokenKind::Percent,
+            ],
+            Parser::unary,
+        )
+    }
+
+    /// A left associative binary expression with the `operators` between the `operands`
+    fn binary(
+        &mut self,
+        operators: &[TokenKind],
+        operand: fn(&mut Parser) -> Result<Expr>,
+    ) -> Result<Expr> {
+        let mut expr = operand(self)?;
+
+        while self.same_line() && self.match_kinds(operators) {
+            let operator = self.previous().clone();
+            let right = operand(self)?;
+            expr = Expr::Binary(Box::new(expr), operator, Box::new(right));
+        }
+
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.match_kinds(&[TokenKind::Minus]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
+            return Ok(Expr::Unary(operator, Box::new(right)));
+        }
+
+        self.power()
+    }
+
+    /// `**` is right associative and binds tighter than the unary minus at its left
+    fn power(&mut self) -> Result<Expr> {
+        let expr = self.call()?;
+
+        if self.same_line() && self.match_kinds(&[TokenKind::DoubleStar]) {
+            let operator = self.previous().clone();
+            let right = self.unary()?;
+            return Ok(Expr::Binary(Box::new(expr), operator, Box::new(right)));
+        }
+
+        Ok(expr)
+    }
+
+    fn call(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.same_line() && self.match_kinds(&[TokenKind::LParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.same_line() && self.match_kinds(&[TokenKind::Dot]) {
+                let name =
+                    self.consume(TokenKind::Ident, "expected an attribute name after '.'")?;
+                expr = Expr::Get(Box::new(expr), name);
+            } else if self.same_line() && self.match_kinds(&[TokenKind::LBracket]) {
+                let bracket = self.previous().clone();
+                self.nesting += 1;
+                let index = self.expression()?;
+                self.nesting -= 1;
+                self.consume(TokenKind::RBracket, "expected ']' after the index")?;
+                expr = Expr::Index(Box::new(expr), bracket, Box::new(index));
+            } else {
+                break;
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr> {
+        self.nesting += 1;
+        let mut arguments: Vec<Argument> = Vec::new();
+
+        if !self.check(TokenKind::RParen) {
+            loop {
+                let name =
+                    if self.check(TokenKind::Ident) && self.peek_next().kind == TokenKind::Equal {
+                        let name = self.advance().clone();
+                        self.advance();
+                        Some(name)
+                    } else {
+                        None
+                    };
+                if name.is_none() && arguments.iter().any(|argument| argument.name.is_some()) {
+                    return Err(self.error(
+                        self.peek(),
+                        "a positional argument can not follow a keyword argument",
+                    ));
+                }
+                let value = self.expression()?;
+                arguments.push(Argument { name, value });
+                if !self.match_kinds(&[TokenKind::Comma]) || self.check(TokenKind::RParen) {
+                    break;
+                }
+            }
+        }
+
+        self.nesting -= 1;
+        let paren = self.consume(TokenKind::RParen, "expected ')' after the arguments")?;
+        Ok(Expr::Call(Box::new(callee), paren, arguments))
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        if self.match_kinds(&[
+            TokenKind::Number,
+            TokenKind::String,
+            TokenKind::Boolean,
+            TokenKind::Null,
+        ]) {
+            return Ok(Expr::Literal(self.previous().clone()));
+        }
+
+        if self.match_kinds(&[TokenKind::Ident]) {
+            return Ok(Expr::Variable(self.previous().clone(), Cell::default()));
+        }
+
+        if self.match_kinds(&[TokenKind::LParen]) {
+            return self.grouping_or_tuple();
+        }
+
+        if self.match_kinds(&[TokenKind::LBracket]) {
+            let bracket = self.previous().clone();
+            let elements = self.elements(TokenKind::RBracket, Parser::expression)?;
+            self.consume(TokenKind::RBracket, "expected ']' after the elements")?;
+            return Ok(Expr::Array(bracket, elements));
+        }
+
+        if self.match_kinds(&[TokenKind::LBrace]) {
+            let brace = self.previous().clone();
+            let entries = self.elements(TokenKind::RBrace, Parser::entry)?;
+            self.consume(TokenKind::RBrace, "expected '}' after the entries")?;
+            return Ok(Expr::Map(brace, entries));
+        }
+
+        if self.match_keyword("super") {
+            let keyword = self.previous().clone();
+            self.consume(TokenKind::Dot, "expected '.' after 'super'")?;
+            let method = self.consume(TokenKind::Ident, "expected a method name after 'super.'")?;
+            return Ok(Expr::Super(keyword, method));
+        }
+
+        if self.match_keyword("func") {
+            let keyword = self.previous().clone();
+            return Ok(Expr::Function(Rc::new(self.function(keyword)?)));
+        }
+
+        if self.check_keyword("if") {
+            return Ok(Expr::If(Box::new(self.if_body()?)));
+        }
+
+        if self.check_keyword("match") {
+            return Ok(Expr::Match(Box::new(self.match_body()?)));
+        }
+
+        Err(self.error(self.peek(), "expected an expression"))
+    }
+
+    /// `(value)` is a grouping, `()`, `(value,)` and `(value, value...)` are tuples
+    fn grouping_or_tuple(&mut self) -> Result<Expr> {
+        let paren = self.previous().clone();
+        self.nesting += 1;
+
+        if self.match_kinds(&[TokenKind::RParen]) {
+            self.nesting -= 1;
+            return Ok(Expr::Tuple(paren, Vec::new()));
+        }
+
+        let first = self.expression()?;
+        if !self.check(TokenKind::Comma) {
+            self.nesting -= 1;
+            self.consume(TokenKind::RParen, "expected ')' after the expression")?;
+            return Ok(Expr::Grouping(Box::new(first)));
+        }
+
+        self.advance();
+        self.nesting -= 1;
+        let mut elements = vec![first];
+        elements.extend(self.elements(TokenKind::RParen, Parser::expression)?);
+        self.consume(TokenKind::RParen, "expected ')' after the elements")?;
+        Ok(Expr::Tuple(paren, elements))
+    }
+
+    /// The elements of a literal separated by commas, until the `closing` token. The last one
+    /// can have a comma after it.
+    fn elements<T>(
+        &mut self,
+        closing: TokenKind,
+        element: fn(&mut Parser) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        self.nesting += 1;
+        let mut elements = Vec::new();
+
+        while !self.check(closing) && !self.is_at_end() {
+            elements.push(element(self)?);
+            if !self.match_kinds(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+
+        self.nesting -= 1;
+        Ok(elements)
+    }
+
+    /// `key: value` inside of an associative array, a name as the key is the same as a string
+    fn entry(&mut self) -> Result<(Expr, Expr)> {
+        let key = if self.check(TokenKind::Ident) && self.peek_next().kind == TokenKind::Colon {
+            let name = self.advance().clone();
+            Expr::Literal(Token {
+                kind: TokenKind::String,
+                literal: Some(Literal::String(name.lexeme.clone())),
+                ..name
+            })
+        } else {
+            self.expression()?
+        };
+        self.consume(TokenKind::Colon, "expected ':' after the key")?;
+        let value = self.expression()?;
+        Ok((key, value))
+    }
+
+    /// Check that nothing else is in the line of the statement that was just parsed
+    fn end_statement(&mut self) -> Result<()> {
+        if self.ends_statement() {
+            return Ok(());
+        }
+        Err(self.error(self.peek(), "expected the end of the statement"))
+    }
+
+    fn ends_statement(&self) -> bool {
+        self.new_line()
+            || self.check(TokenKind::Dedent)
+            || self.is_at_end()
+            || (self.current > 0 && self.previous().kind == TokenKind::Dedent)
+    }
+
+    /// True if the next token is in a line after the one of the previous token
+    fn new_line(&self) -> bool {
+        self.current > 0 && self.peek().span.line > end_line(self.previous())
+    }
+
+    /// True if the next token can continue the current expression
+    fn same_line(&self) -> bool {
+        self.nesting > 0 || !self.new_line()
+    }
+
+    fn match_kinds(&mut self, kinds: &[TokenKind]) -> bool {
+        if self.peek().in_types(kinds) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn match_keyword(&mut self, keyword: &str) -> bool {
+        if self.check_keyword(keyword) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    fn consume(&mut self, kind: TokenKind, msg: &str) -> Result<Token> {
+        if self.check(kind) {
+            return Ok(self.advance().clone());
+        }
+        Err(self.error(self.peek(), msg))
+    }
+
+    fn consume_keyword(&mut self, keyword: &str, msg: &str) -> Result<Token> {
+        if self.check_keyword(keyword) {
+            return Ok(self.advance().clone());
+        }
+        Err(self.error(self.peek(), msg))
+    }
+
+    fn check(&self, kind: TokenKind) -> bool {
+        self.peek().kind == kind
+    }
+
+    fn check_keyword(&self, keyword: &str) -> bool {
+        self.check(TokenKind::Keyword) && self.peek().lexeme == keyword
+    }
+
+    fn advance(&mut self) -> &Token {
+        if !self.is_at_end() {
+            self.current += 1;
+        }
+        self.previous()
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.check(TokenKind::Eof)
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.current]
+    }
+
+    /// The token after the next one, the last one is `Eof`
+    fn peek_next(&self) -> &Token {
+        &self.tokens[(self.current + 1).min(self.tokens.len() - 1)]
+    }
+
+    fn previous(&self) -> &Token {
+        &self.tokens[self.current.saturating_sub(1)]
+    }
+
+    fn error(&self, token: &Token, msg: &str) -> Error {
+        let near = match token.kind {
+            TokenKind::Eof => "end of file".to_string(),
+            TokenKind::Indent => "indentation".to_string(),
+            TokenKind::Dedent => "end of block".to_string(),
+            _ => token.lexeme.clone(),
+        };
+        Error::Parse(token.span, msg.to_string(), near)
+    }
+}
+
+/// The line where the token ends, strings can take more than one line
+fn end_line(token: &Token) -> u64 {
+    token.span.line + token.lexeme.matches('\n').count() as u64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ezcript_lexer::lexer::Lexer;
+    use ezcript_lexer::number::Number;
+
+    fn parse(source: &str) -> Result<Vec<Stmt>> {
+        let tokens = Lexer::new(source.chars()).tokenize()?;
+        Parser::new(tokens).parse()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let program = parse("set x = 1 + 2 * 3").unwrap();
+
+        match &program[0] {
+            Stmt::Set(name, None, Some(Expr::Binary(left, op, right))) => {
+                assert_eq!(name.lexeme, "x");
+                assert_eq!(op.lexeme, "+");
+                assert!(matches!(**left, Expr::Literal(_)));
+                assert!(matches!(**right, Expr::Binary(_, ref op, _) if op.lexeme == "*"));
+            }
+            stmt => panic!("unexpected statement {:?}", stmt),
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let program = parse(
+            "set counter = 0\n\
+             while counter < 5 do\n    print(counter)\n    counter++\n\
+             do\n    counter--\nwhile counter > 0\n\
+             if counter == 0 do print(1) else do print(2)",
+        )
+        .unwrap();
+
+        assert_eq!(program.len(), 4);
+        assert!(matches!(&program[1], Stmt::While(_, body) if body.len() == 2));
+        assert!(matches!(&program[2], Stmt::DoWhile(body, _) if body.len() == 1));
+        assert!(matches!(
+            &program[3],
+            Stmt::If(If {
+                otherwise: Some(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_statements_end_at_new_lines() {
+        assert_eq!(parse("set x = 1\n-1").unwrap().len(), 2);
+        assert_eq!(parse("print(1,\n  2)").unwrap().len(), 1);
+        assert!(parse("set x = 1 set y = 2").is_err());
+        assert!(parse("while true do\nprint(1)").is_err());
+    }
+
+    #[test]
+    fn test_collections() {
+        let program = parse(
+            "set data = [1, (2,), (3), (), { name: \"Ana\", 5: [],\n  \"key\": (1, 2), }]\n\
+             data[4][\"name\"] = 1 in data",
+        )
+        .unwrap();
+
+        match &program[0] {
+            Stmt::Set(_, _, Some(Expr::Array(_, elements))) => {
+                assert!(matches!(&elements[1], Expr::Tuple(_, items) if items.len() == 1));
+                assert!(matches!(&elements[2], Expr::Grouping(_)));
+                assert!(matches!(&elements[3], Expr::Tuple(_, items) if items.is_empty()));
+                assert!(matches!(&elements[4], Expr::Map(_, entries) if entries.len() == 3));
+            }
+            stmt => panic!("unexpected statement {:?}", stmt),
+        }
+        assert!(matches!(
+            &program[1],
+            Stmt::Assign(Expr::Index(_, _, _), _, Expr::Binary(_, op, _)) if op.lexeme == "in"
+        ));
+    }
+
+    #[test]
+    fn test_functions() {
+        let program = parse(
+            "func f(a, b: number = 2) do return a\n\
+             set g = func(x) do return x\n\
+             f(1, b = g(3))",
+        )
+        .unwrap();
+
+        assert!(matches!(&program[0], Stmt::Func(f) if f.params[1].default.is_some()));
+        assert!(
+            matches!(&program[1], Stmt::Set(_, _, Some(Expr::Function(f))) if f.name().is_none())
+        );
+        assert!(matches!(
+            &program[2],
+            Stmt::Expression(Expr::Call(_, _, args)) if args[1].name.is_some()
+        ));
+        assert!(parse("func f(a = 1, b) do return a").is_err());
+        assert!(parse("f(a = 1, 2)").is_err());
+    }
+
+    #[test]
+    fn test_classes() {
+        let program = parse(
+            "class B inherit A do\n    func init(self) do\n        super.init()\n\
+             \n    func f(self) do return 1\n",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            &program[0],
+            Stmt::Class(class) if class.inherits.len() == 1 && class.methods.len() == 2
+        ));
+        assert!(parse("class A do set x = 1").is_err());
+
+        let program =
+            parse("interface I do\n    a: String\n    b: { type: Integer, required: false }\n")
+                .unwrap();
+        assert!(matches!(
+            &program[0],
+            Stmt::Interface(interface) if interface.fields[0].required && !interface.fields[1].required
+        ));
+        assert!(parse("interface I do a: { required: false }").is_err());
+    }
+
+    #[test]
+    fn test_match() {
+        let program = parse(
+            "match x\n    1 do return 1,\n    2..-1 do y\n    [a, _] if a do y\n    \
+             (b,) do y\n    P { q, r: 1 } do y\n",
+        )
+        .unwrap();
+
+        match &program[0] {
+            Stmt::Match(match_body) => {
+                assert!(
+                    matches!(match_body.arms[1].pattern, Pattern::Range(_, _, ref high)
+                    if high.literal == Some(Literal::Number(Number::Integer(-1))))
+                );
+                assert!(match_body.arms[2].guard.is_some());
+                assert!(
+                    matches!(match_body.arms[3].pattern, Pattern::Tuple(_, ref elements)
+                    if elements.len() == 1)
+                );
+                assert!(
+                    matches!(match_body.arms[4].pattern, Pattern::Instance(_, ref fields)
+                    if fields.len() == 2)
+                );
+            }
+            statement => panic!("expected a match, got {:?}", statement),
+        }
+        assert!(parse("match x do 1 do y").is_err());
+    }
+
+    #[test]
+    fn test_exhaustive_warnings() {
+        let warnings = |source: &str| {
+            let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+            let mut parser = Parser::new(tokens);
+            parser.parse().unwrap();
+            parser
+                .warnings()
+                .iter()
+                .map(|warning| warning.message.clone())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            warnings("match x\n    true do y\n"),
+            vec!["this match does not cover false"]
+        );
+        assert!(warnings("match x\n    true do y\n    false do z\n").is_empty());
+        let classes =
+            "class A do func f(self) do return\nclass B inherit A do func f(self) do return\n\
+                       class C inherit A do func f(self) do return\n";
+        assert_eq!(
+            warnings(&format!("{}match x\n    B {{}} do y\n", classes)),
+            vec!["this match does not cover C"]
+        );
+        assert!(warnings(&format!(
+            "{}match x\n    B {{}} do y\n    _ do z\n",
+            classes
+        ))
+        .is_empty());
+    }
+}
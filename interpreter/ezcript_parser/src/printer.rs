@@ -0,0 +1,396 @@
+use serde_json::json;
+
+use super::ast::{Expr, Function, If, Match, Pattern, Stmt, TypeExpr};
+use ezcript_lexer::tokens::{Literal, Token};
+
+/// A node of the syntax tree reduced to what the printers need: what it is, the value that
+/// identifies it (like the name of a variable or an operator) and its children
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub kind: &'static str,
+    pub value: Option<String>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    fn new(kind: &'static str, value: Option<String>, children: Vec<Node>) -> Self {
+        Node {
+            kind,
+            value,
+            children,
+        }
+    }
+
+    fn leaf(kind: &'static str, value: String) -> Self {
+        Node::new(kind, Some(value), Vec::new())
+    }
+
+    fn block(kind: &'static str, statements: &[Stmt]) -> Self {
+        Node::new(kind, None, statements.iter().map(Node::from).collect())
+    }
+
+    /// The first line of the node in the tree, like `Binary +`
+    fn label(&self) -> String {
+        match self.value {
+            Some(ref value) => format!("{} {}", self.kind, value),
+            None => self.kind.to_string(),
+        }
+    }
+}
+
+impl From<&Stmt> for Node {
+    fn from(stmt: &Stmt) -> Self {
+        match stmt {
+            Stmt::Expression(expr) => Node::new("Expression", None, vec![expr.into()]),
+            Stmt::Set(name, annotation, value) => {
+                let mut children: Vec<Node> = annotation.iter().map(Node::from).collect();
+                children.extend(value.iter().map(Node::from));
+                Node::new("Set", Some(name.lexeme.clone()), children)
+            }
+            Stmt::Const(name, annotation, value) => {
+                let mut children: Vec<Node> = annotation.iter().map(Node::from).collect();
+                children.push(value.into());
+                Node::new("Const", Some(name.lexeme.clone()), children)
+            }
+            Stmt::Assign(target, operator, value) => Node::new(
+                "Assign",
+                Some(operator.lexeme.clone()),
+                vec![target.into(), value.into()],
+            ),
+            Stmt::Increment(target, operator) => Node::new(
+                "Increment",
+                Some(operator.lexeme.clone()),
+                vec![target.into()],
+            ),
+            Stmt::If(if_body) => if_body.into(),
+            Stmt::Match(match_body) => match_body.into(),
+            Stmt::While(condition, body) => Node::new(
+                "While",
+                None,
+                vec![condition.into(), Node::block("Body", body)],
+            ),
+            Stmt::DoWhile(body, condition) => Node::new(
+                "DoWhile",
+                None,
+                vec![Node::block("Body", body), condition.into()],
+            ),
+            Stmt::ForEach(name, iterable, body) => Node::new(
+                "ForEach",
+                Some(name.lexeme.clone()),
+                vec![iterable.into(), Node::block("Body", body)],
+            ),
+            Stmt::Func(function) => function.as_ref().into(),
+            Stmt::Class(class) => {
+                let mut children: Vec<Node> = class
+                    .inherits
+                    .iter()
+                    .map(|name| Node::leaf("Inherit", name.lexeme.clone()))
+                    .collect();
+                children.extend(class.methods.iter().map(|method| method.as_ref().into()));
+                Node::new("Class", Some(class.name.lexeme.clone()), children)
+            }
+            Stmt::Interface(interface) => {
+                let fields = interface
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let mut children = vec![Node::from(&field.type_expr)];
+                        if !field.required {
+                            children.push(Node::new("Optional", None, Vec::new()));
+                        }
+                        Node::new("Field", Some(field.name.lexeme.clone()), children)
+                    })
+                    .collect();
+                Node::new("Interface", Some(interface.name.lexeme.clone()), fields)
+            }
+            Stmt::Return(_, value) => {
+                Node::new("Return", None, value.iter().map(Node::from).collect())
+            }
+            Stmt::Break(_) => Node::new("Break", None, Vec::new()),
+        }
+    }
+}
+
+impl From<&Expr> for Node {
+    fn from(expr: &Expr) -> Self {
+        match expr {
+            Expr::Literal(token) => Node::leaf("Literal", literal(token)),
+            Expr::Variable(name, _) => Node::leaf("Variable", name.lexeme.clone()),
+            Expr::Grouping(expr) => Node::new("Grouping", None, vec![expr.as_ref().into()]),
+            Expr::Unary(operator, right) => Node::new(
+                "Unary",
+                Some(operator.lexeme.clone()),
+                vec![right.as_ref().into()],
+            ),
+            Expr::Binary(left, operator, right) => Node::new(
+                "Binary",
+                Some(operator.lexeme.clone()),
+                vec![left.as_ref().into(), right.as_ref().into()],
+            ),
+            Expr::Logical(left, operator, right) => Node::new(
+                "Logical",
+                Some(operator.lexeme.clone()),
+                vec![left.as_ref().into(), right.as_ref().into()],
+            ),
+            Expr::Call(callee, _, arguments) => {
+                let mut children = vec![callee.as_ref().into()];
+                children.extend(arguments.iter().map(|argument| match argument.name {
+                    Some(ref name) => Node::new(
+                        "Keyword",
+                        Some(name.lexeme.clone()),
+                        vec![(&argument.value).into()],
+                    ),
+                    None => (&argument.value).into(),
+                }));
+                Node::new("Call", None, children)
+            }
+            Expr::Get(object, name) => Node::new(
+                "Get",
+                Some(name.lexeme.clone()),
+                vec![object.as_ref().into()],
+            ),
+            Expr::Index(object, _, index) => Node::new(
+                "Index",
+                None,
+                vec![object.as_ref().into(), index.as_ref().into()],
+            ),
+            Expr::Array(_, elements) => {
+                Node::new("Array", None, elements.iter().map(Node::from).collect())
+            }
+            Expr::Map(_, entries) => Node::new(
+                "Map",
+                None,
+                entries
+                    .iter()
+                    .map(|(key, value)| Node::new("Entry", None, vec![key.into(), value.into()]))
+                    .collect(),
+            ),
+            Expr::Tuple(_, elements) => {
+                Node::new("Tuple", None, elements.iter().map(Node::from).collect())
+            }
+            Expr::Super(_, method) => Node::leaf("Super", method.lexeme.clone()),
+            Expr::Function(function) => function.as_ref().into(),
+            Expr::If(if_body) => if_body.as_ref().into(),
+            Expr::Match(match_body) => match_body.as_ref().into(),
+        }
+    }
+}
+
+impl From<&If> for Node {
+    fn from(if_body: &If) -> Self {
+        let mut children: Vec<Node> = if_body
+            .branches
+            .iter()
+            .map(|(condition, body)| {
+                Node::new(
+                    "Branch",
+                    None,
+                    vec![condition.into(), Node::block("Body", body)],
+                )
+            })
+            .collect();
+        children.extend(
+            if_body
+                .otherwise
+                .iter()
+                .map(|body| Node::block("Else", body)),
+        );
+        Node::new("If", None, children)
+    }
+}
+
+impl From<&Match> for Node {
+    fn from(match_body: &Match) -> Self {
+        let mut children = vec![Node::from(&match_body.subject)];
+        children.extend(match_body.arms.iter().map(|arm| {
+            let mut children = vec![Node::from(&arm.pattern)];
+            children.extend(
+                arm.guard
+                    .iter()
+                    .map(|guard| Node::new("Guard", None, vec![guard.into()])),
+            );
+            children.push(Node::block("Body", &arm.body));
+            Node::new("Arm", None, children)
+        }));
+        Node::new("Match", None, children)
+    }
+}
+
+impl From<&Pattern> for Node {
+    fn from(pattern: &Pattern) -> Self {
+        match pattern {
+            Pattern::Wildcard(_) => Node::new("Wildcard", None, Vec::new()),
+            Pattern::Literal(token) => Node::leaf("Literal", literal(token)),
+            Pattern::Range(low, _, high) => Node::new(
+                "Range",
+                None,
+                vec![
+                    Node::leaf("Literal", literal(low)),
+                    Node::leaf("Literal", literal(high)),
+                ],
+            ),
+            Pattern::Binding(name) => Node::leaf("Binding", name.lexeme.clone()),
+            Pattern::Array(_, elements) => {
+                Node::new("Array", None, elements.iter().map(Node::from).collect())
+            }
+            Pattern::Tuple(_, elements) => {
+                Node::new("Tuple", None, elements.iter().map(Node::from).collect())
+            }
+            Pattern::Instance(class, fields) => Node::new(
+                "Instance",
+                Some(class.lexeme.clone()),
+                fields
+                    .iter()
+                    .map(|(name, pattern)| {
+                        Node::new("Field", Some(name.lexeme.clone()), vec![pattern.into()])
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl From<&Function> for Node {
+    fn from(function: &Function) -> Self {
+        let mut children: Vec<Node> = function
+            .params
+            .iter()
+            .map(|param| {
+                let mut children: Vec<Node> = param.annotation.iter().map(Node::from).collect();
+                children.extend(
+                    param
+                        .default
+                        .iter()
+                        .map(|default| Node::new("Default", None, vec![default.into()])),
+                );
+                Node::new("Param", Some(param.name.lexeme.clone()), children)
+            })
+            .collect();
+        if let Some(ref return_type) = function.return_type {
+            children.push(Node::new("Returns", None, vec![return_type.into()]));
+        }
+        children.push(Node::block("Body", &function.body));
+        Node::new("Func", function.name().map(String::from), children)
+    }
+}
+
+impl From<&TypeExpr> for Node {
+    fn from(type_expr: &TypeExpr) -> Self {
+        match type_expr {
+            TypeExpr::Name(name) => Node::leaf("Type", name.lexeme.clone()),
+        }
+    }
+}
+
+fn literal(token: &Token) -> String {
+    match token.literal {
+        Some(Literal::String(ref s)) => format!("{:?}", s),
+        Some(ref literal) => literal.to_string(),
+        None => token.lexeme.clone(),
+    }
+}
+
+fn program(statements: &[Stmt]) -> Node {
+    Node::block("Program", statements)
+}
+
+/// The syntax tree with a node per line, the children are indented under their parent
+pub fn tree(statements: &[Stmt]) -> String {
+    fn write(node: &Node, depth: usize, out: &mut String) {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(&node.label());
+        out.push('\n');
+        for child in &node.children {
+            write(child, depth + 1, out);
+        }
+    }
+
+    let mut out = String::new();
+    write(&program(statements), 0, &mut out);
+    out
+}
+
+/// The syntax tree as S-expressions, one per statement, like `(set x (+ 1 (* 2 3)))`
+pub fn sexp(statements: &[Stmt]) -> String {
+    fn write(node: &Node) -> String {
+        match (node.kind, &node.value) {
+            ("Literal", Some(value)) | ("Variable", Some(value)) | ("Type", Some(value)) => {
+                return value.clone()
+            }
+            _ => (),
+        }
+
+        let mut parts = vec![match (node.kind, &node.value) {
+            ("Binary", Some(op)) | ("Logical", Some(op)) | ("Unary", Some(op)) => op.clone(),
+            (kind, Some(value)) => format!("{} {}", kind.to_lowercase(), value),
+            (kind, None) => kind.to_lowercase(),
+        }];
+        parts.extend(node.children.iter().map(write));
+        format!("({})", parts.join(" "))
+    }
+
+    statements
+        .iter()
+        .map(|stmt| write(&stmt.into()) + "\n")
+        .collect()
+}
+
+/// The syntax tree as a JSON object, every node has a `kind`, a `value` and its `children`
+pub fn json(statements: &[Stmt]) -> String {
+    fn write(node: &Node) -> serde_json::Value {
+        json!({
+            "kind": node.kind,
+            "value": node.value,
+            "children": node.children.iter().map(write).collect::<Vec<_>>(),
+        })
+    }
+
+    write(&program(statements)).to_string() + "\n"
+}
+
+/// The syntax tree as a Graphviz DOT graph
+pub fn dot(statements: &[Stmt]) -> String {
+    fn write(node: &Node, next_id: &mut usize, out: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        out.push_str(&format!("    n{} [label={:?}];\n", id, node.label()));
+        for child in &node.children {
+            let child_id = write(child, next_id, out);
+            out.push_str(&format!("    n{} -> n{};\n", id, child_id));
+        }
+        id
+    }
+
+    let mut out = String::from("digraph ast {\n    node [shape=box];\n");
+    write(&program(statements), &mut 0, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::Parser;
+    use ezcript_lexer::lexer::Lexer;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Lexer::new(source.chars()).tokenize().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn test_printers() {
+        let program = parse("set x = 1 + 2 * 3");
+
+        assert_eq!(sexp(&program), "(set x (+ 1 (* 2 3)))\n");
+        assert_eq!(
+            tree(&program),
+            "Program\n  Set x\n    Binary +\n      Literal 1\n      Binary *\n        Literal 2\n        Literal 3\n"
+        );
+        assert!(dot(&program).contains("n1 -> n2;"));
+
+        let value: serde_json::Value = serde_json::from_str(&json(&program)).unwrap();
+        assert_eq!(value["children"][0]["kind"], "Set");
+        assert_eq!(value["children"][0]["value"], "x");
+    }
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+
+use serde_json::json;
+
+use super::{Error, Span, Warning};
+
+/// How bad is the problem described by a diagnostic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A problem found in a script, with everything a tool needs to show it to the user
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub code: &'static str,
+    pub severity: Severity,
+    pub message: String,
+    pub file: String,
+    pub span: Option<Span>,
+    pub suggestions: Vec<String>,
+}
+
+impl Diagnostic {
+    /// Describe an error that happened while running the script saved in `file`
+    pub fn from_error(err: &Error, file: &str) -> Self {
+        let (code, message) = match *err {
+            Error::Usage => ("E0001", err.to_string()),
+            Error::IO(ref e) => ("E0002", e.to_string()),
+            Error::Lexical(_, ref msg, ref whence) => ("E0100", format!("{}: {:?}", msg, whence)),
+            Error::Parse(_, ref msg, ref near) => ("E0200", format!("{}: near {}", msg, near)),
+            Error::Type(_, ref msg, ref near) => ("E0400", format!("{}: near {}", msg, near)),
+            Error::Runtime(_, ref msg, ref near) => ("E0300", format!("{}: near {}", msg, near)),
+            Error::Undefined(_, ref name, _) => {
+                ("E0302", format!("{}: near {}", err.message(), name))
+            }
+            Error::Break(_) => ("E0301", "unexpected break statement".to_string()),
+        };
+
+        let diagnostic = Diagnostic {
+            code,
+            severity: Severity::Error,
+            message,
+            file: file.to_string(),
+            span: err.span(),
+            suggestions: Vec::new(),
+        };
+        match *err {
+            Error::Undefined(_, _, Some(ref similar)) => diagnostic.with_suggestion(similar),
+            _ => diagnostic,
+        }
+    }
+
+    /// Describe a warning about the script saved in `file`
+    pub fn from_warning(warning: &Warning, file: &str) -> Self {
+        Diagnostic {
+            code: "W0001",
+            severity: Severity::Warning,
+            message: warning.message.clone(),
+            file: file.to_string(),
+            span: Some(warning.span),
+            suggestions: Vec::new(),
+        }
+    }
+
+    /// Add a possible fix for the problem
+    pub fn with_suggestion(mut self, suggestion: &str) -> Self {
+        self.suggestions.push(suggestion.to_string());
+        self
+    }
+
+    /// The diagnostic as a single line JSON object
+    pub fn to_json(&self) -> String {
+        let span = self.span.map(|s| json!({ "start": s.start, "end": s.end }));
+
+        json!({
+            "code": self.code,
+            "severity": self.severity.to_string(),
+            "message": self.message,
+            "file": self.file,
+            "line": self.span.map(|s| s.line),
+            "column": self.span.map(|s| s.column),
+            "span": span,
+            "suggestions": self.suggestions,
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lexical_to_json() {
+        let err = Error::Lexical(
+            Span::new(2, 5, 12, 13),
+            "unexpected character".to_string(),
+            "?".to_string(),
+        );
+        let diagnostic = Diagnostic::from_error(&err, "main.ez");
+        let value: serde_json::Value = serde_json::from_str(&diagnostic.to_json()).unwrap();
+
+        assert_eq!(value["code"], "E0100");
+        assert_eq!(value["severity"], "error");
+        assert_eq!(value["message"], "unexpected character: \"?\"");
+        assert_eq!(value["file"], "main.ez");
+        assert_eq!(value["line"], 2);
+        assert_eq!(value["column"], 5);
+        assert_eq!(value["span"], json!({ "start": 12, "end": 13 }));
+        assert_eq!(value["suggestions"], json!([]));
+    }
+
+    #[test]
+    fn test_suggestions_to_json() {
+        let err = Error::Undefined(
+            Span::new(1, 7, 6, 12),
+            "countr".to_string(),
+            Some("counter".to_string()),
+        );
+        let diagnostic = Diagnostic::from_error(&err, "main.ez");
+        let value: serde_json::Value = serde_json::from_str(&diagnostic.to_json()).unwrap();
+
+        assert_eq!(value["code"], "E0302");
+        assert_eq!(
+            value["message"],
+            "undefined variable 'countr', did you mean 'counter'?: near countr"
+        );
+        assert_eq!(value["suggestions"], json!(["counter"]));
+    }
+
+    #[test]
+    fn test_usage_has_no_span() {
+        let diagnostic = Diagnostic::from_error(&Error::Usage, "<cli>");
+        let value: serde_json::Value = serde_json::from_str(&diagnostic.to_json()).unwrap();
+
+        assert!(value["line"].is_null());
+        assert!(value["span"].is_null());
+    }
+}
@@ -3,9 +3,97 @@ use std::fmt;
 use std::io;
 use std::result;
 
+mod diagnostic;
+
+pub use diagnostic::{Diagnostic, Severity};
+
 /// A Ezcript-Specific Result Type
 pub type Result<T> = result::Result<T, Error>;
 
+/// The place of the source code where a token or an error was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Span {
+    /// Line of the first character, starting at 1
+    pub line: u64,
+    /// Column of the first character, starting at 1
+    pub column: u64,
+    /// Offset (in characters) of the first character
+    pub start: usize,
+    /// Offset (in characters) right after the last character
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(line: u64, column: u64, start: usize, end: usize) -> Self {
+        Span {
+            line,
+            column,
+            start,
+            end,
+        }
+    }
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span::new(1, 1, 0, 0)
+    }
+}
+
+/// A problem that does not stop the script, but that is probably a mistake
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Warning {
+    pub fn new(span: Span, message: &str) -> Self {
+        Warning {
+            span,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Warning [line {}] {}", self.span.line, self.message)
+    }
+}
+
+/// The candidate that is closest to the name, if it is close enough to be a typo of it, for the
+/// "did you mean" of the errors
+pub fn suggest<'a, I>(name: &str, candidates: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The number of characters that have to be added, removed or changed to turn one text into the
+/// other
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let changed = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(changed.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 /// A Lox-Specific Error
 #[derive(Debug)]
 pub enum Error {
@@ -14,13 +102,60 @@ pub enum Error {
     /// Returned if there is an error reading from a file or stdin
     IO(io::Error),
     /// Returned if the scanner encounters an error
-    Lexical(u64, String, String),
+    Lexical(Span, String, String),
     /// Returned if the parser encounters an error
-    Parse(u64, String, String),
+    Parse(Span, String, String),
+    /// Returned if the type checker finds a value that does not match its annotation
+    Type(Span, String, String),
     /// Returned if there is an error at runtime
-    Runtime(u64, String, String),
+    Runtime(Span, String, String),
+    /// Returned if a variable that does not exist is used, with the name of the variable and the
+    /// one of a similar variable that exists, if there is one
+    Undefined(Span, String, Option<String>),
     /// Sentinel error for break statements
-    Break(u64),
+    Break(Span),
+}
+
+impl Error {
+    /// The place of the source code where the error happened, if there is one
+    pub fn span(&self) -> Option<Span> {
+        match *self {
+            Error::Usage | Error::IO(_) => None,
+            Error::Lexical(span, _, _)
+            | Error::Parse(span, _, _)
+            | Error::Type(span, _, _)
+            | Error::Runtime(span, _, _)
+            | Error::Undefined(span, _, _)
+            | Error::Break(span) => Some(span),
+        }
+    }
+
+    /// What went wrong, without the place where it happened
+    pub fn message(&self) -> String {
+        match *self {
+            Error::Lexical(_, ref msg, _)
+            | Error::Parse(_, ref msg, _)
+            | Error::Type(_, ref msg, _)
+            | Error::Runtime(_, ref msg, _) => msg.clone(),
+            Error::Undefined(_, ref name, Some(ref similar)) => {
+                format!("undefined variable '{}', did you mean '{}'?", name, similar)
+            }
+            Error::Undefined(_, ref name, None) => format!("undefined variable '{}'", name),
+            Error::Usage | Error::IO(_) | Error::Break(_) => self.to_string(),
+        }
+    }
+
+    /// The exit code of the process when the error stops it, every kind of error has its own
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            Error::Usage => 2,
+            Error::Lexical(_, _, _) => 3,
+            Error::Parse(_, _, _) => 4,
+            Error::Runtime(_, _, _) | Error::Undefined(_, _, _) | Error::Break(_) => 5,
+            Error::IO(_) => 6,
+            Error::Type(_, _, _) => 7,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
@@ -32,42 +167,67 @@ impl From<io::Error> for Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Error::Usage => write!(f, "Usage: ezcript [script]"),
+            Error::Usage => write!(f, "Usage: ezcript [SUBCOMMAND] [script]"),
             Error::IO(ref e) => e.fmt(f),
-            Error::Lexical(ref line, ref msg, ref whence) => {
-                write!(f, "Lexical Error [line {}] {}: {:?}", line, msg, whence)
+            Error::Lexical(ref span, ref msg, ref whence) => {
+                write!(
+                    f,
+                    "Lexical Error [line {}] {}: {:?}",
+                    span.line, msg, whence
+                )
             }
-            Error::Parse(ref line, ref msg, ref near) => {
-                write!(f, "Parse Error [line {}] {}: near {}", line, msg, &near)
+            Error::Parse(ref span, ref msg, ref near) => {
+                write!(
+                    f,
+                    "Parse Error [line {}] {}: near {}",
+                    span.line, msg, &near
+                )
             }
-            Error::Runtime(ref line, ref msg, ref near) => {
-                write!(f, "Runtime Error [line {}] {}: near {}", line, msg, &near)
+            Error::Type(ref span, ref msg, ref near) => {
+                write!(f, "Type Error [line {}] {}: near {}", span.line, msg, &near)
             }
-            Error::Break(ref line) => write!(
+            Error::Runtime(ref span, ref msg, ref near) => {
+                write!(
+                    f,
+                    "Runtime Error [line {}] {}: near {}",
+                    span.line, msg, &near
+                )
+            }
+            Error::Undefined(ref span, ref name, _) => write!(
+                f,
+                "Runtime Error [line {}] {}: near {}",
+                span.line,
+                self.message(),
+                name
+            ),
+            Error::Break(ref span) => write!(
                 f,
                 "Runtime Error [line {}] unexpected break statement",
-                line
+                span.line
             ),
         }
     }
 }
 
 impl error::Error for Error {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
         match *self {
-            Error::Usage => "usage error",
-            Error::IO(ref e) => e.description(),
-            Error::Lexical(_, _, _) => "lexical error",
-            Error::Parse(_, _, _) => "parse error",
-            Error::Runtime(_, _, _) => "runtime error",
-            Error::Break(_) => "break error",
+            Error::IO(ref e) => Some(e),
+            _ => None,
         }
     }
+}
 
-    fn cause(&self) -> Option<&dyn error::Error> {
-        match *self {
-            Error::IO(ref e) => e.source(),
-            _ => None,
-        }
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_suggest() {
+        let names = ["counter", "count", "total"];
+        assert_eq!(suggest("conter", names.iter().copied()), Some("counter"));
+        assert_eq!(suggest("cont", names.iter().copied()), Some("count"));
+        assert_eq!(suggest("x", names.iter().copied()), None);
+        assert_eq!(distance("kitten", "sitting"), 3);
     }
 }